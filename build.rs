@@ -0,0 +1,7 @@
+// Exposes the target triple for --version.
+fn main() {
+    println!(
+        "cargo:rustc-env=TREE_BUILD_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+}
@@ -0,0 +1,571 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+use super::{options, Flags};
+use crate::core::charset::Charset;
+use crate::core::colors::{self, ColorMode};
+
+// Where the config is looked for when --config isn't given.
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+
+    Some(config_dir.join("small-axe").join("config.toml"))
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => {
+                write!(f, "{}:{line}: {}", self.path.display(), self.reason)
+            }
+            None => write!(f, "{}: {}", self.path.display(), self.reason),
+        }
+    }
+}
+
+// Applies the config at `path`, or at the default location if there is
+// one, to `flags`. A missing default config is fine, a missing --config
+// file isn't.
+pub fn load(path: Option<&Path>, flags: &mut Flags) -> Result<(), ConfigError> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(()),
+        },
+    };
+
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(())
+        }
+        Err(err) => {
+            return Err(ConfigError {
+                path,
+                line: None,
+                reason: err.to_string(),
+            })
+        }
+    };
+
+    apply(&source, flags).map_err(|(line, reason)| ConfigError {
+        path,
+        line: Some(line),
+        reason,
+    })
+}
+
+// Sets whatever the config mentions. The command line is parsed on top
+// of the result, so anything given there wins.
+fn apply(source: &str, flags: &mut Flags) -> Result<(), (usize, String)> {
+    for entry in parse(source)? {
+        let invalid =
+            |reason: &str| (entry.line, format!("{}: {reason}", entry.key));
+
+        match (entry.table.as_deref(), entry.key.as_str()) {
+            (None, "dirsfirst") => {
+                flags.dirs_first = entry
+                    .value
+                    .as_bool()
+                    .ok_or_else(|| invalid("expected true or false"))?;
+            }
+            (None, "charset") => {
+                let expected = "expected utf8, ascii, ansi or cp437";
+                let charset = entry.value.as_str().and_then(Charset::parse);
+
+                flags.charset = Some(charset.ok_or_else(|| invalid(expected))?);
+            }
+            (None, "sort") => {
+                let expected = "expected name, reverse or mtime";
+
+                (flags.reverse_alpha_sort, flags.last_modified_sort) = entry
+                    .value
+                    .as_str()
+                    .and_then(options::parse_sort)
+                    .ok_or_else(|| invalid(expected))?;
+            }
+            (None, "ignore") => {
+                // a single glob or a list of them
+                flags.ignore = match &entry.value {
+                    Value::String(glob) => vec![glob.clone()],
+                    Value::Array(globs) => globs
+                        .iter()
+                        .map(|glob| glob.as_str().map(str::to_owned))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| invalid("expected a list of globs"))?,
+                    _ => return Err(invalid("expected a list of globs")),
+                };
+            }
+            (None, "color") => {
+                let expected = "expected auto, always or never";
+                let mode = entry.value.as_str().and_then(ColorMode::parse);
+
+                flags.color = mode.ok_or_else(|| invalid(expected))?;
+            }
+            (Some("colors"), name) => {
+                let sgr = entry
+                    .value
+                    .as_str()
+                    .ok_or_else(|| invalid("expected an SGR string"))?;
+
+                colors::check_theme_entry(name, sgr)
+                    .map_err(|reason| invalid(&reason))?;
+
+                flags.theme.push((name.to_owned(), sgr.to_owned()));
+            }
+            (Some(table), _) if table != "colors" => {
+                return Err((entry.line, format!("unknown table [{table}]")));
+            }
+            _ => {
+                return Err((
+                    entry.line,
+                    format!("unknown key '{}'", entry.key),
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The slice of TOML the config needs: `key = value` pairs under optional
+// `[table]` headers, `#` comments, and string, boolean, integer and array
+// values.
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    line: usize,
+    table: Option<String>,
+    key: String,
+    value: Value,
+}
+
+fn parse(source: &str) -> Result<Vec<Entry>, (usize, String)> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+        line: 1,
+    };
+
+    parser.entries().map_err(|reason| (parser.line, reason))
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<char> {
+        let ch = self.chars.next();
+
+        if ch == Some('\n') {
+            self.line += 1;
+        }
+
+        ch
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.chars.peek() == Some(&expected) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.chars.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    // Blank lines and comments between entries and array items.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.chars.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.next();
+                }
+                Some('#') => {
+                    while !matches!(self.chars.peek(), None | Some('\n')) {
+                        self.next();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+
+        if self.eat('#') {
+            while !matches!(self.chars.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+
+        self.eat('\r');
+
+        match self.chars.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.next();
+                Ok(())
+            }
+            Some(ch) => Err(format!("unexpected '{ch}' after value")),
+        }
+    }
+
+    fn entries(&mut self) -> Result<Vec<Entry>, String> {
+        let mut entries = vec![];
+        let mut table = None;
+
+        loop {
+            self.skip_blank();
+
+            if self.chars.peek().is_none() {
+                return Ok(entries);
+            }
+
+            if self.eat('[') {
+                self.skip_spaces();
+                table = Some(self.key()?);
+                self.skip_spaces();
+
+                if !self.eat(']') {
+                    return Err(String::from("expected ']' after table name"));
+                }
+
+                self.end_of_line()?;
+                continue;
+            }
+
+            let line = self.line;
+            let key = self.key()?;
+            self.skip_spaces();
+
+            if !self.eat('=') {
+                return Err(format!("expected '=' after '{key}'"));
+            }
+
+            self.skip_spaces();
+            let value = self.value()?;
+            self.end_of_line()?;
+
+            entries.push(Entry {
+                line,
+                table: table.clone(),
+                key,
+                value,
+            });
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.chars.peek() {
+            Some('"') => {
+                self.next();
+                self.basic_string()
+            }
+            Some('\'') => {
+                self.next();
+                self.literal_string()
+            }
+            _ => {
+                let mut key = String::new();
+
+                while let Some(&ch) = self.chars.peek() {
+                    if !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '-') {
+                        break;
+                    }
+
+                    key.push(ch);
+                    self.next();
+                }
+
+                if key.is_empty() {
+                    Err(String::from("expected a key"))
+                } else {
+                    Ok(key)
+                }
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.chars.peek() {
+            Some('"') => {
+                self.next();
+                self.basic_string().map(Value::String)
+            }
+            Some('\'') => {
+                self.next();
+                self.literal_string().map(Value::String)
+            }
+            Some('[') => {
+                self.next();
+                self.array()
+            }
+            _ => {
+                let mut word = String::new();
+
+                while let Some(&ch) = self.chars.peek() {
+                    if !(ch.is_ascii_alphanumeric() || "+-_".contains(ch)) {
+                        break;
+                    }
+
+                    word.push(ch);
+                    self.next();
+                }
+
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => Err(String::from("expected a value")),
+                    _ => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| format!("unsupported value '{word}'")),
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut items = vec![];
+
+        loop {
+            self.skip_blank();
+
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+
+            items.push(self.value()?);
+            self.skip_blank();
+
+            if !self.eat(',') {
+                self.skip_blank();
+
+                return if self.eat(']') {
+                    Ok(Value::Array(items))
+                } else {
+                    Err(String::from("expected ',' or ']' in array"))
+                };
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+
+        loop {
+            // strings can't span lines
+            let Some(ch) = self.chars.next_if(|ch| *ch != '\n') else {
+                return Err(String::from("unterminated string"));
+            };
+
+            match ch {
+                '"' => return Ok(string),
+                '\\' => string.push(match self.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('e') => '\x1b',
+                    Some(ch) => return Err(format!("unknown escape '\\{ch}'")),
+                    None => return Err(String::from("unterminated string")),
+                }),
+                ch => string.push(ch),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+
+        loop {
+            // strings can't span lines
+            let Some(ch) = self.chars.next_if(|ch| *ch != '\n') else {
+                return Err(String::from("unterminated string"));
+            };
+
+            match ch {
+                '\'' => return Ok(string),
+                ch => string.push(ch),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml_subset() {
+        let source = "# defaults\n\
+            dirsfirst = true # trailing comment\n\
+            depth = 1_000\n\
+            ignore = [\n  \"target\",\n  'node_modules', # deps\n]\n\
+            \n\
+            [colors]\n\
+            \"*.rs\" = \"01;33\"\n";
+
+        let entries = parse(source).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    line: 2,
+                    table: None,
+                    key: String::from("dirsfirst"),
+                    value: Value::Bool(true),
+                },
+                Entry {
+                    line: 3,
+                    table: None,
+                    key: String::from("depth"),
+                    value: Value::Integer(1000),
+                },
+                Entry {
+                    line: 4,
+                    table: None,
+                    key: String::from("ignore"),
+                    value: Value::Array(vec![
+                        Value::String(String::from("target")),
+                        Value::String(String::from("node_modules")),
+                    ]),
+                },
+                Entry {
+                    line: 10,
+                    table: Some(String::from("colors")),
+                    key: String::from("*.rs"),
+                    value: Value::String(String::from("01;33")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_lines() {
+        assert_eq!(
+            parse("a = 1\nb = \"open\n"),
+            Err((2, String::from("unterminated string")))
+        );
+        assert_eq!(
+            parse("a = 1 2"),
+            Err((1, String::from("unexpected '2' after value")))
+        );
+        assert_eq!(
+            parse("\n\nkey\n"),
+            Err((3, String::from("expected '=' after 'key'")))
+        );
+    }
+
+    #[test]
+    fn applies_settings_to_flags() {
+        let mut flags = Flags::default();
+
+        apply(
+            "dirsfirst = true\n\
+             charset = \"ascii\"\n\
+             sort = \"reverse\"\n\
+             ignore = [\"*.o\", \"target\"]\n\
+             color = \"never\"\n\
+             [colors]\n\
+             directory = \"01;35\"\n\
+             perm_read = \"36\"\n\
+             \"*.rs\" = \"33\"\n",
+            &mut flags,
+        )
+        .unwrap();
+
+        assert!(flags.dirs_first);
+        assert!(flags.reverse_alpha_sort);
+        assert_eq!(flags.charset, Some(Charset::Ascii));
+        assert_eq!(flags.ignore, vec!["*.o", "target"]);
+        assert_eq!(flags.color, ColorMode::Never);
+        assert_eq!(
+            flags.theme,
+            vec![
+                (String::from("directory"), String::from("01;35")),
+                (String::from("perm_read"), String::from("36")),
+                (String::from("*.rs"), String::from("33")),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        let mut flags = Flags::default();
+
+        assert_eq!(
+            apply("dirs_first = true", &mut flags),
+            Err((1, String::from("unknown key 'dirs_first'")))
+        );
+        assert_eq!(
+            apply("charset = \"ebcdic\"", &mut flags),
+            Err((
+                1,
+                String::from("charset: expected utf8, ascii, ansi or cp437")
+            ))
+        );
+        assert_eq!(
+            apply("[colors]\nfolder = \"34\"", &mut flags),
+            Err((2, String::from("folder: unknown color name")))
+        );
+        assert_eq!(
+            apply("[colors]\ndirectory = \"blue\"", &mut flags),
+            Err((2, String::from("directory: invalid SGR sequence 'blue'")))
+        );
+        assert_eq!(
+            apply("[layout]\nwidth = 80", &mut flags),
+            Err((2, String::from("unknown table [layout]")))
+        );
+    }
+}
@@ -1,43 +1,96 @@
-use std::{env::Args, path::PathBuf};
+use std::path::PathBuf;
 
-#[derive(Debug, Default)]
+pub mod config;
+pub mod options;
+
+use options::CliError;
+
+use crate::core::charset::Charset;
+use crate::core::colors::ColorMode;
+use crate::core::display::ReportStyle;
+use crate::core::top::TopBy;
+
+#[derive(Clone, Debug, Default)]
 #[cfg(unix)]
 pub struct Flags {
-    pub dir_path: Option<PathBuf>, // done
-    pub help: bool,                // done
+    pub dir_paths: Vec<PathBuf>, // done
+    pub from_file: bool,
+    pub archive: bool,
+    pub help: bool, // done
     pub version: bool,
-    pub all: bool,                     // done
-    pub dirs: bool,                    // done
-    pub full_path: bool,               // done
-    pub no_indent: bool,               // done
-    pub follow_symlinks: bool,         // done
+    pub all: bool,             // done
+    pub dirs: bool,            // done
+    pub full_path: bool,       // done
+    pub no_indent: bool,       // done
+    pub follow_symlinks: bool, // done
+    pub one_fs: bool,
     pub pattern_match: Option<String>, // done
     pub pattern_exclude: Option<String>,
+    pub ignore: Vec<String>,
+    pub regex_match: Option<String>,
+    pub regex_exclude: Option<String>,
+    pub size_filter: Option<String>,
+    pub newer: Option<String>,
+    pub older_than: Option<String>,
+    pub entry_type: Option<String>,
+    pub perm: Option<String>,
+    pub owner: Option<String>,
     pub prune: bool, // done
     pub limit: Option<usize>,
     pub time_fmt: Option<String>,
-    pub no_report: bool,           // done
+    pub no_report: bool, // done
+    pub report: ReportStyle,
+    pub stats: bool,
+    pub top: Option<usize>,
+    pub top_by: TopBy,
     pub protections: bool,         // done
     pub size: bool,                //done
     pub human_readable_size: bool, //done
     pub username: bool,            // done unix only
     pub group: bool,               // done unix only
     pub last_modified: bool,
-    pub inode: bool,                     // done unix only
-    pub device: bool,                    // done unix only
+    pub inode: bool,  // done unix only
+    pub device: bool, // done unix only
+    pub meta_first: bool,
+    pub condense: bool,
+    pub interactive: bool,
+    pub watch: bool,
+    pub snapshot: Option<PathBuf>,
+    pub diff: Option<PathBuf>,
+    pub diff_only: bool,
+    pub compare: bool,
+    pub hash: bool,
     pub identify: bool,                  // done
     pub unprintable_question_mark: bool, // done
     pub unprintable_as_is: bool,         // done
-    pub reverse_alpha_sort: bool,        // done
-    pub last_modified_sort: bool,        // done
-    pub dirs_first: bool,                // done
-    pub output_file: Option<PathBuf>,    // done
-    pub no_colors: bool,                 // done
-    pub colors: bool,                    // done
-    pub max_depth: Option<usize>,        // done
+    pub octal_escape: bool,
+    pub quote_names: bool,
+    pub hyperlink: bool,
+    pub charset: Option<Charset>,
+    pub reverse_alpha_sort: bool,     // done
+    pub last_modified_sort: bool,     // done
+    pub dirs_first: bool,             // done
+    pub output_file: Option<PathBuf>, // done
+    pub color: ColorMode,
+    pub theme: Vec<(String, String)>,
+    pub config: Option<PathBuf>,
+    pub max_depth: Option<usize>, // done
 }
 
 impl Flags {
+    // Whether any [..] metadata column is shown.
+    #[cfg(unix)]
+    pub fn shows_metadata(&self) -> bool {
+        self.protections
+            || self.size
+            || self.human_readable_size
+            || self.last_modified
+            || self.inode
+            || self.group
+            || self.device
+            || self.username
+    }
+
     #[cfg(unix)]
     pub fn get_metatdata_flags(&self) -> [bool; 10] {
         [
@@ -50,7 +103,7 @@ impl Flags {
             self.group,
             self.device,
             self.username,
-            self.colors,
+            self.color == ColorMode::Always,
         ]
     }
 }
@@ -70,197 +123,91 @@ pub struct TreeIteratorFlags {
     pub last_mod_sort: bool,
     pub rev_alpha_sort: bool,
     pub follow_symlinks: bool,
+    pub one_fs: bool,
+    pub prune: bool,
+    pub file_limit: Option<usize>,
+}
+// Cargo features this binary was built with, for --version.
+const FEATURES: [(&str, bool); 1] = [("archive", cfg!(feature = "archive"))];
+
+pub fn version() -> String {
+    let features = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    format!(
+        "tree ({}) v{}\nfeatures: {}\ntarget: {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        if features.is_empty() {
+            String::from("none")
+        } else {
+            features.join(", ")
+        },
+        env!("TREE_BUILD_TARGET"),
+    )
 }
-#[derive(Debug)]
+
+#[derive(Clone, Debug)]
 pub struct Cmd {
     pub flags: Flags,
 }
 
 impl Cmd {
-    pub fn process_args(args: Vec<String>) -> Vec<String> {
-        let (additional_processing, ready): (Vec<_>, Vec<_>) =
-            args.into_iter().partition(|flag| {
-                !flag.starts_with("--")
-                    && flag.starts_with('-')
-                    && flag.len() > 2
-            });
+    // Parses the arguments after the program name. Usage errors are
+    // returned rather than printed so main can exit with status 2.
+    pub fn parse<I>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        // TREE_OPTIONS goes first so anything on the command line wins
+        let mut env_args = match std::env::var("TREE_OPTIONS") {
+            Ok(options) => {
+                options::split_words(&options).map_err(CliError::EnvOptions)?
+            }
+            Err(_) => vec![],
+        };
+
+        env_args.extend(args);
+        let args = env_args;
+
+        // a first pass only to find --config, the config then provides
+        // the defaults the arguments are parsed over
+        let mut cli_flags = Flags::default();
+        options::parse_into(args.iter().cloned(), &mut cli_flags)?;
+
+        let mut flags = Flags::default();
+        config::load(cli_flags.config.as_deref(), &mut flags)
+            .map_err(CliError::Config)?;
+
+        options::parse_into(args, &mut flags)?;
 
-        if !additional_processing.is_empty() {
-            return additional_processing
-                .iter()
-                .flat_map(|flag| {
-                    flag.chars()
-                        .skip(1)
-                        .map(|ch| format!("-{ch}"))
-                        .collect::<Vec<String>>()
-                })
-                .chain(ready.into_iter())
-                .collect::<Vec<String>>();
+        if flags.dir_paths.is_empty() {
+            flags.dir_paths.push(if flags.from_file {
+                PathBuf::from("-")
+            } else {
+                std::env::current_dir().expect("failed to get cwd")
+            });
         }
 
-        ready
+        Ok(Self { flags })
     }
 
-    pub fn get_tree_iter_flags(mut self) -> TreeIteratorFlags {
+    pub fn get_tree_iter_flags(&self, root: PathBuf) -> TreeIteratorFlags {
         TreeIteratorFlags {
-            root: self.flags.dir_path.take(),
-            max_depth: self.flags.max_depth.take(),
+            root: Some(root),
+            max_depth: self.flags.max_depth,
             visit_all: self.flags.all,
             dirs_only: self.flags.dirs,
             dirs_first: self.flags.dirs_first,
             last_mod_sort: self.flags.last_modified_sort,
             rev_alpha_sort: self.flags.reverse_alpha_sort,
             follow_symlinks: self.flags.follow_symlinks,
+            one_fs: self.flags.one_fs,
+            prune: self.flags.prune,
+            file_limit: self.flags.limit,
         }
     }
 }
-
-impl From<Args> for Cmd {
-    fn from(args: Args) -> Self {
-        let mut flags = Flags {
-            dir_path: None,
-            ..Flags::default()
-        };
-
-        let commands = Cmd::process_args(args.skip(1).collect::<Vec<_>>());
-
-        let mut cmd_flags = commands.iter();
-
-        while let Some(flag) = cmd_flags.next() {
-            match flag.as_str() {
-                "--help" => {
-                    flags.help = true;
-                }
-                "--version" => {
-                    flags.version = true;
-                }
-                "--noreport" => {
-                    flags.no_report = true;
-                }
-                "--inodes" => {
-                    flags.inode = true;
-                }
-                "--device" => {
-                    flags.device = true;
-                }
-                "--dirsfirst" => {
-                    flags.dirs_first = true;
-                }
-                "--prune" => {
-                    flags.prune = true;
-                }
-                "--filelimit" => {
-                    flags.limit = cmd_flags.next().map(|d| {
-                        d.trim()
-                            .parse::<usize>()
-                            .expect("error parsing file limit value")
-                    });
-                }
-                "-D" => flags.last_modified = true,
-                "-a" => flags.all = true,
-                "-d" => flags.dirs = true,
-                "-f" => flags.full_path = true,
-                "-F" => flags.identify = true,
-                "-i" => flags.no_indent = true,
-                "-l" => flags.follow_symlinks = true,
-                "-x" => todo!(),
-                "-P" => {
-                    flags.pattern_match =
-                        cmd_flags.next().map(|f| f.trim().to_owned());
-                }
-                "-I" => {
-                    flags.pattern_exclude =
-                        cmd_flags.next().map(|f| f.trim().to_owned());
-                }
-                "-p" => flags.protections = true,
-                "-s" => flags.size = true,
-                "-h" => flags.human_readable_size = true,
-                "-u" => flags.username = true,
-                "-g" => flags.group = true,
-                "-q" => flags.unprintable_question_mark = true,
-                "-N" => flags.unprintable_as_is = true,
-                "-r" => flags.reverse_alpha_sort = true,
-                "-t" => flags.last_modified_sort = true,
-                "-n" => flags.no_colors = true,
-                "-C" => flags.colors = true,
-                "-A" => todo!(),
-                "-S" => todo!(),
-                "-L" => {
-                    flags.max_depth = cmd_flags.next().map(|d| {
-                        d.trim()
-                            .parse::<usize>()
-                            .expect("error parsing max depth value")
-                    });
-                }
-                "-o" => {
-                    flags.output_file = cmd_flags
-                        .next()
-                        .map(|f| PathBuf::from(f.trim().to_owned()));
-                }
-                _ => {
-                    if flag.starts_with('-') {
-                        println!("\n{flag} is not a valid flag.\n");
-                    } else {
-                        flags.dir_path = Some(PathBuf::from(flag));
-                    }
-                }
-            }
-        }
-
-        if flags.dir_path.is_none() {
-            flags.dir_path =
-                Some(std::env::current_dir().expect("failed to get cwd"));
-        }
-
-        Self { flags }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn stringify(str_refs: Vec<&str>) -> Vec<String> {
-        str_refs
-            .iter()
-            .map(|&a| String::from(a))
-            .collect::<Vec<String>>()
-    }
-
-    #[test]
-    fn parses_hyphen_delimited_args() {
-        let args = stringify(vec![
-            "-a",
-            "-h",
-            "-s",
-            "-d",
-            "--dirsfirst",
-            "--prune",
-            "src",
-        ]);
-
-        let result = Cmd::process_args(args.clone());
-
-        assert_eq!(result, args)
-    }
-
-    #[test]
-    fn parses_non_hyphen_delimited_args() {
-        let args = stringify(vec!["-ahsd", "--dirsfirst", "--prune", "src"]);
-
-        let result = Cmd::process_args(args);
-
-        assert_eq!(
-            result,
-            stringify(vec![
-                "-a",
-                "-h",
-                "-s",
-                "-d",
-                "--dirsfirst",
-                "--prune",
-                "src",
-            ])
-        )
-    }
-}
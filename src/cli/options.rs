@@ -0,0 +1,1094 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use super::config::ConfigError;
+use super::Flags;
+use crate::core::charset::Charset;
+use crate::core::colors::ColorMode;
+use crate::core::display::ReportStyle;
+use crate::core::pattern::regex::Regex;
+use crate::core::predicate::Predicate;
+use crate::core::top::TopBy;
+
+// What an option does once it has been matched. Switches just set a flag,
+// value options get their argument (from `--opt=value`, `--opt value`,
+// `-L2` or `-L 2`) and may reject it.
+pub enum Action {
+    Switch(fn(&mut Flags)),
+    Value(&'static str, fn(&mut Flags, &str) -> Result<(), String>),
+}
+
+// The groups --help is split into, in the order they're printed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Section {
+    Listing,
+    Filtering,
+    Sorting,
+    Metadata,
+    Output,
+    Misc,
+}
+
+impl Section {
+    const ALL: [Section; 6] = [
+        Section::Listing,
+        Section::Filtering,
+        Section::Sorting,
+        Section::Metadata,
+        Section::Output,
+        Section::Misc,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Section::Listing => "Listing options",
+            Section::Filtering => "Filtering options",
+            Section::Sorting => "Sorting options",
+            Section::Metadata => "Metadata options",
+            Section::Output => "Output format options",
+            Section::Misc => "Miscellaneous options",
+        }
+    }
+}
+
+pub struct Opt {
+    pub short: Option<char>,
+    pub long: Option<&'static str>,
+    pub section: Section,
+    pub help: &'static str,
+    pub action: Action,
+}
+
+impl Opt {
+    // How the option is spelled in messages, long form preferred.
+    pub fn name(&self) -> String {
+        match (self.long, self.short) {
+            (Some(long), _) => format!("--{long}"),
+            (None, Some(short)) => format!("-{short}"),
+            (None, None) => String::new(),
+        }
+    }
+
+    fn takes_value(&self) -> bool {
+        matches!(self.action, Action::Value(..))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum CliError {
+    UnknownOption {
+        option: String,
+        suggestion: Option<String>,
+    },
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        reason: String,
+    },
+    Config(ConfigError),
+    EnvOptions(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption { option, suggestion } => {
+                write!(f, "unrecognized option '{option}'")?;
+
+                if let Some(suggestion) = suggestion {
+                    write!(f, "\n\n  did you mean '{suggestion}'?")?;
+                }
+
+                Ok(())
+            }
+            CliError::MissingValue(option) => {
+                write!(f, "option '{option}' requires a value")
+            }
+            CliError::UnexpectedValue(option) => {
+                write!(f, "option '{option}' doesn't take a value")
+            }
+            CliError::InvalidValue {
+                option,
+                value,
+                reason,
+            } => write!(f, "invalid value '{value}' for '{option}': {reason}"),
+            CliError::Config(err) => write!(f, "bad config file {err}"),
+            CliError::EnvOptions(reason) => {
+                write!(f, "bad TREE_OPTIONS: {reason}")
+            }
+        }
+    }
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| String::from("expected a non-negative whole number"))
+}
+
+// The sort orders --sort and the config's `sort` name, as
+// (reverse_alpha_sort, last_modified_sort).
+pub fn parse_sort(value: &str) -> Option<(bool, bool)> {
+    match value.trim() {
+        "name" => Some((false, false)),
+        "reverse" => Some((true, false)),
+        "mtime" => Some((false, true)),
+        _ => None,
+    }
+}
+
+fn owned(value: &str) -> Option<String> {
+    Some(value.trim().to_owned())
+}
+
+// Every option tree accepts. Parsing, suggestions and --help all come
+// from this table, so adding a row is all a new option needs.
+pub static OPTIONS: &[Opt] = &[
+    Opt {
+        short: None,
+        long: Some("help"),
+        section: Section::Misc,
+        help: "list all flags",
+        action: Action::Switch(|flags| flags.help = true),
+    },
+    Opt {
+        short: None,
+        long: Some("version"),
+        section: Section::Misc,
+        help: "prints version of tree",
+        action: Action::Switch(|flags| flags.version = true),
+    },
+    Opt {
+        short: None,
+        long: Some("config"),
+        section: Section::Misc,
+        help: "read defaults from this file instead of ~/.config/small-axe/config.toml",
+        action: Action::Value("[file]", |flags, value| {
+            flags.config = Some(PathBuf::from(value));
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("noreport"),
+        section: Section::Output,
+        help: "silence total directory and file count",
+        action: Action::Switch(|flags| flags.no_report = true),
+    },
+    Opt {
+        short: None,
+        long: Some("report"),
+        section: Section::Output,
+        help: "summary style: counts only, or also links, bytes, skipped entries and time",
+        action: Action::Value("[short|full]", |flags, value| {
+            flags.report = ReportStyle::parse(value)
+                .ok_or_else(|| String::from("expected short or full"))?;
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("stats"),
+        section: Section::Output,
+        help: "after the tree, total up files by extension and by top-level directory",
+        action: Action::Switch(|flags| flags.stats = true),
+    },
+    Opt {
+        short: None,
+        long: Some("top"),
+        section: Section::Output,
+        help: "after the tree, list the top N paths ranked by --by",
+        action: Action::Value("[#]", |flags, value| {
+            flags.top = Some(parse_count(value)?);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("by"),
+        section: Section::Output,
+        help: "what --top ranks by: file size, file mtime or directory entry count",
+        action: Action::Value("[size|mtime|count]", |flags, value| {
+            flags.top_by = TopBy::parse(value).ok_or_else(|| {
+                String::from("expected size, mtime or count")
+            })?;
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("inodes"),
+        section: Section::Metadata,
+        help: "include inode of resource",
+        action: Action::Switch(|flags| flags.inode = true),
+    },
+    Opt {
+        short: None,
+        long: Some("device"),
+        section: Section::Metadata,
+        help: "include device id of resource",
+        action: Action::Switch(|flags| flags.device = true),
+    },
+    Opt {
+        short: None,
+        long: Some("metafirst"),
+        section: Section::Metadata,
+        help: "print the metadata block at the start of the line, before the indentation",
+        action: Action::Switch(|flags| flags.meta_first = true),
+    },
+    Opt {
+        short: None,
+        long: Some("dirsfirst"),
+        section: Section::Sorting,
+        help: "print directories before files",
+        action: Action::Switch(|flags| flags.dirs_first = true),
+    },
+    Opt {
+        short: None,
+        long: Some("no-dirsfirst"),
+        section: Section::Sorting,
+        help: "mix directories with files, overriding the config",
+        action: Action::Switch(|flags| flags.dirs_first = false),
+    },
+    Opt {
+        short: None,
+        long: Some("sort"),
+        section: Section::Sorting,
+        help: "sort by name, reverse name or mtime, overriding the config",
+        action: Action::Value("[name|reverse|mtime]", |flags, value| {
+            (flags.reverse_alpha_sort, flags.last_modified_sort) =
+                parse_sort(value).ok_or_else(|| {
+                    String::from("expected name, reverse or mtime")
+                })?;
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("prune"),
+        section: Section::Filtering,
+        help: "remove empty directories from output",
+        action: Action::Switch(|flags| flags.prune = true),
+    },
+    Opt {
+        short: None,
+        long: Some("fromfile"),
+        section: Section::Listing,
+        help: "read paths from file (or stdin with '-') instead of walking a directory",
+        action: Action::Switch(|flags| flags.from_file = true),
+    },
+    Opt {
+        short: None,
+        long: Some("archive"),
+        section: Section::Listing,
+        help: "list the contents of a tar, tar.gz or zip archive",
+        action: Action::Switch(|flags| flags.archive = true),
+    },
+    Opt {
+        short: None,
+        long: Some("filelimit"),
+        section: Section::Listing,
+        help: "skips directories with a file count over this limit",
+        action: Action::Value("[#]", |flags, value| {
+            flags.limit = Some(parse_count(value)?);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: Some('D'),
+        long: None,
+        section: Section::Metadata,
+        help: "print last modified",
+        action: Action::Switch(|flags| flags.last_modified = true),
+    },
+    Opt {
+        short: Some('a'),
+        long: None,
+        section: Section::Listing,
+        help: "include hidden files",
+        action: Action::Switch(|flags| flags.all = true),
+    },
+    Opt {
+        short: Some('d'),
+        long: None,
+        section: Section::Listing,
+        help: "include directories only",
+        action: Action::Switch(|flags| flags.dirs = true),
+    },
+    Opt {
+        short: Some('f'),
+        long: None,
+        section: Section::Listing,
+        help: "print full path of resource",
+        action: Action::Switch(|flags| flags.full_path = true),
+    },
+    Opt {
+        short: Some('F'),
+        long: None,
+        section: Section::Output,
+        help: "print '/' to identify directories",
+        action: Action::Switch(|flags| flags.identify = true),
+    },
+    Opt {
+        short: Some('i'),
+        long: None,
+        section: Section::Output,
+        help: "no indentation",
+        action: Action::Switch(|flags| flags.no_indent = true),
+    },
+    Opt {
+        short: Some('l'),
+        long: None,
+        section: Section::Listing,
+        help: "follow symlinks",
+        action: Action::Switch(|flags| flags.follow_symlinks = true),
+    },
+    Opt {
+        short: Some('x'),
+        long: None,
+        section: Section::Listing,
+        help: "stays on the filesystem of each root",
+        action: Action::Switch(|flags| flags.one_fs = true),
+    },
+    Opt {
+        short: Some('P'),
+        long: None,
+        section: Section::Filtering,
+        help: "include files and directories that match pattern",
+        action: Action::Value("[wildcard pattern]", |flags, value| {
+            flags.pattern_match = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: Some('I'),
+        long: None,
+        section: Section::Filtering,
+        help: "exclude files and directories that match pattern",
+        action: Action::Value("[wildcard pattern]", |flags, value| {
+            flags.pattern_exclude = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("match-regex"),
+        section: Section::Filtering,
+        help: "include files that match regular expression",
+        action: Action::Value("[regex]", |flags, value| {
+            Regex::parse(value.trim(), true).map_err(|err| err.to_string())?;
+            flags.regex_match = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("exclude-regex"),
+        section: Section::Filtering,
+        help: "exclude files that match regular expression",
+        action: Action::Value("[regex]", |flags, value| {
+            Regex::parse(value.trim(), false).map_err(|err| err.to_string())?;
+            flags.regex_exclude = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("size"),
+        section: Section::Filtering,
+        help: "include files larger (+), smaller (-) or equal to size",
+        action: Action::Value("[+-]N[ckMGT]", |flags, value| {
+            Predicate::parse_size(value.trim()).map_err(|err| err.to_string())?;
+            flags.size_filter = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("newer"),
+        section: Section::Filtering,
+        help: "include files modified within age or since YYYY-MM-DD",
+        action: Action::Value("[N[smhdw]|date]", |flags, value| {
+            Predicate::parse_newer(value.trim(), 0)
+                .map_err(|err| err.to_string())?;
+            flags.newer = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("older-than"),
+        section: Section::Filtering,
+        help: "include files modified before age or YYYY-MM-DD",
+        action: Action::Value("[age|date]", |flags, value| {
+            Predicate::parse_older_than(value.trim(), 0)
+                .map_err(|err| err.to_string())?;
+            flags.older_than = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("type"),
+        section: Section::Filtering,
+        help: "include file, dir, link, pipe, socket or executable",
+        action: Action::Value("[fdlpsx]", |flags, value| {
+            Predicate::parse_type(value.trim()).map_err(|err| err.to_string())?;
+            flags.entry_type = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("perm"),
+        section: Section::Filtering,
+        help: "include files matching octal or symbolic (u+x) mode",
+        action: Action::Value("[-/]mode", |flags, value| {
+            Predicate::parse_perm(value.trim()).map_err(|err| err.to_string())?;
+            flags.perm = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("owner"),
+        section: Section::Filtering,
+        help: "include files owned by user",
+        action: Action::Value("[name|uid]", |flags, value| {
+            Predicate::parse_owner(value.trim()).map_err(|err| err.to_string())?;
+            flags.owner = owned(value);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: Some('p'),
+        long: None,
+        section: Section::Metadata,
+        help: "print protections on resource",
+        action: Action::Switch(|flags| flags.protections = true),
+    },
+    Opt {
+        short: Some('s'),
+        long: None,
+        section: Section::Metadata,
+        help: "print resource size",
+        action: Action::Switch(|flags| flags.size = true),
+    },
+    Opt {
+        short: Some('h'),
+        long: None,
+        section: Section::Metadata,
+        help: "print human readable resource size",
+        action: Action::Switch(|flags| flags.human_readable_size = true),
+    },
+    Opt {
+        short: Some('u'),
+        long: None,
+        section: Section::Metadata,
+        help: "print user name",
+        action: Action::Switch(|flags| flags.username = true),
+    },
+    Opt {
+        short: Some('g'),
+        long: None,
+        section: Section::Metadata,
+        help: "print group",
+        action: Action::Switch(|flags| flags.group = true),
+    },
+    Opt {
+        short: Some('q'),
+        long: None,
+        section: Section::Output,
+        help: "replace unprintable characters with '?'",
+        action: Action::Switch(|flags| flags.unprintable_question_mark = true),
+    },
+    Opt {
+        short: Some('N'),
+        long: None,
+        section: Section::Output,
+        help: "print unprintable characters as is",
+        action: Action::Switch(|flags| flags.unprintable_as_is = true),
+    },
+    Opt {
+        short: Some('b'),
+        long: None,
+        section: Section::Output,
+        help: "print unprintable characters as octal escapes",
+        action: Action::Switch(|flags| flags.octal_escape = true),
+    },
+    Opt {
+        short: Some('Q'),
+        long: None,
+        section: Section::Output,
+        help: "quote names with double quotes",
+        action: Action::Switch(|flags| flags.quote_names = true),
+    },
+    Opt {
+        short: None,
+        long: Some("charset"),
+        section: Section::Output,
+        help: "line drawing characters (defaults from LANG/LC_ALL)",
+        action: Action::Value("[utf8|ascii|ansi|cp437]", |flags, value| {
+            flags.charset = Some(Charset::parse(value).ok_or_else(|| {
+                String::from("expected utf8, ascii, ansi or cp437")
+            })?);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: Some('A'),
+        long: None,
+        section: Section::Output,
+        help: "use ANSI line graphics",
+        action: Action::Switch(|flags| flags.charset = Some(Charset::Ansi)),
+    },
+    Opt {
+        short: Some('S'),
+        long: None,
+        section: Section::Output,
+        help: "use CP437 line graphics",
+        action: Action::Switch(|flags| flags.charset = Some(Charset::Cp437)),
+    },
+    Opt {
+        short: Some('r'),
+        long: None,
+        section: Section::Sorting,
+        help: "reverse alphabetic sort",
+        action: Action::Switch(|flags| flags.reverse_alpha_sort = true),
+    },
+    Opt {
+        short: Some('t'),
+        long: None,
+        section: Section::Sorting,
+        help: "last modified sort",
+        action: Action::Switch(|flags| flags.last_modified_sort = true),
+    },
+    Opt {
+        short: None,
+        long: Some("condense"),
+        section: Section::Output,
+        help: "fold chains of single-child directories into one line, a/b/c",
+        action: Action::Switch(|flags| flags.condense = true),
+    },
+    Opt {
+        short: None,
+        long: Some("interactive"),
+        section: Section::Output,
+        help: "browse the tree full-screen, printing the path picked with enter",
+        action: Action::Switch(|flags| flags.interactive = true),
+    },
+    Opt {
+        short: None,
+        long: Some("watch"),
+        section: Section::Output,
+        help: "redraw whenever a listed directory changes, highlighting what changed",
+        action: Action::Switch(|flags| flags.watch = true),
+    },
+    Opt {
+        short: None,
+        long: Some("snapshot"),
+        section: Section::Output,
+        help: "also save the walked tree as JSON, for a later --diff",
+        action: Action::Value("[file]", |flags, value| {
+            flags.snapshot = Some(PathBuf::from(value));
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("diff"),
+        section: Section::Output,
+        help: "show what was added, removed or modified since a snapshot or in another directory",
+        action: Action::Value("[snapshot|dir]", |flags, value| {
+            flags.diff = Some(PathBuf::from(value));
+            Ok(())
+        }),
+    },
+    Opt {
+        short: None,
+        long: Some("diff-only"),
+        section: Section::Output,
+        help: "with --diff, leave out entries with no changes at or under them",
+        action: Action::Switch(|flags| flags.diff_only = true),
+    },
+    Opt {
+        short: None,
+        long: Some("compare"),
+        section: Section::Output,
+        help: "show two directories side by side, marking what differs",
+        action: Action::Switch(|flags| flags.compare = true),
+    },
+    Opt {
+        short: None,
+        long: Some("hash"),
+        section: Section::Output,
+        help: "with --compare, also compare file contents",
+        action: Action::Switch(|flags| flags.hash = true),
+    },
+    Opt {
+        short: None,
+        long: Some("hyperlink"),
+        section: Section::Output,
+        help: "make names clickable file:// links in terminals that support OSC 8",
+        action: Action::Switch(|flags| flags.hyperlink = true),
+    },
+    Opt {
+        short: None,
+        long: Some("color"),
+        section: Section::Output,
+        help: "colorize output (auto: only on a terminal, honoring NO_COLOR)",
+        action: Action::Value("[auto|always|never]", |flags, value| {
+            flags.color = ColorMode::parse(value).ok_or_else(|| {
+                String::from("expected auto, always or never")
+            })?;
+            Ok(())
+        }),
+    },
+    Opt {
+        short: Some('n'),
+        long: None,
+        section: Section::Output,
+        help: "no colors",
+        action: Action::Switch(|flags| flags.color = ColorMode::Never),
+    },
+    Opt {
+        short: Some('C'),
+        long: None,
+        section: Section::Output,
+        help: "use ls colors",
+        action: Action::Switch(|flags| flags.color = ColorMode::Always),
+    },
+    Opt {
+        short: Some('L'),
+        long: None,
+        section: Section::Listing,
+        help: "sets max-depth of tree traversal",
+        action: Action::Value("[#]", |flags, value| {
+            flags.max_depth = Some(parse_count(value)?);
+            Ok(())
+        }),
+    },
+    Opt {
+        short: Some('o'),
+        long: None,
+        section: Section::Output,
+        help: "output file path",
+        action: Action::Value("[file]", |flags, value| {
+            flags.output_file = Some(PathBuf::from(value.trim()));
+            Ok(())
+        }),
+    },
+];
+
+fn find_long(name: &str) -> Option<&'static Opt> {
+    OPTIONS.iter().find(|opt| opt.long == Some(name))
+}
+
+fn find_short(short: char) -> Option<&'static Opt> {
+    OPTIONS.iter().find(|opt| opt.short == Some(short))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_ch) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b_ch) in b.iter().enumerate() {
+            let above = row[j + 1];
+
+            row[j + 1] = if a_ch == *b_ch {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+// The closest long option, if it's close enough to be a likely typo.
+fn suggest(name: &str) -> Option<String> {
+    // a typo in a short name is rarely a few edits from a long one
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    OPTIONS
+        .iter()
+        .filter_map(|opt| opt.long)
+        .map(|long| (edit_distance(name, long), long))
+        .filter(|(distance, long)| {
+            *distance <= max_distance
+                || (name.len() >= 3 && long.starts_with(name))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, long)| format!("--{long}"))
+}
+
+fn apply(
+    opt: &Opt,
+    flags: &mut Flags,
+    value: Option<String>,
+) -> Result<(), CliError> {
+    match (&opt.action, value) {
+        (Action::Switch(set), None) => {
+            set(flags);
+            Ok(())
+        }
+        (Action::Switch(_), Some(_)) => {
+            Err(CliError::UnexpectedValue(opt.name()))
+        }
+        (Action::Value(..), None) => Err(CliError::MissingValue(opt.name())),
+        (Action::Value(_, set), Some(value)) => {
+            set(flags, &value).map_err(|reason| CliError::InvalidValue {
+                option: opt.name(),
+                value,
+                reason,
+            })
+        }
+    }
+}
+
+// Parses arguments (without the program name) into `flags`. Anything that
+// isn't an option is a path, as is everything after `--`.
+pub fn parse_into<I>(args: I, flags: &mut Flags) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut options_ended = false;
+
+    while let Some(arg) = args.next() {
+        if options_ended || arg == "-" || !arg.starts_with('-') {
+            // a lone '-' is a path meaning stdin
+            flags.dir_paths.push(PathBuf::from(arg));
+        } else if arg == "--" {
+            options_ended = true;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (long, None),
+            };
+
+            let opt =
+                find_long(name).ok_or_else(|| CliError::UnknownOption {
+                    option: format!("--{name}"),
+                    suggestion: suggest(name),
+                })?;
+
+            let value = if opt.takes_value() && inline.is_none() {
+                args.next()
+            } else {
+                inline
+            };
+
+            apply(opt, flags, value)?;
+        } else {
+            let cluster = &arg[1..];
+
+            for (idx, short) in cluster.char_indices() {
+                let Some(opt) = find_short(short) else {
+                    // `-prune` is almost certainly a missing dash, but
+                    // anything else is a short option, not a long typo
+                    let suggestion =
+                        find_long(cluster).map(|_| format!("--{cluster}"));
+
+                    return Err(CliError::UnknownOption {
+                        option: format!("-{short}"),
+                        suggestion,
+                    });
+                };
+
+                if opt.takes_value() {
+                    // the rest of the cluster is the value, as in -L2
+                    let rest = &cluster[idx + short.len_utf8()..];
+
+                    let value = if rest.is_empty() {
+                        args.next()
+                    } else {
+                        Some(rest.to_owned())
+                    };
+
+                    apply(opt, flags, value)?;
+                    break;
+                }
+
+                apply(opt, flags, None)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Splits TREE_OPTIONS into arguments the way a shell would: whitespace
+// separates words, single quotes are literal, double quotes allow \" and
+// \\, and a backslash outside quotes escapes the next character. No
+// expansion of any kind is done.
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => return Err(String::from("unterminated quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\')) => word.push(ch),
+                            Some(ch) => {
+                                word.push('\\');
+                                word.push(ch);
+                            }
+                            None => {
+                                return Err(String::from("unterminated quote"))
+                            }
+                        },
+                        Some(ch) => word.push(ch),
+                        None => return Err(String::from("unterminated quote")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(ch) => word.get_or_insert_with(String::new).push(ch),
+                None => return Err(String::from("trailing backslash")),
+            },
+            ch => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+
+    words.extend(word);
+
+    Ok(words)
+}
+
+// Builds the usage line and option list from OPTIONS.
+pub fn help() -> String {
+    let shorts: String = OPTIONS
+        .iter()
+        .filter(|opt| opt.long.is_none())
+        .filter_map(|opt| opt.short)
+        .collect();
+
+    let longs = OPTIONS
+        .iter()
+        .filter_map(|opt| opt.long)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut help =
+        format!("\n  usage: tree [-{shorts}] --[{longs}] [path ...]\n\n");
+
+    for section in Section::ALL {
+        help.push_str(&format!("  {}:\n", section.title()));
+
+        for opt in OPTIONS.iter().filter(|opt| opt.section == section) {
+            let mut usage = opt.name();
+
+            if let Action::Value(value_name, _) = opt.action {
+                usage.push(' ');
+                usage.push_str(value_name);
+            }
+
+            help.push_str(&format!("    {usage:<26}-- {}\n", opt.help));
+        }
+
+        help.push('\n');
+    }
+
+    help
+}
+
+#[cfg(test)]
+mod option_parsing_tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_like_a_shell() {
+        assert_eq!(
+            split_words(r#"  -a --charset ascii -I 'foo bar' -P "*.rs" "#),
+            Ok(vec![
+                String::from("-a"),
+                String::from("--charset"),
+                String::from("ascii"),
+                String::from("-I"),
+                String::from("foo bar"),
+                String::from("-P"),
+                String::from("*.rs"),
+            ])
+        );
+        assert_eq!(
+            split_words(r#"a\ b "c \"d\" \n" ''"#),
+            Ok(vec![
+                String::from("a b"),
+                String::from("c \"d\" \\n"),
+                String::new(),
+            ])
+        );
+        assert_eq!(split_words(""), Ok(vec![]));
+        assert_eq!(
+            split_words("-I 'open"),
+            Err(String::from("unterminated quote"))
+        );
+        assert_eq!(
+            split_words("-a\\"),
+            Err(String::from("trailing backslash"))
+        );
+    }
+
+    fn parse(args: &[&str]) -> Result<Flags, CliError> {
+        let mut flags = Flags::default();
+        parse_into(args.iter().map(|&arg| String::from(arg)), &mut flags)?;
+        Ok(flags)
+    }
+
+    #[test]
+    fn parses_combined_short_flags_and_paths() {
+        let flags = parse(&["-ahsd", "--dirsfirst", "--prune", "src"]).unwrap();
+
+        assert!(flags.all && flags.human_readable_size && flags.size);
+        assert!(flags.dirs && flags.dirs_first && flags.prune);
+        assert_eq!(flags.dir_paths, vec![PathBuf::from("src")]);
+    }
+
+    #[test]
+    fn parses_values_in_every_form() {
+        let flags =
+            parse(&["-aL", "2", "--size", "-10M", "--perm=-644", "-Ifoo*"])
+                .unwrap();
+
+        assert!(flags.all);
+        assert_eq!(flags.max_depth, Some(2));
+        assert_eq!(flags.size_filter.as_deref(), Some("-10M"));
+        assert_eq!(flags.perm.as_deref(), Some("-644"));
+        assert_eq!(flags.pattern_exclude.as_deref(), Some("foo*"));
+
+        assert_eq!(parse(&["-L2"]).unwrap().max_depth, Some(2));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let flags = parse(&["-a", "--", "-weird", "--dirsfirst", "-"]).unwrap();
+
+        assert!(flags.all && !flags.dirs_first);
+        assert_eq!(
+            flags.dir_paths,
+            vec![
+                PathBuf::from("-weird"),
+                PathBuf::from("--dirsfirst"),
+                PathBuf::from("-")
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_usage_errors() {
+        assert_eq!(
+            parse(&["-L", "two"]).unwrap_err(),
+            CliError::InvalidValue {
+                option: String::from("-L"),
+                value: String::from("two"),
+                reason: String::from("expected a non-negative whole number"),
+            }
+        );
+        assert_eq!(
+            parse(&["-P"]).unwrap_err(),
+            CliError::MissingValue(String::from("-P"))
+        );
+        assert_eq!(
+            parse(&["--prune=yes"]).unwrap_err(),
+            CliError::UnexpectedValue(String::from("--prune"))
+        );
+        assert_eq!(
+            parse(&["-aZ"]).unwrap_err(),
+            CliError::UnknownOption {
+                option: String::from("-Z"),
+                suggestion: None,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_filters_that_dont_parse() {
+        let reason = |args: &[&str]| match parse(args) {
+            Err(CliError::InvalidValue { reason, .. }) => reason,
+            other => panic!("expected an invalid value, got {other:?}"),
+        };
+
+        assert_eq!(reason(&["--match-regex", "a("]), "unbalanced parenthesis");
+        assert_eq!(reason(&["--size", "10Q"]), "expected [+-]N[ckMGT]");
+        assert_eq!(
+            reason(&["--newer=yesterday"]),
+            "expected N[smhdw] or YYYY-MM-DD"
+        );
+        assert_eq!(
+            reason(&["--type", "fq"]),
+            "unknown type 'q', expected one of f d l p s x"
+        );
+        assert_eq!(
+            reason(&["--perm", "/9"]),
+            "expected [-/]octal or [-/]ugo+rwx"
+        );
+    }
+
+    #[test]
+    fn suggests_close_long_options() {
+        let suggestion = |args: &[&str]| match parse(args) {
+            Err(CliError::UnknownOption { suggestion, .. }) => suggestion,
+            _ => None,
+        };
+
+        assert_eq!(suggestion(&["--dirfirst"]), Some("--dirsfirst".into()));
+        assert_eq!(suggestion(&["--no-report"]), Some("--noreport".into()));
+        assert_eq!(suggestion(&["-prune"]), Some("--prune".into()));
+        assert_eq!(suggestion(&["--xyzzy"]), None);
+        assert_eq!(suggestion(&["--q"]), None);
+        assert_eq!(suggestion(&["-k"]), None);
+        assert_eq!(suggestion(&["-dirsfrst"]), None);
+    }
+
+    #[test]
+    fn help_lists_every_option() {
+        let help = help();
+
+        for opt in OPTIONS {
+            assert!(help.contains(&opt.name()), "{} missing", opt.name());
+        }
+    }
+
+    #[test]
+    fn help_groups_options_by_section() {
+        let help = help();
+        let position = |needle: &str| help.find(needle).unwrap();
+
+        let sorting = position("Sorting options:");
+        let metadata = position("Metadata options:");
+
+        assert!(sorting < position("--dirsfirst"));
+        assert!(position("--dirsfirst") < metadata);
+        assert!(metadata < position("--inodes"));
+    }
+}
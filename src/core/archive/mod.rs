@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use super::source::VirtualSource;
+
+mod tar;
+mod zip;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    Gzip,
+    Zip,
+}
+
+impl ArchiveKind {
+    // Sniffs the archive type from its leading bytes, so the extension
+    // doesn't matter.
+    pub fn detect(path: &Path) -> io::Result<Option<Self>> {
+        let mut header = Vec::with_capacity(tar::BLOCK_SIZE);
+
+        File::open(path)?
+            .take(tar::BLOCK_SIZE as u64)
+            .read_to_end(&mut header)?;
+
+        Ok(Self::from_magic(&header))
+    }
+
+    fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"PK\x03\x04")
+            || header.starts_with(b"PK\x05\x06")
+        {
+            Some(ArchiveKind::Zip)
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveKind::Gzip)
+        } else if header.get(257..262) == Some(b"ustar") {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+// Builds a virtual hierarchy from an archive's headers. Nothing is
+// extracted; sizes, modes, owners, mtimes and link targets all come from
+// the archive itself.
+pub fn read_archive(path: &Path, root: PathBuf) -> io::Result<VirtualSource> {
+    let mut source = VirtualSource::new(root);
+
+    match ArchiveKind::detect(path)? {
+        Some(ArchiveKind::Tar) => tar::read_entries(
+            io::BufReader::new(File::open(path)?),
+            &mut source,
+        )?,
+        Some(ArchiveKind::Gzip) => read_gzip(path, &mut source)?,
+        Some(ArchiveKind::Zip) => {
+            zip::read_entries(&mut File::open(path)?, &mut source)?
+        }
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a tar, tar.gz or zip archive",
+            ))
+        }
+    }
+
+    Ok(source)
+}
+
+#[cfg(feature = "archive")]
+fn read_gzip(path: &Path, source: &mut VirtualSource) -> io::Result<()> {
+    let decoder = flate2::read::MultiGzDecoder::new(io::BufReader::new(
+        File::open(path)?,
+    ));
+
+    tar::read_entries(decoder, source)
+}
+
+#[cfg(not(feature = "archive"))]
+fn read_gzip(_path: &Path, _source: &mut VirtualSource) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reading tar.gz requires the `archive` feature",
+    ))
+}
+
+#[cfg(feature = "archive")]
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut inflated = vec![];
+
+    flate2::read::DeflateDecoder::new(data)
+        .read_to_end(&mut inflated)
+        .ok()?;
+
+    Some(inflated)
+}
+
+#[cfg(not(feature = "archive"))]
+fn inflate(_data: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod archive_detection_tests {
+    use super::*;
+
+    #[test]
+    fn detects_archives_by_magic() {
+        let mut tar_header = vec![0_u8; 512];
+        tar_header[257..262].copy_from_slice(b"ustar");
+
+        assert_eq!(
+            ArchiveKind::from_magic(&tar_header),
+            Some(ArchiveKind::Tar)
+        );
+        assert_eq!(
+            ArchiveKind::from_magic(&[0x1f, 0x8b, 8, 0]),
+            Some(ArchiveKind::Gzip)
+        );
+        assert_eq!(
+            ArchiveKind::from_magic(b"PK\x03\x04rest"),
+            Some(ArchiveKind::Zip)
+        );
+        assert_eq!(ArchiveKind::from_magic(b"fn main() {}"), None);
+    }
+}
@@ -0,0 +1,396 @@
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::core::dirent::{
+    Metadata, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFREG,
+};
+use crate::core::source::VirtualSource;
+
+pub const BLOCK_SIZE: usize = 512;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+// Values from a pax extended header that override the next entry's
+// ustar fields.
+#[derive(Default)]
+struct PaxOverrides {
+    path: Option<Vec<u8>>,
+    linkpath: Option<Vec<u8>>,
+    size: Option<u64>,
+    mtime: Option<u64>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl PaxOverrides {
+    // Records look like "<len> <key>=<value>\n", where len covers the whole
+    // record.
+    fn parse(data: &[u8]) -> Self {
+        let mut overrides = Self::default();
+        let mut rest = data;
+
+        while let Some(space) = rest.iter().position(|&b| b == b' ') {
+            let Some(len) = std::str::from_utf8(&rest[..space])
+                .ok()
+                .and_then(|len| len.parse::<usize>().ok())
+                .filter(|&len| len > space && len <= rest.len())
+            else {
+                break;
+            };
+
+            let record = &rest[space + 1..len];
+            let record = record.strip_suffix(b"\n").unwrap_or(record);
+
+            if let Some(eq) = record.iter().position(|&b| b == b'=') {
+                let (key, value) = (&record[..eq], &record[eq + 1..]);
+
+                let number = || {
+                    std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|v| v.split('.').next())
+                        .and_then(|v| v.parse::<u64>().ok())
+                };
+
+                match key {
+                    b"path" => overrides.path = Some(value.to_vec()),
+                    b"linkpath" => overrides.linkpath = Some(value.to_vec()),
+                    b"size" => overrides.size = number(),
+                    b"mtime" => overrides.mtime = number(),
+                    b"uid" => overrides.uid = number().map(|v| v as u32),
+                    b"gid" => overrides.gid = number().map(|v| v as u32),
+                    _ => (),
+                }
+            }
+
+            rest = &rest[len..];
+        }
+
+        overrides
+    }
+}
+
+// Fields until the first NUL.
+fn field(bytes: &[u8]) -> &[u8] {
+    bytes
+        .iter()
+        .position(|&b| b == 0)
+        .map_or(bytes, |end| &bytes[..end])
+}
+
+// Numeric fields are octal text, or big-endian base-256 when the high bit
+// of the first byte is set (GNU extension for large values).
+fn parse_numeric(bytes: &[u8]) -> io::Result<u64> {
+    if bytes.first().is_some_and(|&b| b & 0x80 != 0) {
+        return Ok(bytes[1..]
+            .iter()
+            .fold(u64::from(bytes[0] & 0x7f), |acc, &b| {
+                (acc << 8) | u64::from(b)
+            }));
+    }
+
+    let text = std::str::from_utf8(field(bytes))
+        .map_err(|_| invalid("invalid tar numeric field"))?
+        .trim_matches(|c: char| c == ' ' || c == '\0');
+
+    if text.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(text, 8)
+        .map_err(|_| invalid("invalid tar numeric field"))
+}
+
+fn is_valid_checksum(header: &[u8; BLOCK_SIZE]) -> bool {
+    let Ok(expected) = parse_numeric(&header[148..156]) else {
+        return false;
+    };
+
+    let (unsigned, signed) = header.iter().enumerate().fold(
+        (0_u64, 0_i64),
+        |(unsigned, signed), (idx, &b)| {
+            // the checksum field itself is summed as spaces
+            let b = if (148..156).contains(&idx) { b' ' } else { b };
+            (unsigned + u64::from(b), signed + i64::from(b as i8))
+        },
+    );
+
+    unsigned == expected || signed == expected as i64
+}
+
+// Fills `block`, returning false on a clean EOF before any bytes.
+fn read_block<R: Read>(
+    reader: &mut R,
+    block: &mut [u8; BLOCK_SIZE],
+) -> io::Result<bool> {
+    let mut filled = 0;
+
+    while filled < BLOCK_SIZE {
+        match reader.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => filled += read,
+        }
+    }
+
+    Ok(true)
+}
+
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+}
+
+fn read_data<R: Read>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.take(padded(size)).read_to_end(&mut data)?;
+
+    if (data.len() as u64) < padded(size) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    data.truncate(size as usize);
+    Ok(data)
+}
+
+fn skip_data<R: Read>(reader: &mut R, size: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(padded(size)), &mut io::sink())?;
+
+    if skipped < padded(size) {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+// Reads ustar, GNU and pax headers, adding each member to `source`.
+pub fn read_entries<R: Read>(
+    mut reader: R,
+    source: &mut VirtualSource,
+) -> io::Result<()> {
+    let mut header = [0_u8; BLOCK_SIZE];
+    let mut long_name: Option<Vec<u8>> = None;
+    let mut long_link: Option<Vec<u8>> = None;
+    let mut pax = PaxOverrides::default();
+
+    while read_block(&mut reader, &mut header)? {
+        // An all zero block marks the end of the archive.
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        if !is_valid_checksum(&header) {
+            return Err(invalid("invalid tar header checksum"));
+        }
+
+        let header_size = parse_numeric(&header[124..136])?;
+        let typeflag = header[156];
+
+        match typeflag {
+            b'L' => {
+                long_name =
+                    Some(field(&read_data(&mut reader, header_size)?).to_vec());
+                continue;
+            }
+            b'K' => {
+                long_link =
+                    Some(field(&read_data(&mut reader, header_size)?).to_vec());
+                continue;
+            }
+            b'x' => {
+                pax =
+                    PaxOverrides::parse(&read_data(&mut reader, header_size)?);
+                continue;
+            }
+            b'g' => {
+                skip_data(&mut reader, header_size)?;
+                continue;
+            }
+            _ => (),
+        }
+
+        let name = long_name.take().or(pax.path.take()).unwrap_or_else(|| {
+            let name = field(&header[0..100]);
+            let prefix = field(&header[345..500]);
+
+            if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                [prefix, b"/", name].concat()
+            } else {
+                name.to_vec()
+            }
+        });
+
+        let link = long_link
+            .take()
+            .or(pax.linkpath.take())
+            .unwrap_or_else(|| field(&header[157..257]).to_vec());
+
+        let size = pax.size.unwrap_or(header_size);
+
+        let file_type = match typeflag {
+            b'2' => S_IFLNK,
+            b'3' => S_IFCHR,
+            b'4' => S_IFBLK,
+            b'5' => S_IFDIR,
+            b'6' => S_IFIFO,
+            _ if name.ends_with(b"/") => S_IFDIR,
+            _ => S_IFREG,
+        };
+
+        let metadata = Metadata {
+            mode: file_type
+                | (parse_numeric(&header[100..108])? as u32 & 0o7777),
+            size: if file_type == S_IFREG { size } else { 0 },
+            modified: Some(Duration::from_secs(
+                pax.mtime.unwrap_or(parse_numeric(&header[136..148])?),
+            )),
+            uid: pax.uid.unwrap_or(parse_numeric(&header[108..116])? as u32),
+            gid: pax.gid.unwrap_or(parse_numeric(&header[116..124])? as u32),
+            ..Metadata::default()
+        };
+
+        let linked_path = (file_type == S_IFLNK)
+            .then(|| PathBuf::from(OsStr::from_bytes(&link)));
+
+        source.insert(
+            Path::new(OsStr::from_bytes(&name)),
+            metadata,
+            linked_path,
+        );
+
+        // Hard links, devices, dirs, symlinks and fifos carry no data.
+        if !matches!(typeflag, b'1'..=b'6') {
+            skip_data(&mut reader, size)?;
+        }
+
+        pax = PaxOverrides::default();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tar_tests {
+    use super::*;
+
+    use crate::core::source::EntrySource;
+
+    // Builds a ustar header with a valid checksum.
+    fn header(
+        name: &str,
+        typeflag: u8,
+        mode: u32,
+        size: u64,
+        link: &str,
+    ) -> Vec<u8> {
+        let mut header = vec![0_u8; BLOCK_SIZE];
+
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(format!("{mode:07o}").as_bytes());
+        header[108..115].copy_from_slice(b"0001750");
+        header[116..123].copy_from_slice(b"0001750");
+        header[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        header[136..147].copy_from_slice(b"14524770400");
+        header[156] = typeflag;
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+
+        header
+    }
+
+    fn pad(data: &[u8]) -> Vec<u8> {
+        let mut padded = data.to_vec();
+        padded.resize(padded.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        padded
+    }
+
+    #[test]
+    fn reads_members_with_metadata() {
+        let mut archive = vec![];
+        archive.extend(header("pkg/", b'5', 0o755, 0, ""));
+        archive.extend(header("pkg/bin/tool", b'0', 0o755, 5, ""));
+        archive.extend(pad(b"hello"));
+        archive.extend(header("pkg/current", b'2', 0o777, 0, "bin/tool"));
+        archive.extend(vec![0; BLOCK_SIZE * 2]);
+
+        let mut source = VirtualSource::new(PathBuf::from("a.tar"));
+        read_entries(archive.as_slice(), &mut source).unwrap();
+
+        let pkg = source.read_dir(Path::new("a.tar/pkg"), 2).unwrap();
+        let mut names = pkg
+            .iter()
+            .map(|e| e.get_name().unwrap().to_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["bin", "current"]);
+
+        let link = pkg.iter().find(|e| e.is_symlink()).unwrap();
+        assert_eq!(link.linked_path(), Some(&PathBuf::from("bin/tool")));
+
+        let tool = source.read_dir(Path::new("a.tar/pkg/bin"), 3).unwrap();
+        assert_eq!(tool[0].get_size(), 5);
+        assert_eq!(tool[0].get_mode(), S_IFREG | 0o755);
+        assert_eq!(tool[0].get_uid(), 1000);
+        assert_eq!(tool[0].get_last_modified().as_secs(), 1_700_000_000);
+    }
+
+    #[test]
+    fn reads_gnu_long_names_and_pax_paths() {
+        let long = format!("{}/file.txt", "d".repeat(120));
+        let pax_record = "24 path=pax/renamed.txt\n";
+
+        let mut archive = vec![];
+        archive.extend(header(
+            "././@LongLink",
+            b'L',
+            0,
+            long.len() as u64 + 1,
+            "",
+        ));
+        archive.extend(pad(format!("{long}\0").as_bytes()));
+        archive.extend(header("ignored", b'0', 0o644, 0, ""));
+        archive.extend(header(
+            "PaxHeader",
+            b'x',
+            0,
+            pax_record.len() as u64,
+            "",
+        ));
+        archive.extend(pad(pax_record.as_bytes()));
+        archive.extend(header("short.txt", b'0', 0o644, 0, ""));
+        archive.extend(vec![0; BLOCK_SIZE * 2]);
+
+        let mut source = VirtualSource::new(PathBuf::from("a.tar"));
+        read_entries(archive.as_slice(), &mut source).unwrap();
+
+        let long_dir = format!("a.tar/{}", "d".repeat(120));
+        assert_eq!(source.read_dir(Path::new(&long_dir), 2).unwrap().len(), 1);
+        assert_eq!(
+            source.read_dir(Path::new("a.tar/pax"), 2).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_headers() {
+        let mut archive = header("a.txt", b'0', 0o644, 0, "");
+        archive[0] = b'b';
+
+        let mut source = VirtualSource::new(PathBuf::from("a.tar"));
+        assert!(read_entries(archive.as_slice(), &mut source).is_err());
+    }
+
+    #[test]
+    fn parses_base_256_numbers() {
+        assert_eq!(parse_numeric(&[0x80, 0, 0, 0, 1, 0]).unwrap(), 256);
+        assert_eq!(parse_numeric(b"0000644 \0").unwrap(), 0o644);
+    }
+}
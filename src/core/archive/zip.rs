@@ -0,0 +1,432 @@
+use std::ffi::OsStr;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::core::dirent::{Metadata, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use crate::core::source::VirtualSource;
+use crate::core::time::days_from_civil;
+
+const EOCD_SIGNATURE: &[u8] = b"PK\x05\x06";
+const EOCD64_LOCATOR_SIGNATURE: &[u8] = b"PK\x06\x07";
+const EOCD64_SIGNATURE: &[u8] = b"PK\x06\x06";
+const CENTRAL_HEADER_SIGNATURE: &[u8] = b"PK\x01\x02";
+const LOCAL_HEADER_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+const EOCD_SIZE: usize = 22;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const LOCAL_HEADER_SIZE: usize = 30;
+const MAX_COMMENT_SIZE: usize = 0xffff;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const HOST_UNIX: u16 = 3;
+const MAX_LINK_SIZE: u64 = 4096;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+// MS-DOS timestamps have two second resolution and no time zone, so they
+// are read as UTC.
+fn dos_time_to_unix(date: u16, time: u16) -> u64 {
+    let year = i64::from(date >> 9) + 1980;
+    let month = i64::from((date >> 5) & 0xf).max(1);
+    let day = i64::from(date & 0x1f).max(1);
+
+    let secs = i64::from(time >> 11) * 3600
+        + i64::from((time >> 5) & 0x3f) * 60
+        + i64::from(time & 0x1f) * 2;
+
+    u64::try_from(days_from_civil(year, month, day) * 86_400 + secs)
+        .unwrap_or(0)
+}
+
+struct CentralDirectory {
+    offset: u64,
+    size: u64,
+    entries: u64,
+}
+
+fn find_central_directory<R: Read + Seek>(
+    reader: &mut R,
+) -> io::Result<CentralDirectory> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_len = len.min((EOCD_SIZE + MAX_COMMENT_SIZE) as u64);
+
+    let mut tail = vec![0; tail_len as usize];
+    reader.seek(SeekFrom::Start(len - tail_len))?;
+    reader.read_exact(&mut tail)?;
+
+    // The end record is followed by a variable length comment, so search
+    // backwards for its signature.
+    let eocd = (0..=tail.len().saturating_sub(EOCD_SIZE))
+        .rev()
+        .find(|&at| tail[at..].starts_with(EOCD_SIGNATURE))
+        .ok_or_else(|| invalid("zip end of central directory not found"))?;
+
+    let mut directory = CentralDirectory {
+        entries: u64::from(le_u16(&tail, eocd + 10)),
+        size: u64::from(le_u32(&tail, eocd + 12)),
+        offset: u64::from(le_u32(&tail, eocd + 16)),
+    };
+
+    let is_zip64 = directory.entries == 0xffff
+        || directory.size == 0xffff_ffff
+        || directory.offset == 0xffff_ffff;
+
+    if is_zip64
+        && eocd >= 20
+        && tail[eocd - 20..].starts_with(EOCD64_LOCATOR_SIGNATURE)
+    {
+        let eocd64_offset = le_u64(&tail, eocd - 20 + 8);
+
+        let mut eocd64 = [0_u8; 56];
+        reader.seek(SeekFrom::Start(eocd64_offset))?;
+        reader.read_exact(&mut eocd64)?;
+
+        if !eocd64.starts_with(EOCD64_SIGNATURE) {
+            return Err(invalid("invalid zip64 end of central directory"));
+        }
+
+        directory = CentralDirectory {
+            entries: le_u64(&eocd64, 32),
+            size: le_u64(&eocd64, 40),
+            offset: le_u64(&eocd64, 48),
+        };
+    }
+
+    // the sizes come from the archive itself, so check them before
+    // anything is allocated to hold the directory
+    let end = directory.offset.checked_add(directory.size);
+
+    if end.is_none_or(|end| end > len) {
+        return Err(invalid("zip central directory lies outside the archive"));
+    }
+
+    Ok(directory)
+}
+
+// Symlink targets are stored as the member's contents.
+fn read_link_target<R: Read + Seek>(
+    reader: &mut R,
+    local_offset: u64,
+    compressed_size: u64,
+    method: u16,
+) -> Option<PathBuf> {
+    if compressed_size > MAX_LINK_SIZE {
+        return None;
+    }
+
+    let mut local = [0_u8; LOCAL_HEADER_SIZE];
+    reader.seek(SeekFrom::Start(local_offset)).ok()?;
+    reader.read_exact(&mut local).ok()?;
+
+    if !local.starts_with(LOCAL_HEADER_SIGNATURE) {
+        return None;
+    }
+
+    let skip = i64::from(le_u16(&local, 26)) + i64::from(le_u16(&local, 28));
+    reader.seek(SeekFrom::Current(skip)).ok()?;
+
+    let mut data = vec![0; compressed_size as usize];
+    reader.read_exact(&mut data).ok()?;
+
+    let target = match method {
+        METHOD_STORED => data,
+        METHOD_DEFLATED => super::inflate(&data)?,
+        _ => return None,
+    };
+
+    Some(PathBuf::from(OsStr::from_bytes(&target)))
+}
+
+// Reads the central directory, adding each member to `source`.
+pub fn read_entries<R: Read + Seek>(
+    reader: &mut R,
+    source: &mut VirtualSource,
+) -> io::Result<()> {
+    let directory = find_central_directory(reader)?;
+
+    let mut records = vec![0; directory.size as usize];
+    reader.seek(SeekFrom::Start(directory.offset))?;
+    reader.read_exact(&mut records)?;
+
+    let mut at = 0;
+
+    for _ in 0..directory.entries {
+        if records.len() < at + CENTRAL_HEADER_SIZE
+            || !records[at..].starts_with(CENTRAL_HEADER_SIGNATURE)
+        {
+            return Err(invalid("invalid zip central directory entry"));
+        }
+
+        let record = &records[at..];
+
+        let made_by = le_u16(record, 4);
+        let method = le_u16(record, 10);
+        let mut compressed_size = u64::from(le_u32(record, 20));
+        let mut size = u64::from(le_u32(record, 24));
+        let name_len = usize::from(le_u16(record, 28));
+        let extra_len = usize::from(le_u16(record, 30));
+        let comment_len = usize::from(le_u16(record, 32));
+        let external_attrs = le_u32(record, 38);
+        let mut local_offset = u64::from(le_u32(record, 42));
+
+        let record_len =
+            CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+
+        if record.len() < record_len {
+            return Err(invalid("truncated zip central directory entry"));
+        }
+
+        let name = &record[CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_len];
+        let extra = &record[CENTRAL_HEADER_SIZE + name_len
+            ..CENTRAL_HEADER_SIZE + name_len + extra_len];
+
+        let mut modified =
+            dos_time_to_unix(le_u16(record, 14), le_u16(record, 12));
+        let mut uid = 0;
+        let mut gid = 0;
+
+        let mut field_at = 0;
+
+        while field_at + 4 <= extra.len() {
+            let id = le_u16(extra, field_at);
+            let len = usize::from(le_u16(extra, field_at + 2));
+            let data =
+                &extra[field_at + 4..(field_at + 4 + len).min(extra.len())];
+
+            match id {
+                // zip64 sizes, present only for fields that overflowed
+                0x0001 => {
+                    let mut values = data.chunks_exact(8).map(|v| le_u64(v, 0));
+
+                    if size == 0xffff_ffff {
+                        size = values.next().unwrap_or(size);
+                    }
+                    if compressed_size == 0xffff_ffff {
+                        compressed_size =
+                            values.next().unwrap_or(compressed_size);
+                    }
+                    if local_offset == 0xffff_ffff {
+                        local_offset = values.next().unwrap_or(local_offset);
+                    }
+                }
+                // extended timestamp
+                0x5455 if data.len() >= 5 && data[0] & 1 != 0 => {
+                    modified = u64::from(le_u32(data, 1));
+                }
+                // Info-ZIP unix uid/gid
+                0x7875 if data.len() >= 3 => {
+                    let uid_len = usize::from(data[1]);
+                    let read_id = |bytes: &[u8]| {
+                        bytes
+                            .iter()
+                            .rev()
+                            .fold(0_u32, |acc, &b| (acc << 8) | u32::from(b))
+                    };
+
+                    if data.len() >= 3 + uid_len {
+                        uid = read_id(&data[2..2 + uid_len]);
+                        let gid_len = usize::from(data[2 + uid_len]);
+
+                        if data.len() >= 3 + uid_len + gid_len {
+                            gid = read_id(
+                                &data[3 + uid_len..3 + uid_len + gid_len],
+                            );
+                        }
+                    }
+                }
+                _ => (),
+            }
+
+            field_at += 4 + len;
+        }
+
+        let is_dir_name = name.ends_with(b"/");
+        let unix_mode = external_attrs >> 16;
+
+        let mode = if made_by >> 8 == HOST_UNIX && unix_mode & S_IFMT != 0 {
+            unix_mode
+        } else if is_dir_name {
+            S_IFDIR | 0o755
+        } else {
+            S_IFREG | 0o644
+        };
+
+        let linked_path = (mode & S_IFMT == S_IFLNK)
+            .then(|| {
+                read_link_target(reader, local_offset, compressed_size, method)
+            })
+            .flatten();
+
+        let metadata = Metadata {
+            mode,
+            size: if mode & S_IFMT == S_IFREG { size } else { 0 },
+            modified: Some(Duration::from_secs(modified)),
+            uid,
+            gid,
+            ..Metadata::default()
+        };
+
+        source.insert(
+            Path::new(OsStr::from_bytes(name)),
+            metadata,
+            linked_path,
+        );
+
+        at += record_len;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod zip_tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::core::source::EntrySource;
+
+    struct Member<'a> {
+        name: &'a str,
+        mode: u32,
+        data: &'a [u8],
+    }
+
+    // Writes a minimal stored (uncompressed) archive made on unix.
+    fn build_zip(members: &[Member]) -> Vec<u8> {
+        let mut archive = vec![];
+        let mut central = vec![];
+
+        for member in members {
+            let offset = archive.len() as u32;
+            let size = member.data.len() as u32;
+
+            archive.extend(LOCAL_HEADER_SIGNATURE);
+            archive.extend([20, 0, 0, 0, 0, 0]);
+            archive.extend([0, 0, 0x21, 0x58]); // 2024-01-01 00:00:00
+            archive.extend([0, 0, 0, 0]);
+            archive.extend(size.to_le_bytes());
+            archive.extend(size.to_le_bytes());
+            archive.extend((member.name.len() as u16).to_le_bytes());
+            archive.extend([0, 0]);
+            archive.extend(member.name.as_bytes());
+            archive.extend(member.data);
+
+            central.extend(CENTRAL_HEADER_SIGNATURE);
+            central.extend([20, 3, 20, 0, 0, 0, 0, 0]);
+            central.extend([0, 0, 0x21, 0x58]);
+            central.extend([0, 0, 0, 0]);
+            central.extend(size.to_le_bytes());
+            central.extend(size.to_le_bytes());
+            central.extend((member.name.len() as u16).to_le_bytes());
+            central.extend([0, 0, 0, 0, 0, 0, 0, 0]);
+            central.extend((member.mode << 16).to_le_bytes());
+            central.extend(offset.to_le_bytes());
+            central.extend(member.name.as_bytes());
+        }
+
+        let central_offset = archive.len() as u32;
+        archive.extend(&central);
+
+        archive.extend(EOCD_SIGNATURE);
+        archive.extend([0, 0, 0, 0]);
+        archive.extend((members.len() as u16).to_le_bytes());
+        archive.extend((members.len() as u16).to_le_bytes());
+        archive.extend((central.len() as u32).to_le_bytes());
+        archive.extend(central_offset.to_le_bytes());
+        archive.extend([0, 0]);
+
+        archive
+    }
+
+    #[test]
+    fn reads_central_directory() {
+        let archive = build_zip(&[
+            Member {
+                name: "app/",
+                mode: S_IFDIR | 0o755,
+                data: b"",
+            },
+            Member {
+                name: "app/run.sh",
+                mode: S_IFREG | 0o755,
+                data: b"#!/bin/sh\n",
+            },
+            Member {
+                name: "app/latest",
+                mode: S_IFLNK | 0o777,
+                data: b"run.sh",
+            },
+        ]);
+
+        let mut source = VirtualSource::new(PathBuf::from("a.zip"));
+        read_entries(&mut Cursor::new(archive), &mut source).unwrap();
+
+        let mut app = source.read_dir(Path::new("a.zip/app"), 2).unwrap();
+        app.sort_by(|a, b| a.path().cmp(b.path()));
+
+        assert_eq!(app.len(), 2);
+        assert!(app[0].is_symlink());
+        assert_eq!(app[0].linked_path(), Some(&PathBuf::from("run.sh")));
+        assert_eq!(app[1].get_size(), 10);
+        assert_eq!(app[1].get_mode(), S_IFREG | 0o755);
+        assert_eq!(app[1].get_last_modified().as_secs(), 1_704_067_200);
+    }
+
+    #[test]
+    fn rejects_missing_end_record() {
+        let mut source = VirtualSource::new(PathBuf::from("a.zip"));
+        let result =
+            read_entries(&mut Cursor::new(b"PK\x03\x04".to_vec()), &mut source);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_directory_past_the_end() {
+        let mut archive = build_zip(&[Member {
+            name: "a",
+            mode: S_IFREG | 0o644,
+            data: b"",
+        }]);
+
+        // claim a 4 GiB central directory
+        let size_at = archive.len() - 10;
+        archive[size_at..size_at + 4].copy_from_slice(&[0xfe; 4]);
+
+        let mut source = VirtualSource::new(PathBuf::from("a.zip"));
+        let err =
+            read_entries(&mut Cursor::new(archive), &mut source).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "zip central directory lies outside the archive"
+        );
+    }
+
+    #[test]
+    fn converts_dos_times() {
+        assert_eq!(dos_time_to_unix(0x5821, 0), 1_704_067_200);
+        assert_eq!(
+            dos_time_to_unix(0x5821, (13 << 11) | (30 << 5) | 5),
+            1_704_115_810
+        );
+    }
+}
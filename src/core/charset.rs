@@ -0,0 +1,126 @@
+// Line drawing characters for the tree's connectors.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Charset {
+    Utf8,
+    Ascii,
+    // VT100 line graphics (-A), switched in and out with escape sequences
+    Ansi,
+    // IBM code page 437 box drawing bytes (-S)
+    Cp437,
+}
+
+// The pieces Display draws with. Each is written as is, so they're bytes
+// rather than strs to allow for CP437.
+pub struct Connectors {
+    pub vertical: &'static [u8],
+    pub last: &'static [u8],
+    pub tee: &'static [u8],
+    pub horizontal: &'static [u8],
+}
+
+const UTF8: Connectors = Connectors {
+    vertical: "\u{2502}".as_bytes(),
+    last: "\u{2514}".as_bytes(),
+    tee: "\u{251C}".as_bytes(),
+    horizontal: "\u{2500}\u{2500}\u{2500}".as_bytes(),
+};
+
+const ASCII: Connectors = Connectors {
+    vertical: b"|",
+    last: b"`",
+    tee: b"|",
+    horizontal: b"---",
+};
+
+const ANSI: Connectors = Connectors {
+    vertical: b"\x1b(0x\x1b(B",
+    last: b"\x1b(0m\x1b(B",
+    tee: b"\x1b(0t\x1b(B",
+    horizontal: b"\x1b(0qqq\x1b(B",
+};
+
+const CP437: Connectors = Connectors {
+    vertical: b"\xb3",
+    last: b"\xc0",
+    tee: b"\xc3",
+    horizontal: b"\xc4\xc4\xc4",
+};
+
+impl Charset {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Some(Charset::Utf8),
+            "ascii" | "us-ascii" => Some(Charset::Ascii),
+            "ansi" => Some(Charset::Ansi),
+            "cp437" | "ibm437" => Some(Charset::Cp437),
+            _ => None,
+        }
+    }
+
+    // Follows the usual LC_ALL > LC_CTYPE > LANG precedence. A locale
+    // that names anything other than UTF-8 (including "C") gets ASCII;
+    // with no locale set at all we keep drawing UTF-8.
+    pub fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty());
+
+        Self::for_locale(locale.as_deref())
+    }
+
+    fn for_locale(locale: Option<&str>) -> Self {
+        match locale {
+            None => Charset::Utf8,
+            Some(locale) => {
+                let codeset = locale
+                    .split_once('.')
+                    .map_or("", |(_, codeset)| codeset)
+                    .split('@')
+                    .next()
+                    .unwrap_or("")
+                    .to_ascii_lowercase();
+
+                if codeset == "utf-8" || codeset == "utf8" {
+                    Charset::Utf8
+                } else {
+                    Charset::Ascii
+                }
+            }
+        }
+    }
+
+    pub fn connectors(&self) -> &'static Connectors {
+        match self {
+            Charset::Utf8 => &UTF8,
+            Charset::Ascii => &ASCII,
+            Charset::Ansi => &ANSI,
+            Charset::Cp437 => &CP437,
+        }
+    }
+}
+
+#[cfg(test)]
+mod charset_tests {
+    use super::*;
+
+    #[test]
+    fn parses_charset_names() {
+        assert_eq!(Charset::parse("UTF-8"), Some(Charset::Utf8));
+        assert_eq!(Charset::parse("ascii"), Some(Charset::Ascii));
+        assert_eq!(Charset::parse("IBM437"), Some(Charset::Cp437));
+        assert_eq!(Charset::parse("ebcdic"), None);
+    }
+
+    #[test]
+    fn picks_charset_from_locale() {
+        assert_eq!(Charset::for_locale(None), Charset::Utf8);
+        assert_eq!(Charset::for_locale(Some("en_US.UTF-8")), Charset::Utf8);
+        assert_eq!(Charset::for_locale(Some("de_DE.utf8@euro")), Charset::Utf8);
+        assert_eq!(Charset::for_locale(Some("C")), Charset::Ascii);
+        assert_eq!(
+            Charset::for_locale(Some("en_US.ISO-8859-1")),
+            Charset::Ascii
+        );
+    }
+}
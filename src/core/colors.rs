@@ -1,64 +1,298 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-use once_cell::sync::OnceCell;
+use super::file_type::FileType;
 
 enum ColorFormats {
     LsColors(String),
     LsColorsDelimited(String),
-    Undefined,
 }
 
 const ANSI_COLOR_RESET: &str = "\x1B[0m";
-static DEFAULT_COLOR: (String, &'static str) = (String::new(), "");
 
-static COLORS: OnceCell<Option<HashMap<&'static str, (String, &'static str)>>> =
-    OnceCell::new();
+// Used when neither LS_COLORS nor LSCOLORS is set. These are the
+// `dircolors --print-database` defaults for types, plus the common
+// archive, image and audio extensions.
+const DEFAULT_LS_COLORS: &str = "rs=0:di=01;34:ln=01;36:pi=40;33:\
+    so=01;35:bd=40;33;01:cd=40;33;01:or=40;31;01:mi=00:su=37;41:sg=30;43:\
+    tw=30;42:ow=34;42:ex=01;32:\
+    *.tar=01;31:*.tgz=01;31:*.gz=01;31:*.bz2=01;31:*.xz=01;31:\
+    *.zst=01;31:*.zip=01;31:*.7z=01;31:*.rar=01;31:*.deb=01;31:\
+    *.rpm=01;31:*.jar=01;31:\
+    *.jpg=01;35:*.jpeg=01;35:*.png=01;35:*.gif=01;35:*.bmp=01;35:\
+    *.svg=01;35:*.webp=01;35:*.mp4=01;35:*.mkv=01;35:*.webm=01;35:\
+    *.mp3=00;36:*.flac=00;36:*.ogg=00;36:*.wav=00;36";
+
+// --color: `auto` colors only when writing to a terminal.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "auto" | "tty" | "if-tty" => Some(ColorMode::Auto),
+            "always" | "yes" | "force" => Some(ColorMode::Always),
+            "never" | "no" | "none" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+
+    // An explicit --color/-C/-n wins. In auto mode any non-empty NO_COLOR,
+    // even "0", turns colors off, and a CLICOLOR_FORCE other than "0"
+    // turns them on even when piped
+    // (https://no-color.org, https://bixense.com/clicolors).
+    pub fn is_enabled(&self, is_terminal: bool) -> bool {
+        let value =
+            |var: &str| std::env::var_os(var).filter(|value| !value.is_empty());
+
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto if value("NO_COLOR").is_some() => false,
+            ColorMode::Auto => {
+                is_terminal
+                    || value("CLICOLOR_FORCE").is_some_and(|force| force != "0")
+            }
+        }
+    }
+}
+
+// LS_COLORS keys and the names entries are looked up by.
+const LS_COLORS_KEYS: [(&str, &str); 16] = [
+    ("di", "directory"),
+    ("ln", "sym_link"),
+    ("so", "socket"),
+    ("pi", "pipe"),
+    ("ex", "executable"),
+    ("bd", "special_block"),
+    ("cd", "special_char"),
+    ("su", "exe_set_uid"),
+    ("sg", "exe_set_gid"),
+    ("tw", "dwo_sticky"),
+    ("ow", "dwo_non_sticky"),
+    ("no", "normal"),
+    ("fi", "file"),
+    ("mi", "missing"),
+    ("or", "orphan"),
+    ("mh", "multi_hard_link"),
+];
+
+// Colors for the metadata columns and the report: (key, name, default
+// SGR). The keys can be set alongside the LS_COLORS ones and don't clash
+// with any of them.
+const THEME_KEYS: [(&str, &str, &str); 22] = [
+    ("pt", "perm_type", "01;34"),
+    ("pr", "perm_read", "33"),
+    ("pw", "perm_write", "31"),
+    ("px", "perm_exec", "32"),
+    ("ps", "perm_special", "35"),
+    ("pn", "perm_none", "90"),
+    ("nb", "size_bytes", "32"),
+    ("nk", "size_kilo", "01;32"),
+    ("nm", "size_mega", "33"),
+    ("ng", "size_giga", "01;33"),
+    ("dr", "date_recent", "01;34"),
+    ("dd", "date_day", "34"),
+    ("dw", "date_week", "36"),
+    ("dx", "date_old", "90"),
+    ("uu", "owner_you", "33"),
+    ("un", "owner_other", "01;31"),
+    ("in", "inode", "35"),
+    ("dv", "device", "36"),
+    ("rp", "report_count", "01"),
+    ("ga", "diff_added", "32"),
+    ("gd", "diff_removed", "31"),
+    ("gm", "diff_modified", "33"),
+];
+
+// Escape sequences by entry type, plus `*.ext` style suffix entries
+// matched against the entry's name, plus the metadata theme.
+#[derive(Debug, PartialEq)]
+pub struct ColorMap {
+    types: HashMap<&'static str, String>,
+    suffixes: Vec<(String, String)>,
+    theme: HashMap<&'static str, String>,
+    reset: Option<String>,
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self {
+            types: HashMap::new(),
+            suffixes: vec![],
+            theme: THEME_KEYS
+                .iter()
+                .map(|(_, name, sgr)| (*name, format!("\x1B[{sgr}m")))
+                .collect(),
+            reset: None,
+        }
+    }
+}
+
+impl ColorMap {
+    // The longest matching suffix wins, so `*.tar.gz` beats `*.gz`.
+    // Matching ignores ASCII case, as GNU ls does.
+    fn suffix_color(&self, name: &str) -> Option<&String> {
+        let name = name.to_ascii_lowercase();
+
+        self.suffixes
+            .iter()
+            .filter(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .max_by_key(|(suffix, _)| suffix.len())
+            .map(|(_, esc_seq)| esc_seq)
+    }
+
+    // Most specific first: the type's own key (except plain files), then
+    // for regular files the name's suffix and `fi`, and finally `no`.
+    fn lookup(&self, file_type: FileType, name: &str) -> Option<&String> {
+        let own_color = match file_type {
+            FileType::File => None,
+            _ => self.types.get(file_type.color_key()),
+        };
+
+        own_color
+            .or_else(|| match file_type {
+                FileType::Orphan => self.types.get("sym_link"),
+                _ if file_type.is_regular() => {
+                    self.suffix_color(name).or_else(|| self.types.get("file"))
+                }
+                _ => None,
+            })
+            .or_else(|| self.types.get("normal"))
+    }
+
+    fn reset(&self) -> &str {
+        self.reset.as_deref().unwrap_or(ANSI_COLOR_RESET)
+    }
+}
+
+// SGR parameters are digits separated by ';' (e.g. "01;38;5;208").
+// Anything else is rejected rather than passed to the terminal.
+fn to_esc_seq(sgr: &str) -> Option<String> {
+    let is_valid = !sgr.is_empty()
+        && sgr
+            .split(';')
+            .all(|param| param.chars().all(|ch| ch.is_ascii_digit()));
+
+    is_valid.then(|| format!("\x1B[{sgr}m"))
+}
+
+// Checks a config file theme entry: a color name from LS_COLORS_KEYS or
+// THEME_KEYS, or a `*.ext` glob, set to an SGR sequence.
+pub fn check_theme_entry(name: &str, sgr: &str) -> Result<(), String> {
+    let is_known = name.starts_with('*')
+        || LS_COLORS_KEYS.iter().any(|(_, entity)| *entity == name)
+        || THEME_KEYS
+            .iter()
+            .any(|(_, theme_name, _)| *theme_name == name);
+
+    if !is_known {
+        return Err(String::from("unknown color name"));
+    }
+
+    match to_esc_seq(sgr) {
+        Some(_) => Ok(()),
+        None => Err(format!("invalid SGR sequence '{sgr}'")),
+    }
+}
 
+// The color context handed to Display. `Colors::default()` renders
+// without colors.
 #[derive(Debug, Default)]
-pub struct Colors {}
+pub struct Colors {
+    color_map: Option<ColorMap>,
+}
 
 impl Colors {
-    pub fn from_ls_colors(with_colors: bool) {
-        if with_colors {
-            let color_fmt = Self::get_color_var();
-            let colors = Self::create_color_map(color_fmt);
-
-            COLORS
-                .set(colors)
-                .expect("Failed to set global colors value")
+    pub fn new(mode: ColorMode, is_terminal: bool) -> Self {
+        if mode.is_enabled(is_terminal) {
+            Self::from_ls_colors()
+        } else {
+            Self::default()
+        }
+    }
+
+    // Layers config file colors over the environment's. Entries are
+    // expected to have passed `check_theme_entry`.
+    pub fn with_theme(mut self, theme: &[(String, String)]) -> Self {
+        let Some(color_map) = self.color_map.as_mut() else {
+            return self;
+        };
+
+        for (name, sgr) in theme {
+            let Some(esc_seq) = to_esc_seq(sgr) else {
+                continue;
+            };
+
+            if let Some(suffix) = name.strip_prefix('*') {
+                // later suffixes win ties in `suffix_color`
+                color_map
+                    .suffixes
+                    .push((suffix.to_ascii_lowercase(), esc_seq));
+            } else if let Some((_, entity)) =
+                LS_COLORS_KEYS.iter().find(|(_, entity)| entity == name)
+            {
+                color_map.types.insert(entity, esc_seq);
+            } else if let Some((_, theme_name, _)) = THEME_KEYS
+                .iter()
+                .find(|(_, theme_name, _)| theme_name == name)
+            {
+                color_map.theme.insert(theme_name, esc_seq);
+            }
+        }
+
+        self
+    }
+
+    pub fn from_ls_colors() -> Self {
+        Self {
+            color_map: Some(Self::create_color_map(Self::get_color_var())),
         }
     }
 
+    // TREE_COLORS takes LS_COLORS syntax and wins over both ls variables,
+    // as in GNU tree.
     fn get_color_var() -> ColorFormats {
+        if let Ok(color_fmt) = std::env::var("TREE_COLORS") {
+            return ColorFormats::LsColorsDelimited(color_fmt);
+        }
+
         match (std::env::var("LSCOLORS"), std::env::var("LS_COLORS")) {
             (Ok(color_fmt), _) => ColorFormats::LsColors(color_fmt),
             (_, Ok(color_fmt)) => ColorFormats::LsColorsDelimited(color_fmt),
-            _ => ColorFormats::Undefined,
+            _ => {
+                ColorFormats::LsColorsDelimited(String::from(DEFAULT_LS_COLORS))
+            }
         }
     }
 
-    // returns (fg, bg) color code tuples - ex. ("31", "103")
+    // returns (fg, bg) color code tuples - ex. ("31", "103"), or None for
+    // a pair that isn't two color letters so the rest keep their place
     fn map_chars_to_ansi_color_code(
         color_var: &str,
-    ) -> Vec<(String, &'static str)> {
+    ) -> Vec<Option<(String, &'static str)>> {
         // Map of letter to tuple of ANSI color codes - (foreground, background)
         let mapped_ls_colors = HashMap::from([
-            ('a', ("30", "40")),   // black
-            ('b', ("31", "41")),   // red
-            ('c', ("32", "42")),   // green
-            ('d', ("33", "43")),   // yellow
-            ('e', ("34", "44")),   // blue
-            ('f', ("35", "45")),   // magenta
-            ('g', ("36", "46")),   // cyan
-            ('h', ("37", "47")),   // white
-            ('A', ("90", "100")),  // bright black
-            ('B', ("91", "101")),  // bright red
-            ('C', ("92", "102)")), // bright green
-            ('D', ("93", "103")),  // bright yellow
-            ('E', ("94", "104)")), // bright blue
-            ('F', ("95", "105")),  // bright magenta
-            ('G', ("96", "106")),  // bright cyan
-            ('H', ("97", "107")),  // bright white
+            ('a', ("30", "40")),  // black
+            ('b', ("31", "41")),  // red
+            ('c', ("32", "42")),  // green
+            ('d', ("33", "43")),  // yellow
+            ('e', ("34", "44")),  // blue
+            ('f', ("35", "45")),  // magenta
+            ('g', ("36", "46")),  // cyan
+            ('h', ("37", "47")),  // white
+            ('A', ("90", "100")), // bright black
+            ('B', ("91", "101")), // bright red
+            ('C', ("92", "102")), // bright green
+            ('D', ("93", "103")), // bright yellow
+            ('E', ("94", "104")), // bright blue
+            ('F', ("95", "105")), // bright magenta
+            ('G', ("96", "106")), // bright cyan
+            ('H', ("97", "107")), // bright white
             ('x', ("", "")),
         ]);
 
@@ -67,123 +301,68 @@ impl Colors {
             .collect::<Vec<char>>()
             .chunks(2)
             .map(|chunk| {
-                let (fg_color, _) = mapped_ls_colors.get(&chunk[0]).unwrap();
-                let (_, bg_color) = mapped_ls_colors.get(&chunk[1]).unwrap();
+                let [fg, bg] = chunk else { return None };
+                let (fg_color, _) = mapped_ls_colors.get(fg)?;
+                let (_, bg_color) = mapped_ls_colors.get(bg)?;
 
-                Colors::map_color_to_esc_seq(fg_color, bg_color)
+                Some(Colors::map_color_to_esc_seq(fg_color, bg_color))
             })
             .collect()
     }
 
-    fn create_color_map(
-        color_fmt: ColorFormats,
-    ) -> Option<HashMap<&'static str, (String, &'static str)>> {
-        let ls_colors_indexed_values = [
-            "directory",
-            "sym_link",
-            "socket",
-            "pipe",
-            "executable",
-            "special_block",
-            "special_char",
-            "exe_set_uid",
-            "exe_set_gid",
-            "dwo_sticky",
-            "dwo_non_sticky",
-        ];
-
-        let ls_color_values = [
-            "di", "ln", "so", "pi", "ex", "bd", "cd", "su", "sg", "tw", "ow",
-        ];
-
-        let colors = match color_fmt {
-            ColorFormats::LsColors(color_var) => {
-                let mapped_color_tuples =
-                    Self::map_chars_to_ansi_color_code(&color_var);
-                Some(
-                    ls_colors_indexed_values
-                        .into_iter()
-                        .zip(mapped_color_tuples.into_iter())
-                        .collect(),
-                )
-            }
+    fn create_color_map(color_fmt: ColorFormats) -> ColorMap {
+        match color_fmt {
+            // BSD LSCOLORS only has the first eleven types, in order
+            ColorFormats::LsColors(color_var) => ColorMap {
+                types: LS_COLORS_KEYS
+                    .iter()
+                    .map(|(_, entity)| *entity)
+                    .zip(
+                        Self::map_chars_to_ansi_color_code(&color_var)
+                            .into_iter()
+                            .map(|pair| pair.map(|(esc_seq, _)| esc_seq)),
+                    )
+                    .filter_map(|(entity, esc_seq)| Some((entity, esc_seq?)))
+                    .filter(|(_, esc_seq)| !esc_seq.is_empty())
+                    .collect(),
+                ..ColorMap::default()
+            },
             ColorFormats::LsColorsDelimited(color_var) => {
-                let resource_set = HashSet::from(ls_color_values);
-
-                let resource_map = ls_color_values
-                    .into_iter()
-                    .zip(ls_colors_indexed_values.into_iter())
-                    .collect::<HashMap<&str, &str>>();
-
-                Some(
-                    color_var
-                        .split(':')
-                        .filter_map(|color| {
-                            if let [resource, color_config] =
-                                color.split('=').collect::<Vec<_>>()[0..=1]
-                            {
-                                if resource_set.contains(resource) {
-                                    let resource_colors = color_config
-                                        .split(';')
-                                        .filter(|cfg_val| {
-                                            let parsed =
-                                                cfg_val.parse::<u8>().expect(
-                                                    "failed to parse value",
-                                                ); // we only care about ansi color codes
-                                            (30..=47).contains(&parsed) // standard fg colors
-                                                || (90..=107).contains(&parsed) // standard bg colors
-                                        })
-                                        .collect::<Vec<&str>>();
-
-                                    if resource_colors.len() == 1 {
-                                        let fg =
-                                            *resource_colors.first().unwrap();
-                                        Some((
-                                            *resource_map.get(resource).expect(
-                                                "Failed to get resource colors",
-                                            ),
-                                            Colors::map_color_to_esc_seq(
-                                                fg, "",
-                                            ),
-                                        ))
-                                    } else {
-                                        let fg =
-                                            *resource_colors.first().expect(
-                                                "Failed to get first colors",
-                                            );
-
-                                        let bg =
-                                            *resource_colors.get(1).expect(
-                                                "Failed to get last colors",
-                                            );
-
-                                        Some((
-                                            *resource_map.get(resource).expect(
-                                                "Failed to get resource color",
-                                            ),
-                                            Colors::map_color_to_esc_seq(
-                                                fg, bg,
-                                            ),
-                                        ))
-                                    }
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<HashMap<&str, (String, &'static str)>>(),
-                )
+                let mut color_map = ColorMap::default();
+
+                for (key, sgr) in color_var
+                    .split(':')
+                    .filter_map(|entry| entry.split_once('='))
+                {
+                    let Some(esc_seq) = to_esc_seq(sgr) else {
+                        continue;
+                    };
+
+                    if key == "rs" {
+                        color_map.reset = Some(esc_seq);
+                    } else if let Some(suffix) = key.strip_prefix('*') {
+                        color_map
+                            .suffixes
+                            .push((suffix.to_ascii_lowercase(), esc_seq));
+                    } else if let Some((_, entity)) =
+                        LS_COLORS_KEYS.iter().find(|(ls_key, _)| *ls_key == key)
+                    {
+                        color_map.types.insert(entity, esc_seq);
+                    } else if let Some((_, name, _)) = THEME_KEYS
+                        .iter()
+                        .find(|(theme_key, _, _)| *theme_key == key)
+                    {
+                        color_map.theme.insert(name, esc_seq);
+                    }
+                }
+
+                color_map
             }
-            ColorFormats::Undefined => None,
-        };
-
-        colors
+        }
     }
 
     pub fn map_color_to_esc_seq(fg: &str, bg: &str) -> (String, &'static str) {
-        let all_parts = [&*fg, ";", &*bg, "m"];
+        let all_parts = [fg, ";", bg, "m"];
 
         let fg_bg = all_parts.iter().fold(String::new(), |mut acc, &val| {
             match val {
@@ -196,25 +375,127 @@ impl Colors {
         });
 
         if fg_bg.is_empty() {
-            return (fg_bg, "");
+            (fg_bg, "")
         } else {
-            return (format!("\x1B[{fg_bg}"), ANSI_COLOR_RESET);
+            (format!("\x1B[{fg_bg}"), ANSI_COLOR_RESET)
         }
     }
 
-    pub fn get_color_esc_seq(entity: &str) -> &(String, &'static str) {
-        if let Some(Some(color_map)) = COLORS.get() {
-            color_map.get(entity).unwrap_or(&DEFAULT_COLOR)
-        } else {
-            &DEFAULT_COLOR
+    // Returns the (color, reset) sequences for an entry of `file_type`
+    // called `name`, or empty strings when colors are off.
+    pub fn get_color_esc_seq(
+        &self,
+        file_type: FileType,
+        name: &str,
+    ) -> (&str, &str) {
+        let Some(color_map) = &self.color_map else {
+            return ("", "");
+        };
+
+        color_map
+            .lookup(file_type, name)
+            .map_or(("", ""), |esc_seq| (esc_seq.as_str(), color_map.reset()))
+    }
+
+    // The `mi` color for the target of a broken symlink.
+    pub fn get_missing_esc_seq(&self) -> (&str, &str) {
+        let Some(color_map) = &self.color_map else {
+            return ("", "");
+        };
+
+        color_map
+            .types
+            .get("missing")
+            .or_else(|| color_map.types.get("normal"))
+            .map_or(("", ""), |esc_seq| (esc_seq.as_str(), color_map.reset()))
+    }
+
+    // Wraps `text` in the theme color called `name`. Without colors the
+    // text comes back as is.
+    pub fn paint(&self, name: &str, text: &str) -> String {
+        match self
+            .color_map
+            .as_ref()
+            .and_then(|color_map| Some((color_map.theme.get(name)?, color_map)))
+        {
+            Some((esc_seq, color_map)) => {
+                format!("{esc_seq}{text}{}", color_map.reset())
+            }
+            None => text.to_owned(),
         }
     }
+
+    // Colors each letter of an `ls -l` permission string.
+    pub fn paint_permissions(&self, permissions: &str) -> String {
+        if self.color_map.is_none() {
+            return permissions.to_owned();
+        }
+
+        permissions
+            .chars()
+            .enumerate()
+            .map(|(idx, ch)| {
+                let name = match ch {
+                    '-' => "perm_none",
+                    _ if idx == 0 => "perm_type",
+                    'r' => "perm_read",
+                    'w' => "perm_write",
+                    'x' => "perm_exec",
+                    _ => "perm_special",
+                };
+
+                self.paint(name, ch.encode_utf8(&mut [0; 4]))
+            })
+            .collect()
+    }
+
+    // Sizes get brighter as they grow.
+    pub fn paint_size(&self, bytes: u64, text: &str) -> String {
+        let name = match bytes {
+            0..=999 => "size_bytes",
+            1_000..=999_999 => "size_kilo",
+            1_000_000..=999_999_999 => "size_mega",
+            _ => "size_giga",
+        };
+
+        self.paint(name, text)
+    }
+
+    // Recent modification times are bright, old ones dim.
+    pub fn paint_age(&self, age_secs: u64, text: &str) -> String {
+        let name = match age_secs {
+            0..=3_599 => "date_recent",
+            3_600..=86_399 => "date_day",
+            86_400..=604_799 => "date_week",
+            _ => "date_old",
+        };
+
+        self.paint(name, text)
+    }
+
+    // Owners and groups other than our own stand out.
+    pub fn paint_owner(&self, is_current_user: bool, text: &str) -> String {
+        let name = if is_current_user {
+            "owner_you"
+        } else {
+            "owner_other"
+        };
+
+        self.paint(name, text)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn types(map: &ColorMap) -> HashMap<&'static str, &str> {
+        map.types
+            .iter()
+            .map(|(entity, esc_seq)| (*entity, esc_seq.as_str()))
+            .collect()
+    }
+
     #[test]
     fn ls_colors() {
         let mock_lscolors =
@@ -223,41 +504,32 @@ mod test {
         let result = Colors::create_color_map(mock_lscolors);
 
         assert_eq!(
-            result,
-            Some(HashMap::from([
-                ("directory", (String::from("\x1B[32;m"), ANSI_COLOR_RESET)),
-                ("sym_link", (String::from("\x1B[35;m"), ANSI_COLOR_RESET)),
-                ("socket", (String::from("\x1B[32;m"), ANSI_COLOR_RESET)),
-                ("pipe", (String::from("\x1B[33;m"), ANSI_COLOR_RESET)),
-                ("executable", (String::from("\x1B[31;m"), ANSI_COLOR_RESET)),
-                (
-                    "special_block",
-                    (String::from("\x1B[34;46m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "special_char",
-                    (String::from("\x1B[34;43m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "exe_set_uid",
-                    (String::from("\x1B[30;41m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "exe_set_gid",
-                    (String::from("\x1B[30;46m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "dwo_sticky",
-                    (String::from("\x1B[30;42m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "dwo_non_sticky",
-                    (String::from("\x1B[30;43m"), ANSI_COLOR_RESET)
-                )
-            ]))
+            types(&result),
+            HashMap::from([
+                ("directory", "\x1B[32;m"),
+                ("sym_link", "\x1B[35;m"),
+                ("socket", "\x1B[32;m"),
+                ("pipe", "\x1B[33;m"),
+                ("executable", "\x1B[31;m"),
+                ("special_block", "\x1B[34;46m"),
+                ("special_char", "\x1B[34;43m"),
+                ("exe_set_uid", "\x1B[30;41m"),
+                ("exe_set_gid", "\x1B[30;46m"),
+                ("dwo_sticky", "\x1B[30;42m"),
+                ("dwo_non_sticky", "\x1B[30;43m"),
+            ])
         )
     }
 
+    #[test]
+    fn ls_colors_skips_bad_pairs() {
+        let result = Colors::create_color_map(ColorFormats::LsColors(
+            String::from("c?zxCEd"),
+        ));
+
+        assert_eq!(types(&result), HashMap::from([("socket", "\x1B[92;104m")]))
+    }
+
     #[test]
     fn ls_colors_delimited() {
         let mock_ls_colors = ColorFormats::LsColorsDelimited(String::from("di=01;31:ln=01;32:so=01;32:pi=01;101:ex=01;35:bd=01;105:cd=40:su=01;35:sg=01;35:ow=01;35:tw=01;35;101"));
@@ -265,39 +537,146 @@ mod test {
         let result = Colors::create_color_map(mock_ls_colors);
 
         assert_eq!(
-            result,
-            Some(HashMap::from([
-                ("directory", (String::from("\x1B[31;m"), ANSI_COLOR_RESET)),
-                ("sym_link", (String::from("\x1B[32;m"), ANSI_COLOR_RESET)),
-                ("socket", (String::from("\x1B[32;m"), ANSI_COLOR_RESET)),
-                ("pipe", (String::from("\x1B[101;m"), ANSI_COLOR_RESET)),
-                ("executable", (String::from("\x1B[35;m"), ANSI_COLOR_RESET)),
-                (
-                    "special_block",
-                    (String::from("\x1B[105;m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "special_char",
-                    (String::from("\x1B[40;m"), ANSI_COLOR_RESET)
-                ),
-                ("exe_set_uid", (String::from("\x1B[35;m"), ANSI_COLOR_RESET)),
-                ("exe_set_gid", (String::from("\x1B[35;m"), ANSI_COLOR_RESET)),
-                (
-                    "dwo_sticky",
-                    (String::from("\x1B[35;101m"), ANSI_COLOR_RESET)
-                ),
-                (
-                    "dwo_non_sticky",
-                    (String::from("\x1B[35;m"), ANSI_COLOR_RESET)
-                )
-            ]))
+            types(&result),
+            HashMap::from([
+                ("directory", "\x1B[01;31m"),
+                ("sym_link", "\x1B[01;32m"),
+                ("socket", "\x1B[01;32m"),
+                ("pipe", "\x1B[01;101m"),
+                ("executable", "\x1B[01;35m"),
+                ("special_block", "\x1B[01;105m"),
+                ("special_char", "\x1B[40m"),
+                ("exe_set_uid", "\x1B[01;35m"),
+                ("exe_set_gid", "\x1B[01;35m"),
+                ("dwo_sticky", "\x1B[01;35;101m"),
+                ("dwo_non_sticky", "\x1B[01;35m"),
+            ])
         )
     }
 
     #[test]
-    fn ls_colors_undefined() {
-        let result = Colors::create_color_map(ColorFormats::Undefined);
+    fn ls_colors_extended_keys_and_sgr() {
+        let mock_ls_colors = ColorFormats::LsColorsDelimited(String::from(
+            "rs=0:no=00:fi=38;5;250:or=01;31:mh=44:*.rs=38;2;222;165;132:\
+             *.gz=01;31:*.tar.gz=04;33:di=bogus:*.md=1;x;4:zz=01",
+        ));
+
+        let result = Colors::create_color_map(mock_ls_colors);
 
-        assert_eq!(result, None)
+        assert_eq!(result.reset(), "\x1B[0m");
+        assert_eq!(
+            result.lookup(FileType::File, "main.rs").unwrap(),
+            "\x1B[38;2;222;165;132m"
+        );
+        assert_eq!(
+            result.lookup(FileType::File, "a.TAR.GZ").unwrap(),
+            "\x1B[04;33m"
+        );
+        assert_eq!(
+            result.lookup(FileType::File, "a.gz").unwrap(),
+            "\x1B[01;31m"
+        );
+        assert_eq!(
+            result.lookup(FileType::File, "notes.md").unwrap(),
+            "\x1B[38;5;250m"
+        );
+        assert_eq!(
+            result.lookup(FileType::Orphan, "x").unwrap(),
+            "\x1B[01;31m"
+        );
+        assert_eq!(
+            result.lookup(FileType::MultiHardLink, "x").unwrap(),
+            "\x1B[44m"
+        );
+
+        // invalid entries are skipped, so directories fall back to `no`
+        assert_eq!(
+            result.lookup(FileType::Directory, "src").unwrap(),
+            "\x1B[00m"
+        );
+    }
+
+    #[test]
+    fn default_ls_colors_parse() {
+        let result = Colors::create_color_map(ColorFormats::LsColorsDelimited(
+            String::from(DEFAULT_LS_COLORS),
+        ));
+
+        assert_eq!(
+            result.lookup(FileType::Directory, "src").unwrap(),
+            "\x1B[01;34m"
+        );
+        assert_eq!(
+            result.lookup(FileType::File, "a.tar.gz").unwrap(),
+            "\x1B[01;31m"
+        );
+        assert_eq!(result.lookup(FileType::File, "main.rs"), None);
+        assert_eq!(
+            result.lookup(FileType::MultiHardLink, "a.tar").unwrap(),
+            "\x1B[01;31m"
+        );
+    }
+
+    #[test]
+    fn paints_metadata_from_theme() {
+        let colors = Colors {
+            color_map: Some(Colors::create_color_map(
+                ColorFormats::LsColorsDelimited(String::from("pr=01;33:nk=35")),
+            )),
+        };
+
+        assert_eq!(
+            colors.paint_permissions("-r-x"),
+            "\x1B[90m-\x1B[0m\x1B[01;33mr\x1B[0m\x1B[90m-\x1B[0m\x1B[32mx\x1B[0m"
+        );
+        assert_eq!(colors.paint_size(2048, "2 K"), "\x1B[35m2 K\x1B[0m");
+        assert_eq!(colors.paint_age(60, "now"), "\x1B[01;34mnow\x1B[0m");
+        assert_eq!(colors.paint_owner(false, "0"), "\x1B[01;31m0\x1B[0m");
+
+        let no_colors = Colors::default();
+
+        assert_eq!(no_colors.paint_permissions("drwx"), "drwx");
+        assert_eq!(no_colors.paint_size(1, "1 B"), "1 B");
+    }
+
+    #[test]
+    fn layers_theme_over_ls_colors() {
+        let colors = Colors {
+            color_map: Some(Colors::create_color_map(
+                ColorFormats::LsColorsDelimited(String::from(
+                    "di=01;34:*.rs=32",
+                )),
+            )),
+        }
+        .with_theme(&[
+            (String::from("directory"), String::from("01;35")),
+            (String::from("*.RS"), String::from("33")),
+            (String::from("report_count"), String::from("04")),
+        ]);
+
+        assert_eq!(
+            colors.get_color_esc_seq(FileType::Directory, "src").0,
+            "\x1B[01;35m"
+        );
+        assert_eq!(
+            colors.get_color_esc_seq(FileType::File, "main.rs").0,
+            "\x1B[33m"
+        );
+        assert_eq!(colors.paint("report_count", "3"), "\x1B[04m3\x1B[0m");
+
+        assert_eq!(check_theme_entry("sym_link", "01;36"), Ok(()));
+        assert!(check_theme_entry("symlink", "01;36").is_err());
+        assert!(check_theme_entry("*.md", "1;x").is_err());
+    }
+
+    #[test]
+    fn parses_color_modes() {
+        assert_eq!(ColorMode::parse("always"), Some(ColorMode::Always));
+        assert_eq!(ColorMode::parse("never"), Some(ColorMode::Never));
+        assert_eq!(ColorMode::parse("auto"), Some(ColorMode::Auto));
+        assert_eq!(ColorMode::parse("sometimes"), None);
+
+        assert!(ColorMode::Always.is_enabled(false));
+        assert!(!ColorMode::Never.is_enabled(true));
     }
 }
@@ -0,0 +1,638 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::charset::{Charset, Connectors};
+use super::colors::Colors;
+use super::dirent::{S_IFLNK, S_IFMT, S_IFREG};
+use super::escape::NameEscaper;
+use super::file_type::FileType;
+use super::snapshot::{Snapshot, SnapshotEntry};
+
+use crate::cli::Cmd;
+
+const DEFAULT_INDENT: &str = "    ";
+
+// How many entries --compare found on each side.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CompareCounts {
+    pub same: usize,
+    pub differ: usize,
+    pub left_only: usize,
+    pub right_only: usize,
+}
+
+impl CompareCounts {
+    pub fn has_differences(&self) -> bool {
+        self.differ + self.left_only + self.right_only != 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Status {
+    Same,
+    Differs,
+    LeftOnly,
+    RightOnly,
+}
+
+impl Status {
+    // The column between the two trees, as sdiff draws it.
+    fn gutter(&self) -> &'static str {
+        match self {
+            Status::Same => " ",
+            Status::Differs => "|",
+            Status::LeftOnly => "<",
+            Status::RightOnly => ">",
+        }
+    }
+
+    fn color(&self) -> Option<&'static str> {
+        match self {
+            Status::Same => None,
+            Status::Differs => Some("diff_modified"),
+            Status::LeftOnly => Some("diff_removed"),
+            Status::RightOnly => Some("diff_added"),
+        }
+    }
+}
+
+// FNV-1a over the file's bytes. It only has to tell two copies of a file
+// apart, not resist anyone trying to make them collide.
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut buf = [0; 64 * 1024];
+
+    loop {
+        let len = match file.read(&mut buf) {
+            Ok(0) => return Ok(hash),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        for byte in &buf[..len] {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+// One walked tree, children listed per directory in walk order so the
+// tree's own sorting carries over.
+struct Side<'a> {
+    root: &'a Path,
+    children: HashMap<&'a Path, Vec<&'a SnapshotEntry>>,
+}
+
+impl<'a> Side<'a> {
+    fn new(snapshot: &'a Snapshot) -> Self {
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+
+        for entry in &snapshot.entries {
+            children
+                .entry(entry.path.parent().unwrap_or(Path::new("")))
+                .or_default()
+                .push(entry);
+        }
+
+        Self {
+            root: &snapshot.root,
+            children,
+        }
+    }
+
+    fn children(&self, dir: Option<&SnapshotEntry>) -> &[&'a SnapshotEntry] {
+        let path = match dir {
+            Some(entry) if entry.is_dir() => entry.path.as_path(),
+            Some(_) => return &[],
+            None => Path::new(""),
+        };
+
+        self.children.get(path).map_or(&[], Vec::as_slice)
+    }
+}
+
+// A pair of entries with the same path, one of them possibly missing.
+struct Node<'a> {
+    left: Option<&'a SnapshotEntry>,
+    right: Option<&'a SnapshotEntry>,
+    status: Status,
+    children: Vec<Node<'a>>,
+    // entries under this one, itself left out
+    descendants: usize,
+}
+
+impl Node<'_> {
+    fn name(&self) -> &OsStr {
+        let entry = self.left.or(self.right).expect("nodes have a side");
+
+        entry.path.file_name().unwrap_or(entry.path.as_os_str())
+    }
+
+    // Present on both sides with nothing under it differing.
+    fn is_collapsible(&self) -> bool {
+        self.status == Status::Same && !self.children.is_empty()
+    }
+
+    fn count(&self, counts: &mut CompareCounts) {
+        for child in &self.children {
+            match child.status {
+                Status::Same => counts.same += 1,
+                Status::Differs => counts.differ += 1,
+                Status::LeftOnly => counts.left_only += 1,
+                Status::RightOnly => counts.right_only += 1,
+            }
+
+            child.count(counts);
+        }
+    }
+}
+
+// What --hash compares file contents with, hash_file outside of tests.
+pub type Hasher<'h> = &'h dyn Fn(&Path) -> io::Result<u64>;
+
+struct Comparison<'a, 'h> {
+    left: Side<'a>,
+    right: Side<'a>,
+    // None unless --hash, in which case files of equal size are also
+    // compared by content
+    hash: Option<Hasher<'h>>,
+}
+
+impl<'a> Comparison<'a, '_> {
+    // The two roots, which have no entries of their own.
+    fn root(&self) -> Node<'a> {
+        self.node(None, None, true)
+    }
+
+    fn node(
+        &self,
+        left: Option<&'a SnapshotEntry>,
+        right: Option<&'a SnapshotEntry>,
+        is_root: bool,
+    ) -> Node<'a> {
+        let children = self
+            .pair_children(
+                self.left.children(left),
+                self.right.children(right),
+                is_root || left.is_some(),
+                is_root || right.is_some(),
+            )
+            .into_iter()
+            .map(|(left, right)| self.node(left, right, false))
+            .collect::<Vec<_>>();
+
+        let status = match (left, right) {
+            (Some(_), None) => Status::LeftOnly,
+            (None, Some(_)) => Status::RightOnly,
+            (Some(left), Some(right)) if !self.entries_match(left, right) => {
+                Status::Differs
+            }
+            _ if children.iter().all(|child| child.status == Status::Same) => {
+                Status::Same
+            }
+            _ => Status::Differs,
+        };
+
+        Node {
+            left,
+            right,
+            status,
+            descendants: children
+                .iter()
+                .map(|child| child.descendants + 1)
+                .sum(),
+            children,
+        }
+    }
+
+    // Lines up two lists of children by name, keeping the left side's
+    // order and slotting in right-only entries where they were walked.
+    // Both sides are sorted the same way, so this is a plain merge for
+    // name sorts and still sensible for -t.
+    fn pair_children(
+        &self,
+        left: &[&'a SnapshotEntry],
+        right: &[&'a SnapshotEntry],
+        has_left: bool,
+        has_right: bool,
+    ) -> Vec<(Option<&'a SnapshotEntry>, Option<&'a SnapshotEntry>)> {
+        fn name<'e>(entry: &&'e SnapshotEntry) -> Option<&'e OsStr> {
+            entry.path.file_name()
+        }
+
+        // a side that doesn't exist has nothing to line up against
+        let left = if has_left { left } else { &[] };
+        let right = if has_right { right } else { &[] };
+
+        let left_names = left.iter().map(name).collect::<HashSet<_>>();
+        let right_by_name = right
+            .iter()
+            .map(|entry| (name(entry), *entry))
+            .collect::<HashMap<_, _>>();
+
+        let mut pairs = vec![];
+        let mut next_right = right.iter().peekable();
+
+        for entry in left {
+            let Some(other) = right_by_name.get(&name(entry)) else {
+                pairs.push((Some(*entry), None));
+                continue;
+            };
+
+            // right-only entries walked before this one go first
+            while let Some(candidate) = next_right.peek() {
+                if name(candidate) == name(entry) {
+                    next_right.next();
+                    break;
+                }
+
+                if !left_names.contains(&name(candidate)) {
+                    pairs.push((None, Some(**candidate)));
+                }
+
+                next_right.next();
+            }
+
+            pairs.push((Some(*entry), Some(*other)));
+        }
+
+        pairs.extend(
+            next_right
+                .filter(|entry| !left_names.contains(&name(entry)))
+                .map(|entry| (None, Some(*entry))),
+        );
+
+        pairs
+    }
+
+    // Whether two entries at the same path match by themselves, their
+    // children aside. Modes and mtimes are left out since copies rarely
+    // keep them.
+    fn entries_match(
+        &self,
+        left: &SnapshotEntry,
+        right: &SnapshotEntry,
+    ) -> bool {
+        if left.mode & S_IFMT != right.mode & S_IFMT {
+            return false;
+        }
+
+        match left.mode & S_IFMT {
+            S_IFLNK => left.target == right.target,
+            S_IFREG if left.size != right.size => false,
+            S_IFREG => self.hash.is_none_or(|hash| {
+                let left = hash(&self.left.root.join(&left.path));
+                let right = hash(&self.right.root.join(&right.path));
+
+                // anything that can't be read can't be shown identical
+                matches!((left, right), (Ok(left), Ok(right)) if left == right)
+            }),
+            _ => true,
+        }
+    }
+}
+
+// A rendered half of a row, and how many columns it takes up on screen.
+struct Cell {
+    text: Vec<u8>,
+    width: usize,
+}
+
+struct Renderer<'a> {
+    escaper: NameEscaper,
+    colors: &'a Colors,
+    connectors: &'static Connectors,
+    hash: bool,
+}
+
+impl Renderer<'_> {
+    fn cell(
+        &self,
+        node: &Node,
+        entry: Option<&SnapshotEntry>,
+        has_more: &[bool],
+        is_last: bool,
+    ) -> Cell {
+        let mut text = vec![];
+
+        for more in has_more {
+            text.extend(if *more {
+                self.connectors.vertical
+            } else {
+                b" "
+            });
+            text.extend(DEFAULT_INDENT.as_bytes());
+        }
+
+        let mut width = has_more.len() * (1 + DEFAULT_INDENT.len());
+
+        // the missing side keeps its ancestors' lines, but nothing else
+        let Some(entry) = entry else {
+            return Cell { text, width };
+        };
+
+        text.extend(if is_last {
+            self.connectors.last
+        } else {
+            self.connectors.tee
+        });
+        text.extend(self.connectors.horizontal);
+        text.push(b' ');
+        width += 5;
+
+        let name = self.escaper.escape(node.name());
+        let mut plain = String::from_utf8_lossy(&name).into_owned();
+
+        match node.status.color() {
+            Some(color) => {
+                text.extend(self.colors.paint(color, &plain).as_bytes())
+            }
+            None => {
+                let (color, reset) = self.colors.get_color_esc_seq(
+                    FileType::from_mode(entry.mode, 1, false),
+                    &node.name().to_string_lossy(),
+                );
+
+                text.extend(color.as_bytes());
+                text.extend(&name);
+                text.extend(reset.as_bytes());
+            }
+        }
+
+        let mut suffix = String::new();
+
+        if let Some(target) = &entry.target {
+            let target = self.escaper.escape(target.as_os_str());
+            suffix.push_str(" -> ");
+            suffix.push_str(&String::from_utf8_lossy(&target));
+        }
+
+        if node.is_collapsible() {
+            suffix.push_str(&format!(
+                " ({} {}, {})",
+                node.descendants,
+                if node.descendants == 1 {
+                    "entry"
+                } else {
+                    "entries"
+                },
+                if self.hash { "identical" } else { "same sizes" }
+            ));
+        } else if !entry.is_dir() && entry.target.is_none() {
+            suffix.push_str(&format!(" ({} B)", entry.size));
+        }
+
+        plain.push_str(&suffix);
+        text.extend(suffix.as_bytes());
+        width += plain.chars().count();
+
+        Cell { text, width }
+    }
+
+    fn rows(
+        &self,
+        node: &Node,
+        has_more: &mut Vec<bool>,
+        rows: &mut Vec<(Cell, Status, Cell)>,
+    ) {
+        for (idx, child) in node.children.iter().enumerate() {
+            let is_last = idx + 1 == node.children.len();
+
+            rows.push((
+                self.cell(child, child.left, has_more, is_last),
+                child.status,
+                self.cell(child, child.right, has_more, is_last),
+            ));
+
+            if !child.is_collapsible() {
+                has_more.push(!is_last);
+                self.rows(child, has_more, rows);
+                has_more.pop();
+            }
+        }
+    }
+
+    fn root(&self, root: &Path) -> Cell {
+        let text = self.escaper.escape(root.as_os_str());
+
+        Cell {
+            width: String::from_utf8_lossy(&text).chars().count(),
+            text,
+        }
+    }
+}
+
+// Renders `left` and `right` side by side, one row per path found in
+// either. The gutter between them is blank where both sides match, |
+// where they differ and < or > where only one side has the entry.
+// Directories that match all the way down are collapsed to one row.
+pub fn render(
+    left: &Snapshot,
+    right: &Snapshot,
+    cmd: &Cmd,
+    colors: &Colors,
+    hash: Option<Hasher>,
+) -> (Vec<u8>, CompareCounts) {
+    let comparison = Comparison {
+        left: Side::new(left),
+        right: Side::new(right),
+        hash,
+    };
+
+    let root = comparison.root();
+    let mut counts = CompareCounts::default();
+    root.count(&mut counts);
+
+    let renderer = Renderer {
+        escaper: NameEscaper::from_flags(&cmd.flags),
+        colors,
+        connectors: cmd
+            .flags
+            .charset
+            .unwrap_or_else(Charset::from_locale)
+            .connectors(),
+        hash: hash.is_some(),
+    };
+
+    let mut rows = vec![(
+        renderer.root(&left.root),
+        Status::Same,
+        renderer.root(&right.root),
+    )];
+    renderer.rows(&root, &mut vec![], &mut rows);
+
+    let width = rows
+        .iter()
+        .map(|(left, _, _)| left.width)
+        .max()
+        .unwrap_or(0);
+    let mut output = vec![];
+
+    for (left, status, right) in rows {
+        output.extend(left.text);
+        output.extend(" ".repeat(width - left.width + 1).as_bytes());
+
+        match status.color() {
+            Some(color) => {
+                output.extend(colors.paint(color, status.gutter()).as_bytes())
+            }
+            None => output.extend(status.gutter().as_bytes()),
+        }
+
+        output.push(b' ');
+        output.extend(right.text);
+
+        // no trailing blanks when the right side is missing
+        while output.last() == Some(&b' ') {
+            output.pop();
+        }
+
+        output.push(b'\n');
+    }
+
+    (output, counts)
+}
+
+pub fn format_counts(counts: &CompareCounts, colors: &Colors) -> String {
+    let paint = |count: usize| colors.paint("report_count", &count.to_string());
+
+    format!(
+        "{} same, {} differ, {} only left, {} only right",
+        paint(counts.same),
+        paint(counts.differ),
+        paint(counts.left_only),
+        paint(counts.right_only)
+    )
+}
+
+#[cfg(test)]
+mod compare_tests {
+    use super::*;
+    use crate::cli::Flags;
+    use crate::core::dirent::S_IFDIR;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, mode: u32, size: u64) -> SnapshotEntry {
+        SnapshotEntry {
+            path: PathBuf::from(path),
+            mode,
+            size,
+            mtime: 0,
+            target: None,
+        }
+    }
+
+    fn snapshot(root: &str, entries: Vec<SnapshotEntry>) -> Snapshot {
+        Snapshot {
+            root: PathBuf::from(root),
+            entries,
+        }
+    }
+
+    fn render_ascii(
+        left: &Snapshot,
+        right: &Snapshot,
+        hash: Option<Hasher>,
+    ) -> (String, CompareCounts) {
+        let cmd = Cmd {
+            flags: Flags {
+                charset: Some(Charset::Ascii),
+                ..Flags::default()
+            },
+        };
+
+        let (output, counts) =
+            render(left, right, &cmd, &Colors::default(), hash);
+
+        (String::from_utf8(output).unwrap(), counts)
+    }
+
+    #[test]
+    fn lines_up_both_trees_and_collapses_matching_ones() {
+        let left = snapshot(
+            "vendor",
+            vec![
+                entry("a.c", S_IFREG | 0o644, 10),
+                entry("docs", S_IFDIR | 0o755, 0),
+                entry("docs/x.md", S_IFREG | 0o644, 1),
+                entry("docs/y.md", S_IFREG | 0o644, 2),
+                entry("old.c", S_IFREG | 0o644, 3),
+                entry("z.c", S_IFREG | 0o644, 4),
+            ],
+        );
+        let right = snapshot(
+            "upstream",
+            vec![
+                entry("a.c", S_IFREG | 0o644, 12),
+                entry("b.c", S_IFREG | 0o644, 5),
+                entry("docs", S_IFDIR | 0o755, 0),
+                entry("docs/x.md", S_IFREG | 0o644, 1),
+                entry("docs/y.md", S_IFREG | 0o600, 2),
+                entry("z.c", S_IFREG | 0o644, 4),
+            ],
+        );
+
+        let (output, counts) = render_ascii(&left, &right, None);
+
+        assert_eq!(
+            output,
+            "vendor                              upstream\n\
+             |--- a.c (10 B)                   | |--- a.c (12 B)\n\
+             \x20                                 > |--- b.c (5 B)\n\
+             |--- docs (2 entries, same sizes)   |--- docs (2 entries, same sizes)\n\
+             |--- old.c (3 B)                  <\n\
+             `--- z.c (4 B)                      `--- z.c (4 B)\n"
+        );
+        assert_eq!(
+            counts,
+            CompareCounts {
+                same: 4,
+                differ: 1,
+                left_only: 1,
+                right_only: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn compares_contents_only_with_a_hash() {
+        let left = snapshot("a", vec![entry("f", S_IFREG | 0o644, 3)]);
+        let right = snapshot("b", vec![entry("f", S_IFREG | 0o644, 3)]);
+
+        // each side's file hashes to its root's name
+        let hash = |path: &Path| -> io::Result<u64> {
+            Ok(u64::from(path.as_os_str().as_encoded_bytes()[0]))
+        };
+
+        assert!(!render_ascii(&left, &right, None).1.has_differences());
+        assert_eq!(
+            render_ascii(&left, &right, Some(&hash)).0,
+            "a              b\n\
+             `--- f (3 B) | `--- f (3 B)\n"
+        );
+    }
+
+    #[test]
+    fn hashes_file_contents() {
+        let dir = std::env::temp_dir()
+            .join(format!("small-axe-compare-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, contents) in [("a", "same"), ("b", "same"), ("c", "diff")] {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let hash = |name: &str| hash_file(&dir.join(name)).unwrap();
+
+        assert_eq!(hash("a"), hash("b"));
+        assert_ne!(hash("a"), hash("c"));
+        assert!(hash_file(&dir.join("missing")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,492 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::charset::Charset;
+use super::colors::Colors;
+use super::dirent::S_IFMT;
+use super::escape::NameEscaper;
+use super::file_type::FileType;
+use super::snapshot::{Snapshot, SnapshotEntry};
+use super::time::format_utc;
+
+use crate::cli::Cmd;
+
+const DEFAULT_INDENT: &str = "    ";
+
+// How many entries --diff found in each state.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DiffCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub unchanged: usize,
+}
+
+impl DiffCounts {
+    pub fn has_changes(&self) -> bool {
+        self.added + self.removed + self.modified != 0
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Status {
+    Added,
+    Removed,
+    // what changed, e.g. "size 10 -> 12 B"
+    Modified(Vec<String>),
+    Unchanged,
+}
+
+impl Status {
+    fn of(old: Option<&SnapshotEntry>, new: Option<&SnapshotEntry>) -> Self {
+        match (old, new) {
+            (None, _) => Status::Added,
+            (_, None) => Status::Removed,
+            (Some(old), Some(new)) => {
+                let changes = changes(old, new);
+
+                if changes.is_empty() {
+                    Status::Unchanged
+                } else {
+                    Status::Modified(changes)
+                }
+            }
+        }
+    }
+
+    fn marker(&self) -> &'static str {
+        match self {
+            Status::Added => "+",
+            Status::Removed => "-",
+            Status::Modified(_) => "~",
+            Status::Unchanged => " ",
+        }
+    }
+
+    fn color(&self) -> Option<&'static str> {
+        match self {
+            Status::Added => Some("diff_added"),
+            Status::Removed => Some("diff_removed"),
+            Status::Modified(_) => Some("diff_modified"),
+            Status::Unchanged => None,
+        }
+    }
+}
+
+// What differs between two versions of an entry. A directory's size and
+// mtime change with whatever is added to it, so only its mode counts.
+fn changes(old: &SnapshotEntry, new: &SnapshotEntry) -> Vec<String> {
+    if old.mode & S_IFMT != new.mode & S_IFMT {
+        return vec![format!("{} -> {}", old.type_name(), new.type_name())];
+    }
+
+    let mut changes = vec![];
+
+    if old.mode & 0o7777 != new.mode & 0o7777 {
+        changes.push(format!(
+            "mode {:04o} -> {:04o}",
+            old.mode & 0o7777,
+            new.mode & 0o7777
+        ));
+    }
+
+    if !new.is_dir() {
+        if old.size != new.size {
+            changes.push(format!("size {} -> {} B", old.size, new.size));
+        }
+
+        if old.mtime != new.mtime {
+            changes.push(format!(
+                "mtime {} -> {}",
+                format_utc(old.mtime),
+                format_utc(new.mtime)
+            ));
+        }
+    }
+
+    if old.target != new.target {
+        let target = |entry: &SnapshotEntry| {
+            entry
+                .target
+                .as_ref()
+                .map_or(String::from("none"), |target| {
+                    target.display().to_string()
+                })
+        };
+
+        changes.push(format!("target {} -> {}", target(old), target(new)));
+    }
+
+    changes
+}
+
+// Both snapshots merged into one tree, keyed by path under the root.
+struct Merged<'a> {
+    entries: HashMap<
+        &'a Path,
+        (Option<&'a SnapshotEntry>, Option<&'a SnapshotEntry>),
+    >,
+    children: HashMap<&'a Path, Vec<&'a Path>>,
+}
+
+impl<'a> Merged<'a> {
+    fn new(old: &'a Snapshot, new: &'a Snapshot) -> Self {
+        let mut merged = Merged {
+            entries: HashMap::new(),
+            children: HashMap::new(),
+        };
+
+        for entry in &old.entries {
+            merged.add(entry).0 = Some(entry);
+        }
+
+        for entry in &new.entries {
+            merged.add(entry).1 = Some(entry);
+        }
+
+        merged
+    }
+
+    fn add(
+        &mut self,
+        entry: &'a SnapshotEntry,
+    ) -> &mut (Option<&'a SnapshotEntry>, Option<&'a SnapshotEntry>) {
+        let path = entry.path.as_path();
+
+        self.entries.entry(path).or_insert_with(|| {
+            self.children
+                .entry(path.parent().unwrap_or(Path::new("")))
+                .or_default()
+                .push(path);
+
+            (None, None)
+        })
+    }
+
+    // Directories first with --dirsfirst, then by name the way the tree
+    // sorts them.
+    fn sort(&mut self, cmd: &Cmd) {
+        let is_dir = |path: &Path, entries: &HashMap<_, _>| {
+            let (old, new): &(Option<&SnapshotEntry>, Option<&SnapshotEntry>) =
+                &entries[path];
+            new.or(*old).is_some_and(|entry| entry.is_dir())
+        };
+
+        let clean_name = |path: &Path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.strip_prefix('.').unwrap_or(&name).to_owned()
+        };
+
+        for children in self.children.values_mut() {
+            children.sort_by(|a, b| {
+                match (is_dir(a, &self.entries), is_dir(b, &self.entries)) {
+                    (true, false) if cmd.flags.dirs_first => Ordering::Less,
+                    (false, true) if cmd.flags.dirs_first => Ordering::Greater,
+                    _ if cmd.flags.reverse_alpha_sort => {
+                        clean_name(b).cmp(&clean_name(a))
+                    }
+                    _ => clean_name(a).cmp(&clean_name(b)),
+                }
+            });
+        }
+    }
+}
+
+// Renders `old` and `new` as one tree, each entry marked + (added),
+// - (removed), ~ (modified) or left blank. With --diff-only, entries
+// with nothing changed at or under them are left out.
+pub fn render(
+    old: &Snapshot,
+    new: &Snapshot,
+    cmd: &Cmd,
+    colors: &Colors,
+) -> (Vec<u8>, DiffCounts) {
+    let mut merged = Merged::new(old, new);
+    merged.sort(cmd);
+
+    let statuses = merged
+        .entries
+        .iter()
+        .map(|(path, (old, new))| (*path, Status::of(*old, *new)))
+        .collect::<HashMap<_, _>>();
+
+    let mut counts = DiffCounts::default();
+
+    for status in statuses.values() {
+        match status {
+            Status::Added => counts.added += 1,
+            Status::Removed => counts.removed += 1,
+            Status::Modified(_) => counts.modified += 1,
+            Status::Unchanged => counts.unchanged += 1,
+        }
+    }
+
+    // every changed entry and the directories leading to it
+    let mut changed = HashSet::new();
+
+    for (path, status) in &statuses {
+        if *status != Status::Unchanged {
+            changed.extend(path.ancestors());
+        }
+    }
+
+    let renderer = Renderer {
+        merged: &merged,
+        statuses: &statuses,
+        changed: cmd.flags.diff_only.then_some(&changed),
+        escaper: NameEscaper::from_flags(&cmd.flags),
+        colors,
+        connectors: cmd
+            .flags
+            .charset
+            .unwrap_or_else(Charset::from_locale)
+            .connectors(),
+    };
+
+    let mut output = vec![];
+
+    output.extend(b"  ");
+    output.extend(renderer.escaper.escape(new.root.as_os_str()));
+    output.push(b'\n');
+
+    renderer.render_children(Path::new(""), &mut vec![], &mut output);
+
+    (output, counts)
+}
+
+struct Renderer<'a> {
+    merged: &'a Merged<'a>,
+    statuses: &'a HashMap<&'a Path, Status>,
+    // Some with --diff-only
+    changed: Option<&'a HashSet<&'a Path>>,
+    escaper: NameEscaper,
+    colors: &'a Colors,
+    connectors: &'static super::charset::Connectors,
+}
+
+impl Renderer<'_> {
+    // `has_more` says, for each ancestor level, whether more siblings
+    // follow it, which decides where vertical lines continue.
+    fn render_children(
+        &self,
+        dir: &Path,
+        has_more: &mut Vec<bool>,
+        output: &mut Vec<u8>,
+    ) {
+        let children = self
+            .merged
+            .children
+            .get(dir)
+            .into_iter()
+            .flatten()
+            .filter(|path| {
+                self.changed.is_none_or(|changed| changed.contains(*path))
+            })
+            .collect::<Vec<_>>();
+
+        for (idx, path) in children.iter().enumerate() {
+            let is_last = idx + 1 == children.len();
+            let status = &self.statuses[**path];
+            let (old, new) = self.merged.entries[**path];
+            let entry = new.or(old).expect("merged entries have a side");
+
+            let paint = |text: &str| match status.color() {
+                Some(color) => self.colors.paint(color, text),
+                None => text.to_owned(),
+            };
+
+            output.extend(paint(status.marker()).as_bytes());
+            output.push(b' ');
+
+            for more in has_more.iter() {
+                output.extend(if *more {
+                    self.connectors.vertical
+                } else {
+                    b" "
+                });
+                output.extend(DEFAULT_INDENT.as_bytes());
+            }
+
+            output.extend(if is_last {
+                self.connectors.last
+            } else {
+                self.connectors.tee
+            });
+            output.extend(self.connectors.horizontal);
+            output.push(b' ');
+
+            let name = self
+                .escaper
+                .escape(path.file_name().unwrap_or(path.as_os_str()));
+
+            match status.color() {
+                Some(_) => output
+                    .extend(paint(&String::from_utf8_lossy(&name)).as_bytes()),
+                None => {
+                    // unchanged entries look like they do in the tree
+                    let (color, reset) = self.colors.get_color_esc_seq(
+                        FileType::from_mode(entry.mode, 1, false),
+                        &path.file_name().unwrap_or_default().to_string_lossy(),
+                    );
+
+                    output.extend(color.as_bytes());
+                    output.extend(&name);
+                    output.extend(reset.as_bytes());
+                }
+            }
+
+            if let Some(target) = &entry.target {
+                output.extend(b" -> ");
+                output.extend(self.escaper.escape(target.as_os_str()));
+            }
+
+            if let Status::Modified(changes) = status {
+                output.extend(format!(" [{}]", changes.join(", ")).as_bytes());
+            }
+
+            output.push(b'\n');
+
+            has_more.push(!is_last);
+            self.render_children(path, has_more, output);
+            has_more.pop();
+        }
+    }
+}
+
+pub fn format_counts(counts: &DiffCounts, colors: &Colors) -> String {
+    let paint = |count: usize| colors.paint("report_count", &count.to_string());
+
+    format!(
+        "{} added, {} removed, {} modified, {} unchanged",
+        paint(counts.added),
+        paint(counts.removed),
+        paint(counts.modified),
+        paint(counts.unchanged)
+    )
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+    use crate::cli::Flags;
+    use crate::core::charset::Charset;
+    use crate::core::dirent::{S_IFDIR, S_IFLNK, S_IFREG};
+    use std::path::PathBuf;
+
+    fn entry(path: &str, mode: u32, size: u64, mtime: u64) -> SnapshotEntry {
+        SnapshotEntry {
+            path: PathBuf::from(path),
+            mode,
+            size,
+            mtime,
+            target: None,
+        }
+    }
+
+    fn snapshots() -> (Snapshot, Snapshot) {
+        let old = Snapshot {
+            root: PathBuf::from("before"),
+            entries: vec![
+                entry("bin", S_IFDIR | 0o755, 4096, 1),
+                entry("bin/tool", S_IFREG | 0o755, 10, 1),
+                entry("lib", S_IFDIR | 0o755, 4096, 1),
+                entry("lib/a.so", S_IFREG | 0o644, 5, 1),
+                entry("lib/old.so", S_IFREG | 0o644, 5, 1),
+                entry("share", S_IFDIR | 0o755, 4096, 1),
+                entry("share/doc", S_IFREG | 0o644, 1, 1),
+            ],
+        };
+
+        let mut link = entry("lib/current.so", S_IFLNK | 0o777, 4, 2);
+        link.target = Some(PathBuf::from("a.so"));
+
+        let new = Snapshot {
+            root: PathBuf::from("after"),
+            entries: vec![
+                // a directory's own size and mtime don't count
+                entry("bin", S_IFDIR | 0o755, 8192, 2),
+                entry("bin/tool", S_IFREG | 0o700, 12, 1),
+                entry("lib", S_IFDIR | 0o755, 4096, 2),
+                entry("lib/a.so", S_IFREG | 0o644, 5, 1),
+                link,
+                entry("share", S_IFDIR | 0o755, 4096, 1),
+                entry("share/doc", S_IFREG | 0o644, 1, 1),
+            ],
+        };
+
+        (old, new)
+    }
+
+    fn render_ascii(diff_only: bool) -> (String, DiffCounts) {
+        let (old, new) = snapshots();
+        let cmd = Cmd {
+            flags: Flags {
+                charset: Some(Charset::Ascii),
+                diff_only,
+                ..Flags::default()
+            },
+        };
+
+        let (output, counts) = render(&old, &new, &cmd, &Colors::default());
+
+        (String::from_utf8(output).unwrap(), counts)
+    }
+
+    #[test]
+    fn marks_every_entry_in_one_tree() {
+        let (output, counts) = render_ascii(false);
+
+        assert_eq!(
+            output,
+            "  after\n\
+             \x20 |--- bin\n\
+             ~ |    `--- tool [mode 0755 -> 0700, size 10 -> 12 B]\n\
+             \x20 |--- lib\n\
+             \x20 |    |--- a.so\n\
+             + |    |--- current.so -> a.so\n\
+             - |    `--- old.so\n\
+             \x20 `--- share\n\
+             \x20      `--- doc\n"
+        );
+        assert_eq!(
+            counts,
+            DiffCounts {
+                added: 1,
+                removed: 1,
+                modified: 1,
+                unchanged: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_unchanged_subtrees() {
+        let (output, _) = render_ascii(true);
+
+        assert_eq!(
+            output,
+            "  after\n\
+             \x20 |--- bin\n\
+             ~ |    `--- tool [mode 0755 -> 0700, size 10 -> 12 B]\n\
+             \x20 `--- lib\n\
+             +      |--- current.so -> a.so\n\
+             -      `--- old.so\n"
+        );
+    }
+
+    #[test]
+    fn describes_type_and_target_changes() {
+        let mut old = entry("x", S_IFREG | 0o644, 1, 1);
+        let new = entry("x", S_IFDIR | 0o755, 1, 1);
+
+        assert_eq!(changes(&old, &new), ["file -> directory"]);
+
+        old.mode = S_IFLNK | 0o777;
+        old.target = Some(PathBuf::from("a"));
+        let mut new = old.clone();
+        new.target = Some(PathBuf::from("b"));
+
+        assert_eq!(changes(&old, &new), ["target a -> b"]);
+    }
+}
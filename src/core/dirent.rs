@@ -1,24 +1,26 @@
+use std::borrow::Cow;
 use std::fs;
-use std::io::Error;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
-#[cfg(linux)]
-use std::os::linux::fs::MetadataExt;
-
+use super::colors::Colors;
+use super::file_type::{permissions_string, FileType};
 use crate::cli::Cmd;
 
-const PERMISSIONS_READ: &str = "r";
-const PERMISSIONS_WRITE: &str = "w";
-const PERMISSIONS_EXEC: &str = "x";
-const PERMISSIONS_DASH: &str = "-";
-
-const S_IFMT: u32 = 0o170_000;
-const S_IFSOCK: u32 = 0o140_000;
-const S_IFIFO: u32 = 0o10_000;
+pub const S_IFMT: u32 = 0o170_000;
+pub const S_IFSOCK: u32 = 0o140_000;
+pub const S_IFLNK: u32 = 0o120_000;
+pub const S_IFREG: u32 = 0o100_000;
+pub const S_IFBLK: u32 = 0o060_000;
+pub const S_IFDIR: u32 = 0o040_000;
+pub const S_IFCHR: u32 = 0o020_000;
+pub const S_IFIFO: u32 = 0o10_000;
 
 const MINUTE: u64 = 60_u64;
 const HOUR: u64 = MINUTE * 60_u64;
@@ -48,6 +50,29 @@ fn calc_years(time: u64) -> (u64, u64) {
     (count, current)
 }
 
+// One field of the [..] metadata block. `text` is what Display measures
+// to line columns up, `painted` is the same text with any colors.
+#[derive(Debug)]
+pub struct InfoColumn {
+    pub text: String,
+    pub painted: String,
+    pub right_align: bool,
+}
+
+impl InfoColumn {
+    fn new(
+        text: String,
+        right_align: bool,
+        paint: impl FnOnce(&str) -> String,
+    ) -> Self {
+        Self {
+            painted: paint(&text),
+            text,
+            right_align,
+        }
+    }
+}
+
 pub enum ExtData {
     Inode,
     Gid,
@@ -56,76 +81,175 @@ pub enum ExtData {
     Permissions,
 }
 
+// The subset of stat data tree displays. Entries read from the
+// filesystem copy it out of fs::Metadata, virtual entries (--fromfile)
+// fill in what they know.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    pub mode: u32,
+    pub size: u64,
+    pub modified: Option<Duration>,
+    pub ino: u64,
+    pub dev: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+}
+
+impl Metadata {
+    pub fn directory() -> Self {
+        Self {
+            mode: S_IFDIR | 0o755,
+            ..Self::default()
+        }
+    }
+
+    pub fn file() -> Self {
+        Self {
+            mode: S_IFREG | 0o644,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<fs::Metadata> for Metadata {
+    fn from(md: fs::Metadata) -> Self {
+        Self {
+            mode: md.mode(),
+            size: md.len(),
+            modified: md.modified().ok().and_then(|mod_time| {
+                mod_time.duration_since(SystemTime::UNIX_EPOCH).ok()
+            }),
+            ino: md.ino(),
+            dev: md.dev(),
+            uid: md.uid(),
+            gid: md.gid(),
+            nlink: md.nlink(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DirEntry {
     path: PathBuf,
-    metadata: std::fs::Metadata,
+    metadata: Metadata,
     pub depth: usize,
-    file_type: std::fs::FileType,
     linked_path: Option<PathBuf>,
+    // a symlink whose target doesn't exist
+    is_orphan: bool,
     pub is_recursive_link: bool,
+    // set by the walk when read_dir fails
+    pub is_unreadable: bool,
+    // set by the walk when --filelimit keeps it from opening a directory
+    pub exceeds_limit: Option<usize>,
+    // a symlink to a directory, as far as its source can tell
+    pub links_to_dir: bool,
+    // from a listing or archive, with nothing on disk behind it
+    is_virtual: bool,
 }
 
 impl DirEntry {
-    pub fn from_path(path: PathBuf, depth: usize) -> Self {
-        let md = fs::metadata(&path).expect("failed to get metadata");
+    // Fails if the path is gone, which a build running under --watch can
+    // do at any moment.
+    pub fn from_path(path: PathBuf, depth: usize) -> io::Result<Self> {
+        let md = fs::metadata(&path)?;
 
         let linked_path: Option<PathBuf> = if md.file_type().is_symlink() {
-            Some(fs::read_link(&path).expect("failed to get linked path"))
+            Some(fs::read_link(&path)?)
         } else {
             None
         };
 
-        Self {
+        Ok(Self {
             depth,
             path,
             linked_path,
-            file_type: md.file_type(),
-            metadata: md,
+            metadata: Metadata::from(md),
+            is_orphan: false,
             is_recursive_link: false,
-        }
+            is_unreadable: false,
+            exceeds_limit: None,
+            links_to_dir: false,
+            is_virtual: false,
+        })
     }
 
-    pub fn from_entry(entry: fs::DirEntry, depth: usize) -> Self {
+    pub fn from_entry(entry: fs::DirEntry, depth: usize) -> io::Result<Self> {
         let path = entry.path();
 
-        let md = entry.metadata().expect("failed to get metadata");
-
-        // if path.is_symlink() {
-        //     std::fs::symlink_metadata(&path).expect("failed to get metadata")
-        // } else {
-        // };
+        let md = entry.metadata()?;
 
         let linked_path: Option<PathBuf> = if md.file_type().is_symlink() {
-            Some(fs::read_link(&path).expect("failed to get linked path"))
+            Some(fs::read_link(&path)?)
         } else {
             None
         };
 
+        let target = linked_path.as_ref().map(|_| fs::metadata(&path));
+        let is_orphan = target.as_ref().is_some_and(Result::is_err);
+        let links_to_dir = target
+            .as_ref()
+            .is_some_and(|target| target.as_ref().is_ok_and(|md| md.is_dir()));
+
+        Ok(Self {
+            depth,
+            path,
+            linked_path,
+            metadata: Metadata::from(md),
+            is_orphan,
+            is_recursive_link: false,
+            is_unreadable: false,
+            exceeds_limit: None,
+            links_to_dir,
+            is_virtual: false,
+        })
+    }
+
+    pub fn from_virtual(
+        path: PathBuf,
+        depth: usize,
+        metadata: Metadata,
+        linked_path: Option<PathBuf>,
+    ) -> Self {
         Self {
             depth,
             path,
             linked_path,
-            file_type: md.file_type(),
-            metadata: md,
+            metadata,
+            is_orphan: false,
             is_recursive_link: false,
+            is_unreadable: false,
+            exceeds_limit: None,
+            links_to_dir: false,
+            is_virtual: true,
         }
     }
 
-    pub fn get_clean_name(&self) -> &str {
-        self.path().file_name().map_or_else(
-            || self.path().to_str().unwrap_or(""),
-            |n| match n.to_str().unwrap().strip_prefix('.') {
-                Some(name) => name,
-                None => n.to_str().unwrap(),
-            },
-        )
+    // Non-UTF-8 names are compared and matched lossily; display escapes
+    // the raw bytes separately.
+    pub fn get_lossy_name(&self) -> Cow<'_, str> {
+        self.path()
+            .file_name()
+            .unwrap_or(self.path().as_os_str())
+            .to_string_lossy()
+    }
+
+    // The name without a leading '.', which is how entries are sorted.
+    pub fn get_clean_name(&self) -> Cow<'_, str> {
+        match self.get_lossy_name() {
+            Cow::Borrowed(name) => {
+                Cow::Borrowed(name.strip_prefix('.').unwrap_or(name))
+            }
+            Cow::Owned(name) => {
+                Cow::Owned(name.strip_prefix('.').unwrap_or(&name).to_owned())
+            }
+        }
     }
 
     pub fn is_hidden(&self) -> bool {
-        self.path().file_name().map_or(false, |n| {
-            n.to_str().map_or(false, |name| name.starts_with('.'))
-        })
+        self.path()
+            .file_name()
+            .is_some_and(|n| n.as_bytes().starts_with(b"."))
     }
 
     pub fn get_name(&self) -> Option<&std::ffi::OsStr> {
@@ -143,8 +267,13 @@ impl DirEntry {
     pub fn full_path(&self) -> PathBuf {
         if self.linked_path.is_some() {
             PathBuf::from(self.get_name().unwrap())
+        } else if self.is_virtual {
+            // virtual entries don't exist on disk, so keep their path as is
+            self.path.clone()
         } else {
-            self.path.canonicalize().unwrap()
+            self.path
+                .canonicalize()
+                .unwrap_or_else(|_| self.path.clone())
         }
     }
 
@@ -153,64 +282,91 @@ impl DirEntry {
     }
 
     pub fn is_dir(&self) -> bool {
-        self.file_type.is_dir()
+        self.metadata.mode & S_IFMT == S_IFDIR
     }
 
     pub fn is_symlink(&self) -> bool {
-        self.file_type.is_symlink()
+        self.metadata.mode & S_IFMT == S_IFLNK
     }
 
     pub fn is_executable(&self) -> bool {
-        !self.is_dir() && self.metadata.mode() & 0o111 != 0
+        !self.is_dir() && self.metadata.mode & 0o111 != 0
     }
 
-    pub fn get_file_type(&self) -> &'static str {
-        if self.is_symlink() {
-            "sym_link"
-        } else if self.is_dir() {
-            "directory"
-        } else if self.is_executable() {
-            "executable"
-        } else {
-            ""
-        }
+    pub fn is_fifo(&self) -> bool {
+        self.metadata.mode & S_IFMT == S_IFIFO
     }
 
-    pub fn get_last_modified(&self) -> Duration {
-        self.metadata.modified().map_or_else(
-            |_| {
-                SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .expect("error getting last modified")
-            },
-            |mod_time| {
-                mod_time
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .expect("error getting last modified")
-            },
+    pub fn is_socket(&self) -> bool {
+        self.metadata.mode & S_IFMT == S_IFSOCK
+    }
+
+    pub fn get_mode(&self) -> u32 {
+        self.metadata.mode
+    }
+
+    pub fn get_uid(&self) -> u32 {
+        self.metadata.uid
+    }
+
+    pub fn is_orphan(&self) -> bool {
+        self.is_orphan
+    }
+
+    pub fn get_file_type(&self) -> FileType {
+        FileType::from_mode(
+            self.metadata.mode,
+            self.metadata.nlink,
+            self.is_orphan,
         )
     }
 
+    // None when the source doesn't know, e.g. --fromfile entries, so
+    // sorting by it stays stable.
+    pub fn modified(&self) -> Option<Duration> {
+        self.metadata.modified
+    }
+
+    pub fn get_last_modified(&self) -> Duration {
+        self.metadata.modified.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+        })
+    }
+
+    pub fn get_device(&self) -> u64 {
+        self.metadata.dev
+    }
+
     pub fn get_size(&self) -> u64 {
-        self.metadata.len()
+        self.metadata.size
     }
 
-    pub fn get_additional_info(&self, cmds: &Cmd) -> String {
-        let mut additional_info_list = Vec::new();
+    // The fields of the [..] metadata block, in display order.
+    pub fn get_additional_info(
+        &self,
+        cmds: &Cmd,
+        colors: &Colors,
+    ) -> Vec<InfoColumn> {
+        let mut columns = Vec::new();
 
         let flags = &cmds.flags;
 
         if flags.protections {
-            additional_info_list.push(self.get_ext_data(ExtData::Permissions));
-        }
-
-        if flags.version {
-            todo!()
+            columns.push(InfoColumn::new(
+                self.get_ext_data(ExtData::Permissions),
+                false,
+                |text| colors.paint_permissions(text),
+            ));
         }
 
         if flags.size && !flags.human_readable_size {
-            let size = format!("{} B", self.get_size());
-            additional_info_list.push(size)
+            let bytes = self.get_size();
+
+            columns.push(InfoColumn::new(format!("{bytes} B"), true, |text| {
+                colors.paint_size(bytes, text)
+            }));
         }
 
         if flags.human_readable_size {
@@ -224,7 +380,9 @@ impl DirEntry {
                 format!("{:?}.{} K", bytes / KB, (bytes % KB) / 100)
             };
 
-            additional_info_list.push(formatted)
+            columns.push(InfoColumn::new(formatted, true, |text| {
+                colors.paint_size(bytes, text)
+            }));
         }
 
         if flags.last_modified {
@@ -241,142 +399,83 @@ impl DirEntry {
             leftover %= HOUR;
             let _mins = leftover / MINUTE;
 
-            additional_info_list.push(years.to_string());
+            let age = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .saturating_sub(total_sec_since_1970);
+
+            columns.push(InfoColumn::new(years.to_string(), true, |text| {
+                colors.paint_age(age, text)
+            }));
         }
 
         if flags.inode {
-            additional_info_list.push(self.get_ext_data(ExtData::Inode));
+            columns.push(InfoColumn::new(
+                self.get_ext_data(ExtData::Inode),
+                true,
+                |text| colors.paint("inode", text),
+            ));
         }
 
         if flags.group {
-            additional_info_list.push(self.get_ext_data(ExtData::Gid));
+            // SAFETY: getegid can't fail and touches no memory of ours
+            let is_own_group = self.metadata.gid == unsafe { libc::getegid() };
+
+            columns.push(InfoColumn::new(
+                self.get_ext_data(ExtData::Gid),
+                true,
+                |text| colors.paint_owner(is_own_group, text),
+            ));
         }
 
         if flags.device {
-            additional_info_list.push(self.get_ext_data(ExtData::Device));
+            columns.push(InfoColumn::new(
+                self.get_ext_data(ExtData::Device),
+                true,
+                |text| colors.paint("device", text),
+            ));
         }
 
         if flags.username {
-            additional_info_list.push(self.get_ext_data(ExtData::Uid));
+            // SAFETY: geteuid can't fail and touches no memory of ours
+            let is_own_user = self.metadata.uid == unsafe { libc::geteuid() };
+
+            columns.push(InfoColumn::new(
+                self.get_ext_data(ExtData::Uid),
+                true,
+                |text| colors.paint_owner(is_own_user, text),
+            ));
         }
 
-        if !additional_info_list.is_empty() {
-            return format!("[{}] ", additional_info_list.join(" "));
-        }
-
-        String::from("")
+        columns
     }
 
+    // The -F suffix.
     pub fn get_identity_character(&self) -> &str {
-        let mode = self.metadata.mode();
-
-        if self.is_symlink() {
-            ""
-        } else if self.is_dir() {
-            "/"
-        } else if mode & S_IFMT == S_IFIFO {
-            "|" // FIFO
-        } else if mode & S_IFMT == S_IFSOCK {
-            "=" // socket
-        } else {
-            "*" // executable file
-        }
-    }
-
-    #[cfg(linux)]
-    pub fn get_ext_data(&self, ext_data: ExtData) -> String {
-        match ext_data {
-            ExtData::Inode => self.metadata.st_ino().to_string(),
-            ExtData::Gid => self.metadata.st_gid().to_string(),
-            ExtData::Uid => self.metadata.st_uid().to_string(),
-            ExtData::Device => self.metadata.st_dev().to_string(),
-            ExtData::Permissions => {
-                let mode = self.metadata.st_mode();
-                // first char in permissions string
-                let mut permissions = if self.is_dir() {
-                    String::from("d")
-                } else if self.is_symlink() {
-                    String::from("l")
-                } else if mode & S_IFMT == S_IFIFO {
-                    String::from("p") // FIFO
-                } else if mode & S_IFMT == S_IFSOCK {
-                    String::from("s") // socket
-                } else {
-                    String::from(PERMISSIONS_DASH)
-                };
-
-                let ugo_perms = [
-                    (PERMISSIONS_READ, mode & 0o400, 256), // user
-                    (PERMISSIONS_WRITE, mode & 0o200, 128),
-                    (PERMISSIONS_EXEC, mode & 0o100, 64),
-                    (PERMISSIONS_READ, mode & 0o040, 32), // group
-                    (PERMISSIONS_WRITE, mode & 0o020, 16),
-                    (PERMISSIONS_EXEC, mode & 0o010, 8),
-                    (PERMISSIONS_READ, mode & 0o004, 4), // other
-                    (PERMISSIONS_WRITE, mode & 0o002, 2),
-                    (PERMISSIONS_EXEC, mode & 0o001, 1),
-                ]
-                .into_iter()
-                .map(|(character, result, expected)| {
-                    if result == expected {
-                        return character;
-                    }
-
-                    PERMISSIONS_DASH
-                })
-                .collect::<String>();
-
-                permissions.push_str(ugo_perms.as_str());
-                permissions
+        match self.get_file_type() {
+            file_type if file_type.is_dir() => "/",
+            FileType::Socket => "=",
+            FileType::Pipe => "|",
+            file_type
+                if file_type.is_regular()
+                    && self.metadata.mode & 0o111 != 0 =>
+            {
+                "*"
             }
+            _ => "",
         }
     }
 
     #[cfg(unix)]
     pub fn get_ext_data(&self, ext_data: ExtData) -> String {
         match ext_data {
-            ExtData::Inode => self.metadata.ino().to_string(),
-            ExtData::Gid => self.metadata.gid().to_string(),
-            ExtData::Uid => self.metadata.uid().to_string(),
-            ExtData::Device => self.metadata.dev().to_string(),
+            ExtData::Inode => self.metadata.ino.to_string(),
+            ExtData::Gid => self.metadata.gid.to_string(),
+            ExtData::Uid => self.metadata.uid.to_string(),
+            ExtData::Device => self.metadata.dev.to_string(),
             ExtData::Permissions => {
-                let mode = self.metadata.mode();
-                // first char in permissions string
-                let mut permissions = if self.is_dir() {
-                    String::from("d")
-                } else if self.is_symlink() {
-                    String::from("l")
-                } else if mode & S_IFMT == S_IFIFO {
-                    String::from("p") // FIFO
-                } else if mode & S_IFMT == S_IFSOCK {
-                    String::from("s") // socket
-                } else {
-                    String::from(PERMISSIONS_DASH)
-                };
-
-                let ugo_perms = [
-                    (PERMISSIONS_READ, mode & 0o400, 256), // user
-                    (PERMISSIONS_WRITE, mode & 0o200, 128),
-                    (PERMISSIONS_EXEC, mode & 0o100, 64),
-                    (PERMISSIONS_READ, mode & 0o040, 32), // group
-                    (PERMISSIONS_WRITE, mode & 0o020, 16),
-                    (PERMISSIONS_EXEC, mode & 0o010, 8),
-                    (PERMISSIONS_READ, mode & 0o004, 4), // other
-                    (PERMISSIONS_WRITE, mode & 0o002, 2),
-                    (PERMISSIONS_EXEC, mode & 0o001, 1),
-                ]
-                .into_iter()
-                .map(|(character, result, expected)| {
-                    if result == expected {
-                        return character;
-                    }
-
-                    PERMISSIONS_DASH
-                })
-                .collect::<String>();
-
-                permissions.push_str(ugo_perms.as_str());
-                permissions
+                permissions_string(self.get_file_type(), self.metadata.mode)
             }
         }
     }
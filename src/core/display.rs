@@ -1,133 +1,770 @@
-use std::ffi::OsStr;
+use std::collections::HashSet;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+use super::charset::Charset;
 use super::colors::Colors;
+use super::dirent::{DirEntry, InfoColumn};
+use super::escape::NameEscaper;
+use super::hyperlink::Hyperlinker;
+use super::stats::Stats;
+use super::top::Top;
 use super::tree::Tree;
 
-use crate::cli::Cmd;
+use crate::cli::{Cmd, Flags};
 
-const VERTICAL_PIPE: &str = "\u{2502}";
-const L_RIGHT: &str = "\u{2514}";
-const T_RIGHT: &str = "\u{251C}";
-const NAME_CONNECTOR: &str = "\u{2500}\u{2500}\u{2500}";
 const DEFAULT_INDENT: &str = "    ";
+
+// Totals for the summary line, summed across every root on the command
+// line.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Report {
+    pub dir_count: usize,
+    pub file_count: usize,
+    // symlinks are also counted as a directory or file, by their target
+    pub link_count: usize,
+    pub total_bytes: u64,
+    pub unreadable_count: usize,
+    pub hidden_count: usize,
+    pub filtered_count: usize,
+    pub elapsed: Duration,
+}
+
+impl std::ops::AddAssign for Report {
+    fn add_assign(&mut self, other: Self) {
+        self.dir_count += other.dir_count;
+        self.file_count += other.file_count;
+        self.link_count += other.link_count;
+        self.total_bytes += other.total_bytes;
+        self.unreadable_count += other.unreadable_count;
+        self.hidden_count += other.hidden_count;
+        self.filtered_count += other.filtered_count;
+        self.elapsed += other.elapsed;
+    }
+}
+
+// --report: GNU's one line summary, or every count we keep.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReportStyle {
+    #[default]
+    Short,
+    Full,
+}
+
+impl ReportStyle {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "short" => Some(ReportStyle::Short),
+            "full" => Some(ReportStyle::Full),
+            _ => None,
+        }
+    }
+}
+
+// One root's tree, rendered but not yet written anywhere.
+pub struct Rendered {
+    pub output: Vec<u8>,
+    pub report: Report,
+    // every directory the walk read, for --watch
+    pub dirs: Vec<PathBuf>,
+}
+
 pub struct Display {}
 
 impl Display {
-    pub fn print(tree: Tree, cmds: &Cmd) {
-        let mut ret = String::new();
+    pub fn print(tree: Tree, cmds: &Cmd, colors: &Colors) -> Report {
+        let rendered = Self::render(tree, cmds, colors, &HashSet::new());
+
+        write_stdout(&rendered.output);
+
+        rendered.report
+    }
+
+    // Names in `highlight` are drawn in reverse video, which is how
+    // --watch shows what just changed.
+    pub fn render(
+        tree: Tree,
+        cmds: &Cmd,
+        colors: &Colors,
+        highlight: &HashSet<PathBuf>,
+    ) -> Rendered {
+        // names are raw bytes on unix, so output is built as bytes too
+        let mut ret: Vec<u8> = vec![];
+
+        let escaper = NameEscaper::from_flags(&cmds.flags);
+        let connectors = cmds
+            .flags
+            .charset
+            .unwrap_or_else(Charset::from_locale)
+            .connectors();
+
+        let hyperlinker = cmds.flags.hyperlink.then(Hyperlinker::new);
 
         let mut has_remaining: std::collections::HashSet<usize> =
             std::collections::HashSet::new();
 
-        let mut file_count = 0;
-        let mut dir_count = 0;
+        let started = Instant::now();
+        let mut entries = tree.into_iter();
+        let mut tally = Tally::new(cmds);
+
+        // metadata columns are padded to their widest value and --condense
+        // looks ahead, so either needs the whole walk first; otherwise
+        // lines are drawn as the walk goes and no rows are kept
+        let needs_rows = cmds.flags.condense || cmds.flags.shows_metadata();
+
+        let (lines, widths): (Box<dyn Iterator<Item = _>>, _) = if needs_rows {
+            let rows = entries
+                .by_ref()
+                .inspect(|(_, entry)| tally.add(entry))
+                .collect::<Vec<_>>();
+
+            let lines = if cmds.flags.condense {
+                condense(rows)
+            } else {
+                rows.into_iter()
+                    .map(|(remaining, entry)| Line::new(remaining, entry))
+                    .collect()
+            };
+
+            let infos = lines
+                .iter()
+                .map(|line| line.entry.get_additional_info(cmds, colors))
+                .collect::<Vec<_>>();
+
+            // the root line never shows metadata
+            let widths = column_widths(
+                lines
+                    .iter()
+                    .zip(&infos)
+                    .filter(|(line, _)| line.depth != 0)
+                    .map(|(_, info)| info.as_slice()),
+            );
+
+            (Box::new(lines.into_iter().zip(infos)), widths)
+        } else {
+            let lines = entries.by_ref().map(|(remaining, entry)| {
+                tally.add(&entry);
+                (Line::new(remaining, entry), vec![])
+            });
+
+            (Box::new(lines), vec![])
+        };
+
+        for (line, info) in lines {
+            let info = format_info(&info, &widths);
+            let entry = &line.entry;
+            let depth = &line.depth;
 
-        for (remaining, entry) in tree {
             let name = if cmds.flags.full_path {
-                String::from(entry.full_path().as_os_str().to_str().unwrap())
+                escaper.escape(entry.full_path().as_os_str())
             } else {
-                entry.get_name().map_or_else(
-                    || String::from("Failed to get name"),
-                    |name| {
-                        String::from(
-                            name.to_str().unwrap_or("Failed to get name"),
-                        )
-                    },
-                )
+                // a condensed chain is written as one path, a/b/c
+                let mut name = vec![];
+
+                for link in &line.chain {
+                    name.extend(
+                        escaper.escape(link.get_name().unwrap_or_default()),
+                    );
+                    name.push(b'/');
+                }
+
+                // roots like "." or "/" have no file name of their own
+                name.extend(escaper.escape(
+                    entry.get_name().unwrap_or(entry.path().as_os_str()),
+                ));
+
+                name
             };
 
-            let depth = entry.get_depth();
-
-            let (fg_bg, reset) =
-                Colors::get_color_esc_seq(entry.get_file_type());
-
-            let (recursion_detected, arrow_chars, linked_path) =
-                match entry.linked_path() {
-                    Some(path) if entry.is_recursive_link => (
-                        " [Recursion detected]",
-                        " -> ",
-                        path.as_os_str()
-                            .to_str()
-                            .unwrap_or("failed to get linked path"),
-                    ),
-                    Some(path) => (
-                        "",
-                        " -> ",
-                        path.as_os_str()
-                            .to_str()
-                            .unwrap_or("failed to get linked path"),
-                    ),
-                    None => ("", "", ""),
-                };
+            let (fg_bg, reset) = colors.get_color_esc_seq(
+                entry.get_file_type(),
+                &entry.get_name().unwrap_or_default().to_string_lossy(),
+            );
+
+            // reverse video after the color so the color survives it
+            let is_highlighted = line
+                .chain
+                .iter()
+                .chain([entry])
+                .any(|link| highlight.contains(link.path()));
+
+            let (fg_bg, reset) = if is_highlighted {
+                (format!("{fg_bg}\x1b[7m"), "\x1b[0m")
+            } else {
+                (fg_bg.to_owned(), reset)
+            };
+
+            // outside the colors so the whole colored name is the link
+            let (link_start, link_end) = hyperlinker
+                .as_ref()
+                .and_then(|hyperlinker| hyperlinker.link(entry.path()))
+                .unwrap_or_default();
 
-            if *depth != 0 {
-                if !entry.is_dir() && !entry.is_symlink() {
-                    file_count += 1;
-                } else if entry.is_dir() {
-                    dir_count += 1;
+            let identity = if cmds.flags.identify {
+                entry.get_identity_character()
+            } else {
+                ""
+            };
+
+            let (recursion_detected, arrow_chars, linked_path) = match entry
+                .linked_path()
+            {
+                Some(path) if entry.is_recursive_link => (
+                    " [Recursion detected]",
+                    " -> ",
+                    escaper.escape(path.as_os_str()),
+                ),
+                Some(path) if entry.is_orphan() => {
+                    let (missing, reset) = colors.get_missing_esc_seq();
+
+                    let mut linked_path = missing.as_bytes().to_vec();
+                    linked_path.extend(escaper.escape(path.as_os_str()));
+                    linked_path.extend(reset.as_bytes());
+
+                    ("", " -> ", linked_path)
                 }
-            }
+                Some(path) => ("", " -> ", escaper.escape(path.as_os_str())),
+                None => ("", "", vec![]),
+            };
+
+            let error_note = if entry.is_unreadable {
+                String::from(" [error opening dir]")
+            } else if let Some(count) = entry.exceeds_limit {
+                format!(" [{count} entries exceeds filelimit, not opening dir]")
+            } else {
+                String::new()
+            };
 
             if *depth == 0 {
-                for val in [fg_bg, name.as_str(), reset, "\n"] {
-                    ret.push_str(val);
+                for val in [
+                    &link_start,
+                    fg_bg.as_bytes(),
+                    &name,
+                    reset.as_bytes(),
+                    link_end,
+                    error_note.as_bytes(),
+                    b"\n",
+                ] {
+                    ret.extend_from_slice(val);
                 }
             } else if cmds.flags.no_indent {
                 for val in [
-                    fg_bg,
-                    name.as_str(),
-                    reset,
-                    recursion_detected,
-                    arrow_chars,
-                    linked_path,
-                    "\n",
+                    &link_start,
+                    fg_bg.as_bytes(),
+                    &name,
+                    reset.as_bytes(),
+                    link_end,
+                    identity.as_bytes(),
+                    recursion_detected.as_bytes(),
+                    error_note.as_bytes(),
+                    arrow_chars.as_bytes(),
+                    &linked_path,
+                    b"\n",
                 ] {
-                    ret.push_str(val);
+                    ret.extend_from_slice(val);
                 }
             } else {
+                if cmds.flags.meta_first {
+                    ret.extend_from_slice(info.as_bytes());
+                }
+
                 for level in 1..*depth {
                     let outer_connector = if has_remaining.contains(&level) {
-                        VERTICAL_PIPE
+                        connectors.vertical
                     } else {
-                        " "
+                        b" "
                     };
 
-                    ret.push_str(outer_connector);
-                    ret.push_str(DEFAULT_INDENT);
+                    ret.extend_from_slice(outer_connector);
+                    ret.extend_from_slice(DEFAULT_INDENT.as_bytes());
                 }
 
-                let connector = if remaining > 1 {
+                let connector = if line.remaining > 1 {
                     has_remaining.insert(*depth);
-                    T_RIGHT
+                    connectors.tee
                 } else {
                     has_remaining.remove(depth);
-                    L_RIGHT
+                    connectors.last
                 };
 
+                let info = if cmds.flags.meta_first { "" } else { &info };
+
                 for val in [
                     connector,
-                    NAME_CONNECTOR,
-                    " ",
-                    entry.get_additional_info(&cmds).as_str(),
-                    fg_bg,
-                    name.as_str(),
-                    reset,
-                    recursion_detected,
-                    arrow_chars,
-                    linked_path,
-                    "\n",
+                    connectors.horizontal,
+                    b" ",
+                    info.as_bytes(),
+                    &link_start,
+                    fg_bg.as_bytes(),
+                    &name,
+                    reset.as_bytes(),
+                    link_end,
+                    identity.as_bytes(),
+                    recursion_detected.as_bytes(),
+                    error_note.as_bytes(),
+                    arrow_chars.as_bytes(),
+                    &linked_path,
+                    b"\n",
                 ] {
-                    ret.push_str(val);
+                    ret.extend_from_slice(val);
                 }
             }
         }
 
-        println!("\n{ret}");
+        ret.push(b'\n');
+
+        let Tally {
+            mut report,
+            stats,
+            top,
+            dirs,
+            ..
+        } = tally;
+
+        if let Some(stats) = stats {
+            ret.extend(stats.render().as_bytes());
+            ret.push(b'\n');
+        }
+
+        if let Some(top) = top {
+            ret.extend(top.render().as_bytes());
+            ret.push(b'\n');
+        }
+
+        let walk_stats = entries.stats();
+        report.unreadable_count = walk_stats.unreadable;
+        report.hidden_count = walk_stats.hidden;
+        report.filtered_count = walk_stats.filtered;
+        report.elapsed = started.elapsed();
 
+        Rendered {
+            output: ret,
+            report,
+            dirs,
+        }
+    }
+
+    pub fn print_report(report: Report, cmds: &Cmd, colors: &Colors) {
         if !cmds.flags.no_report {
-            println!(
-                "Total directories: {dir_count} Total files: {file_count}\n"
+            let report = format_report(&report, &cmds.flags, colors);
+            write_stdout(format!("{report}\n").as_bytes());
+        }
+    }
+}
+
+// Writes to stdout, exiting quietly once the reader has gone away, as
+// with `tree | head`.
+pub fn write_stdout(bytes: &[u8]) {
+    let mut stdout = std::io::stdout().lock();
+
+    if let Err(err) = stdout.write_all(bytes).and_then(|()| stdout.flush()) {
+        if err.kind() == ErrorKind::BrokenPipe {
+            std::process::exit(0);
+        }
+
+        eprintln!("tree: failed to write to stdout - {err}");
+        std::process::exit(2);
+    }
+}
+
+// "N directories, M files" like GNU tree, or with --report=full a few
+// more lines with everything else the walk counted.
+pub fn format_report(
+    report: &Report,
+    flags: &Flags,
+    colors: &Colors,
+) -> String {
+    let count = |count: usize, one: &str, many: &str| {
+        let noun = if count == 1 { one } else { many };
+        format!(
+            "{} {noun}",
+            colors.paint("report_count", &count.to_string())
+        )
+    };
+
+    let mut lines = vec![if flags.dirs {
+        count(report.dir_count, "directory", "directories")
+    } else {
+        format!(
+            "{}, {}",
+            count(report.dir_count, "directory", "directories"),
+            count(report.file_count, "file", "files")
+        )
+    }];
+
+    if flags.report == ReportStyle::Full {
+        lines.push(count(report.link_count, "symlink", "symlinks"));
+
+        if flags.size || flags.human_readable_size {
+            lines.push(format!(
+                "{} bytes",
+                colors.paint("report_count", &report.total_bytes.to_string())
+            ));
+        }
+
+        lines.push(format!(
+            "{}, {}, {}",
+            count(report.hidden_count, "hidden", "hidden"),
+            count(report.filtered_count, "filtered", "filtered"),
+            count(report.unreadable_count, "unreadable", "unreadable")
+        ));
+        lines.push(format!("walked in {:.2?}", report.elapsed));
+    }
+
+    lines.join("\n")
+}
+
+// One printed line. With --condense a line can stand for a chain of
+// directories that each hold nothing but the next: `chain` has all but
+// the last of them, which is `entry`, and `depth` is where it's drawn.
+struct Line {
+    remaining: usize,
+    depth: usize,
+    chain: Vec<DirEntry>,
+    entry: DirEntry,
+}
+
+impl Line {
+    fn new(remaining: usize, entry: DirEntry) -> Self {
+        Self {
+            remaining,
+            depth: entry.depth,
+            chain: vec![],
+            entry,
+        }
+    }
+}
+
+// What the report, --stats, --top and --watch keep of each entry, so the
+// rows themselves can go once they're drawn.
+struct Tally<'c> {
+    cmds: &'c Cmd,
+    report: Report,
+    stats: Option<Stats>,
+    top_dir: Option<String>,
+    top: Option<Top>,
+    dirs: Vec<PathBuf>,
+}
+
+impl<'c> Tally<'c> {
+    fn new(cmds: &'c Cmd) -> Self {
+        Self {
+            cmds,
+            report: Report::default(),
+            stats: cmds.flags.stats.then(Stats::default),
+            top_dir: None,
+            top: cmds
+                .flags
+                .top
+                .map(|limit| Top::new(cmds.flags.top_by, limit)),
+            dirs: vec![],
+        }
+    }
+
+    fn add(&mut self, entry: &DirEntry) {
+        let flags = &self.cmds.flags;
+        let depth = entry.get_depth();
+
+        let is_read_dir = entry.is_dir()
+            || (flags.follow_symlinks
+                && entry.links_to_dir
+                && !entry.is_recursive_link);
+
+        // directories past -L or --filelimit are listed but not read
+        if is_read_dir
+            && !entry.is_unreadable
+            && entry.exceeds_limit.is_none()
+            && flags.max_depth.is_none_or(|max| *depth <= max)
+        {
+            self.dirs.push(entry.path().to_path_buf());
+        }
+
+        // a file given as a root counts as a file, like GNU tree
+        if *depth != 0 || !entry.is_dir() {
+            // symlinks count as whatever they point at
+            if entry.is_dir() || entry.links_to_dir {
+                self.report.dir_count += 1;
+            } else {
+                self.report.file_count += 1;
+            }
+
+            if entry.is_symlink() {
+                self.report.link_count += 1;
+            }
+
+            self.report.total_bytes += entry.get_size();
+        }
+
+        // the root isn't ranked, nor counted against its parent
+        if let Some(top) = self.top.as_mut().filter(|_| *depth != 0) {
+            top.add(
+                entry.path(),
+                entry.get_file_type().is_regular(),
+                entry.get_size(),
+                entry.get_last_modified().as_secs(),
             );
         }
+
+        if let Some(stats) = self.stats.as_mut() {
+            let name = entry.get_clean_name();
+
+            if *depth == 1 {
+                self.top_dir = entry.is_dir().then(|| name.to_string());
+
+                if let Some(top_dir) = &self.top_dir {
+                    stats.add_top_dir(top_dir);
+                }
+            }
+
+            if entry.get_file_type().is_regular() {
+                stats.add_file(
+                    &name,
+                    self.top_dir.as_deref(),
+                    entry.get_size(),
+                    entry.get_last_modified().as_secs(),
+                );
+            }
+        }
+    }
+}
+
+// Folds every directory that is the only entry in its parent directory
+// into the parent's line, and moves everything below up by the number of
+// levels folded away. The root line is never folded into.
+fn condense(rows: Vec<(usize, DirEntry)>) -> Vec<Line> {
+    let mut lines: Vec<Line> = vec![];
+    // levels folded away above each depth, indexed by the entry's depth
+    let mut shifts = vec![0];
+
+    for (remaining, entry) in rows {
+        let depth = entry.depth;
+        shifts.truncate(depth + 1);
+        let shift = shifts[depth];
+
+        // rows come depth first, so a child straight after its parent
+        // with no siblings left is an only child
+        let is_only_child_dir = entry.is_dir()
+            && remaining == 1
+            && lines.last().is_some_and(|parent| {
+                parent.entry.is_dir()
+                    && parent.entry.depth != 0
+                    && parent.entry.depth + 1 == depth
+            });
+
+        if is_only_child_dir {
+            let parent = lines.last_mut().expect("checked above");
+            parent
+                .chain
+                .push(std::mem::replace(&mut parent.entry, entry));
+            shifts.push(shift + 1);
+        } else {
+            lines.push(Line {
+                remaining,
+                depth: depth - shift,
+                chain: vec![],
+                entry,
+            });
+            shifts.push(shift);
+        }
+    }
+
+    lines
+}
+
+// The widest text in each metadata column. Every entry has the same
+// columns, since they're picked by flags.
+pub fn column_widths<'a, I>(rows: I) -> Vec<usize>
+where
+    I: Iterator<Item = &'a [InfoColumn]>,
+{
+    let mut widths: Vec<usize> = vec![];
+
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.text.chars().count());
+        }
+    }
+
+    widths
+}
+
+// The `[..] ` block, numbers right-aligned and everything else left.
+pub fn format_info(columns: &[InfoColumn], widths: &[usize]) -> String {
+    if columns.is_empty() {
+        return String::new();
+    }
+
+    let padded = columns
+        .iter()
+        .zip(widths)
+        .map(|(column, width)| {
+            // rows left out of the widths, like the root, may be wider
+            let padding =
+                " ".repeat(width.saturating_sub(column.text.chars().count()));
+
+            if column.right_align {
+                format!("{padding}{}", column.painted)
+            } else {
+                format!("{}{padding}", column.painted)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    format!("[{}] ", padded.join(" "))
+}
+
+#[cfg(test)]
+mod display_tests {
+    use super::*;
+    use crate::core::dirent::Metadata;
+
+    #[test]
+    fn formats_short_report() {
+        let mut report = Report {
+            dir_count: 1,
+            file_count: 3,
+            ..Report::default()
+        };
+        let mut flags = Flags::default();
+        let colors = Colors::default();
+
+        assert_eq!(
+            format_report(&report, &flags, &colors),
+            "1 directory, 3 files"
+        );
+
+        report.dir_count = 4;
+        report.file_count = 1;
+
+        assert_eq!(
+            format_report(&report, &flags, &colors),
+            "4 directories, 1 file"
+        );
+
+        flags.dirs = true;
+
+        assert_eq!(format_report(&report, &flags, &colors), "4 directories");
+    }
+
+    #[test]
+    fn formats_full_report() {
+        let report = Report {
+            dir_count: 2,
+            file_count: 5,
+            link_count: 1,
+            total_bytes: 2048,
+            unreadable_count: 1,
+            hidden_count: 3,
+            filtered_count: 0,
+            elapsed: Duration::from_millis(12),
+        };
+        let mut flags = Flags {
+            report: ReportStyle::Full,
+            ..Flags::default()
+        };
+        let colors = Colors::default();
+
+        assert_eq!(
+            format_report(&report, &flags, &colors),
+            "2 directories, 5 files\n\
+             1 symlink\n\
+             3 hidden, 0 filtered, 1 unreadable\n\
+             walked in 12.00ms"
+        );
+
+        flags.size = true;
+
+        assert!(
+            format_report(&report, &flags, &colors).contains("\n2048 bytes\n")
+        );
+    }
+
+    #[test]
+    fn aligns_info_columns() {
+        let column = |text: &str, right_align| InfoColumn {
+            text: text.to_owned(),
+            painted: text.to_owned(),
+            right_align,
+        };
+
+        let rows = [
+            vec![column("drwxr-xr-x", false), column("0 B", true)],
+            vec![column("-rw-r--r--", false), column("1024 B", true)],
+        ];
+
+        let widths = column_widths(rows.iter().map(Vec::as_slice));
+
+        assert_eq!(widths, vec![10, 6]);
+        assert_eq!(format_info(&rows[0], &widths), "[drwxr-xr-x    0 B] ");
+        assert_eq!(format_info(&[], &widths), "");
+    }
+
+    #[test]
+    fn formats_rows_wider_than_the_widths() {
+        let column = |text: &str| InfoColumn {
+            text: text.to_owned(),
+            painted: text.to_owned(),
+            right_align: true,
+        };
+
+        // the root isn't measured, and its size can be the widest
+        let root = vec![column("123456789")];
+        let children = [vec![column("12")], vec![column("3")]];
+
+        let widths = column_widths(children.iter().map(Vec::as_slice));
+
+        assert_eq!(format_info(&root, &widths), "[123456789] ");
+        assert_eq!(format_info(&children[1], &widths), "[ 3] ");
+    }
+
+    #[test]
+    fn condenses_single_child_dir_chains() {
+        let entry = |path: &str, metadata: fn() -> Metadata| {
+            let depth = path.matches('/').count();
+            DirEntry::from_virtual(path.into(), depth, metadata(), None)
+        };
+
+        // root/a/b/c/file and root/a/b/c/d/x, then root/e/file
+        let rows = vec![
+            (1, entry("root", Metadata::directory)),
+            (2, entry("root/a", Metadata::directory)),
+            (1, entry("root/a/b", Metadata::directory)),
+            (1, entry("root/a/b/c", Metadata::directory)),
+            (2, entry("root/a/b/c/file", Metadata::file)),
+            (1, entry("root/a/b/c/d", Metadata::directory)),
+            (1, entry("root/a/b/c/d/x", Metadata::file)),
+            (1, entry("root/e", Metadata::directory)),
+            (1, entry("root/e/file", Metadata::file)),
+        ];
+
+        let lines = condense(rows)
+            .into_iter()
+            .map(|line| {
+                let names = line
+                    .chain
+                    .iter()
+                    .chain([&line.entry])
+                    .map(|entry| entry.get_clean_name().into_owned())
+                    .collect::<Vec<_>>();
+
+                (line.remaining, line.depth, names.join("/"))
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                (1, 0, String::from("root")),
+                (2, 1, String::from("a/b/c")),
+                (2, 2, String::from("file")),
+                (1, 2, String::from("d")),
+                (1, 3, String::from("x")),
+                (1, 1, String::from("e")),
+                (1, 2, String::from("file")),
+            ]
+        );
     }
 }
@@ -30,6 +30,10 @@ impl<'path> Error<'path> {
             inner: ErrorInner::Io { path, related },
         }
     }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
 }
 
 impl<'path> fmt::Display for Error<'path> {
@@ -57,10 +61,9 @@ impl<'path> fmt::Display for Error<'path> {
 impl From<Error<'_>> for std::io::Error {
     fn from(err: Error) -> Self {
         match err.inner {
-            ErrorInner::Io { path, .. } => io::Error::new(
-                io::ErrorKind::Other,
-                path.to_str().unwrap_or("path error"),
-            ),
+            ErrorInner::Io { path, .. } => {
+                io::Error::other(path.to_str().unwrap_or("path error"))
+            }
         }
     }
 }
@@ -0,0 +1,170 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::cli::Flags;
+
+// How bytes that can't be shown as-is are written out.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Unprintable {
+    // control characters become '?', invalid UTF-8 is passed through so
+    // non-UTF-8 names are written losslessly
+    #[default]
+    Default,
+    // -q: control characters and invalid UTF-8 both become '?'
+    QuestionMark,
+    // -N: every byte is written untouched
+    AsIs,
+    // -b: control characters and invalid UTF-8 become \ooo
+    Octal,
+}
+
+// Turns file names into the bytes Display writes. Names are raw bytes on
+// unix, so this works on OsStr and never fails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NameEscaper {
+    unprintable: Unprintable,
+    quote: bool,
+}
+
+impl NameEscaper {
+    pub fn new(unprintable: Unprintable, quote: bool) -> Self {
+        Self { unprintable, quote }
+    }
+
+    // -b wins over -q, which wins over -N.
+    pub fn from_flags(flags: &Flags) -> Self {
+        let unprintable = if flags.octal_escape {
+            Unprintable::Octal
+        } else if flags.unprintable_question_mark {
+            Unprintable::QuestionMark
+        } else if flags.unprintable_as_is {
+            Unprintable::AsIs
+        } else {
+            Unprintable::Default
+        };
+
+        Self::new(unprintable, flags.quote_names)
+    }
+
+    pub fn escape(&self, name: &OsStr) -> Vec<u8> {
+        let mut escaped = Vec::with_capacity(name.len() + 2);
+
+        if self.quote {
+            escaped.push(b'"');
+        }
+
+        for chunk in name.as_bytes().utf8_chunks() {
+            for ch in chunk.valid().chars() {
+                self.push_char(&mut escaped, ch);
+            }
+
+            for &byte in chunk.invalid() {
+                match self.unprintable {
+                    Unprintable::Default | Unprintable::AsIs => {
+                        escaped.push(byte)
+                    }
+                    Unprintable::QuestionMark => escaped.push(b'?'),
+                    Unprintable::Octal => push_octal(&mut escaped, byte),
+                }
+            }
+        }
+
+        if self.quote {
+            escaped.push(b'"');
+        }
+
+        escaped
+    }
+
+    fn push_char(&self, escaped: &mut Vec<u8>, ch: char) {
+        let mut utf8 = [0; 4];
+
+        match ch {
+            '"' if self.quote => escaped.extend(b"\\\""),
+            '\\' if self.unprintable == Unprintable::Octal || self.quote => {
+                escaped.extend(b"\\\\")
+            }
+            ch if ch.is_control() => match self.unprintable {
+                Unprintable::AsIs => {
+                    escaped.extend(ch.encode_utf8(&mut utf8).as_bytes())
+                }
+                Unprintable::Default | Unprintable::QuestionMark => {
+                    escaped.push(b'?')
+                }
+                Unprintable::Octal => {
+                    for &byte in ch.encode_utf8(&mut utf8).as_bytes() {
+                        push_octal(escaped, byte);
+                    }
+                }
+            },
+            ch => escaped.extend(ch.encode_utf8(&mut utf8).as_bytes()),
+        }
+    }
+}
+
+fn push_octal(escaped: &mut Vec<u8>, byte: u8) {
+    escaped.extend(format!("\\{byte:03o}").as_bytes());
+}
+
+#[cfg(test)]
+mod escape_tests {
+    use super::*;
+
+    fn escape(unprintable: Unprintable, quote: bool, name: &[u8]) -> Vec<u8> {
+        NameEscaper::new(unprintable, quote).escape(OsStr::from_bytes(name))
+    }
+
+    #[test]
+    fn leaves_printable_names_alone() {
+        for unprintable in [
+            Unprintable::Default,
+            Unprintable::QuestionMark,
+            Unprintable::AsIs,
+            Unprintable::Octal,
+        ] {
+            assert_eq!(
+                escape(unprintable, false, "café.rs".as_bytes()),
+                "café.rs".as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn handles_control_characters() {
+        let name = b"a\tb\x1b[31m";
+
+        assert_eq!(escape(Unprintable::Default, false, name), b"a?b?[31m");
+        assert_eq!(escape(Unprintable::QuestionMark, false, name), b"a?b?[31m");
+        assert_eq!(escape(Unprintable::AsIs, false, name), name);
+        assert_eq!(
+            escape(Unprintable::Octal, false, name),
+            b"a\\011b\\033[31m"
+        );
+    }
+
+    #[test]
+    fn handles_invalid_utf8_losslessly() {
+        let name = b"bad\xff\xfe.txt";
+
+        assert_eq!(escape(Unprintable::Default, false, name), name);
+        assert_eq!(escape(Unprintable::AsIs, false, name), name);
+        assert_eq!(
+            escape(Unprintable::QuestionMark, false, name),
+            b"bad??.txt"
+        );
+        assert_eq!(
+            escape(Unprintable::Octal, false, name),
+            b"bad\\377\\376.txt"
+        );
+    }
+
+    #[test]
+    fn quotes_names() {
+        assert_eq!(
+            escape(Unprintable::Default, true, b"say \"hi\"\\"),
+            b"\"say \\\"hi\\\"\\\\\""
+        );
+        assert_eq!(escape(Unprintable::Octal, false, b"a\\b"), b"a\\\\b");
+        assert_eq!(escape(Unprintable::Default, false, b"a\\b"), b"a\\b");
+    }
+}
@@ -0,0 +1,189 @@
+use super::dirent::{
+    S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFSOCK,
+};
+
+pub const S_ISUID: u32 = 0o4000;
+pub const S_ISGID: u32 = 0o2000;
+pub const S_ISVTX: u32 = 0o1000;
+
+// Everything tree distinguishes about an entry's type. Colors, -F and the
+// -p permission string are all derived from this.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileType {
+    Directory,
+    // other-writable directories, with and without the sticky bit
+    StickyOtherWritable,
+    OtherWritable,
+    Symlink,
+    // a symlink whose target doesn't exist
+    Orphan,
+    Socket,
+    Pipe,
+    BlockDevice,
+    CharDevice,
+    SetUid,
+    SetGid,
+    Executable,
+    MultiHardLink,
+    File,
+}
+
+impl FileType {
+    pub fn from_mode(mode: u32, nlink: u64, is_orphan: bool) -> Self {
+        match mode & S_IFMT {
+            S_IFDIR if mode & (S_ISVTX | 0o002) == S_ISVTX | 0o002 => {
+                FileType::StickyOtherWritable
+            }
+            S_IFDIR if mode & 0o002 != 0 => FileType::OtherWritable,
+            S_IFDIR => FileType::Directory,
+            S_IFLNK if is_orphan => FileType::Orphan,
+            S_IFLNK => FileType::Symlink,
+            S_IFSOCK => FileType::Socket,
+            S_IFIFO => FileType::Pipe,
+            S_IFBLK => FileType::BlockDevice,
+            S_IFCHR => FileType::CharDevice,
+            _ if mode & S_ISUID != 0 => FileType::SetUid,
+            _ if mode & S_ISGID != 0 => FileType::SetGid,
+            _ if mode & 0o111 != 0 => FileType::Executable,
+            _ if nlink > 1 => FileType::MultiHardLink,
+            _ => FileType::File,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        matches!(
+            self,
+            FileType::Directory
+                | FileType::StickyOtherWritable
+                | FileType::OtherWritable
+        )
+    }
+
+    // Regular files, whatever their permission bits or link count.
+    pub fn is_regular(&self) -> bool {
+        matches!(
+            self,
+            FileType::SetUid
+                | FileType::SetGid
+                | FileType::Executable
+                | FileType::MultiHardLink
+                | FileType::File
+        )
+    }
+
+    // The name Colors stores this type's LS_COLORS entry under.
+    pub fn color_key(&self) -> &'static str {
+        match self {
+            FileType::Directory => "directory",
+            FileType::StickyOtherWritable => "dwo_sticky",
+            FileType::OtherWritable => "dwo_non_sticky",
+            FileType::Symlink => "sym_link",
+            FileType::Orphan => "orphan",
+            FileType::Socket => "socket",
+            FileType::Pipe => "pipe",
+            FileType::BlockDevice => "special_block",
+            FileType::CharDevice => "special_char",
+            FileType::SetUid => "exe_set_uid",
+            FileType::SetGid => "exe_set_gid",
+            FileType::Executable => "executable",
+            FileType::MultiHardLink => "multi_hard_link",
+            FileType::File => "file",
+        }
+    }
+
+    // The first character of an `ls -l` style permission string.
+    pub fn mode_char(&self) -> char {
+        match self {
+            _ if self.is_dir() => 'd',
+            FileType::Symlink | FileType::Orphan => 'l',
+            FileType::Socket => 's',
+            FileType::Pipe => 'p',
+            FileType::BlockDevice => 'b',
+            FileType::CharDevice => 'c',
+            _ => '-',
+        }
+    }
+}
+
+// rwx triplets for user, group and other, with setuid, setgid and sticky
+// shown in the execute slots as s/S and t/T like ls does.
+pub fn permissions_string(file_type: FileType, mode: u32) -> String {
+    let special = |bit: u32, exec: u32, set: char| match (
+        mode & bit != 0,
+        mode & exec != 0,
+    ) {
+        (true, true) => set,
+        (true, false) => set.to_ascii_uppercase(),
+        (false, true) => 'x',
+        (false, false) => '-',
+    };
+
+    let flag = |bit: u32, set: char| if mode & bit != 0 { set } else { '-' };
+
+    [
+        file_type.mode_char(),
+        flag(0o400, 'r'),
+        flag(0o200, 'w'),
+        special(S_ISUID, 0o100, 's'),
+        flag(0o040, 'r'),
+        flag(0o020, 'w'),
+        special(S_ISGID, 0o010, 's'),
+        flag(0o004, 'r'),
+        flag(0o002, 'w'),
+        special(S_ISVTX, 0o001, 't'),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[cfg(test)]
+mod file_type_tests {
+    use super::*;
+    use crate::core::dirent::S_IFREG;
+
+    #[test]
+    fn classifies_modes() {
+        let cases = [
+            (S_IFDIR | 0o755, FileType::Directory),
+            (S_IFDIR | 0o1777, FileType::StickyOtherWritable),
+            (S_IFDIR | 0o777, FileType::OtherWritable),
+            (S_IFLNK | 0o777, FileType::Symlink),
+            (S_IFSOCK | 0o755, FileType::Socket),
+            (S_IFIFO | 0o644, FileType::Pipe),
+            (S_IFBLK | 0o660, FileType::BlockDevice),
+            (S_IFCHR | 0o620, FileType::CharDevice),
+            (S_IFREG | 0o4755, FileType::SetUid),
+            (S_IFREG | 0o2755, FileType::SetGid),
+            (S_IFREG | 0o755, FileType::Executable),
+            (S_IFREG | 0o644, FileType::File),
+        ];
+
+        for (mode, expected) in cases {
+            assert_eq!(FileType::from_mode(mode, 1, false), expected);
+        }
+
+        assert_eq!(
+            FileType::from_mode(S_IFREG | 0o644, 2, false),
+            FileType::MultiHardLink
+        );
+        assert_eq!(
+            FileType::from_mode(S_IFLNK | 0o777, 1, true),
+            FileType::Orphan
+        );
+    }
+
+    #[test]
+    fn formats_permissions() {
+        let perms = |mode: u32| {
+            permissions_string(FileType::from_mode(mode, 1, false), mode)
+        };
+
+        assert_eq!(perms(S_IFREG | 0o644), "-rw-r--r--");
+        assert_eq!(perms(S_IFREG | 0o4755), "-rwsr-xr-x");
+        assert_eq!(perms(S_IFREG | 0o2644), "-rw-r-Sr--");
+        assert_eq!(perms(S_IFDIR | 0o1777), "drwxrwxrwt");
+        assert_eq!(perms(S_IFDIR | 0o1770), "drwxrwx--T");
+        assert_eq!(perms(S_IFBLK | 0o660), "brw-rw----");
+        assert_eq!(perms(S_IFCHR | 0o620), "crw--w----");
+    }
+}
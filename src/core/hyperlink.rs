@@ -0,0 +1,116 @@
+use std::ffi::CStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+const OSC8_END: &[u8] = b"\x1b]8;;\x1b\\";
+
+// Wraps names in OSC 8 escapes pointing at file://<host><path>, which
+// terminals that support them make clickable.
+#[derive(Debug, Default)]
+pub struct Hyperlinker {
+    host: String,
+}
+
+impl Hyperlinker {
+    pub fn new() -> Self {
+        Self { host: hostname() }
+    }
+
+    // The escapes to write before and after a name, or None for entries
+    // that aren't on disk (archives, --fromfile listings).
+    pub fn link(&self, path: &Path) -> Option<(Vec<u8>, &'static [u8])> {
+        let path = canonical_path(path)?;
+
+        let mut start = b"\x1b]8;;file://".to_vec();
+        start.extend(percent_encode(self.host.as_bytes()).as_bytes());
+        start.extend(percent_encode(path.as_os_str().as_bytes()).as_bytes());
+        start.extend(b"\x1b\\");
+
+        Some((start, OSC8_END))
+    }
+}
+
+// The path with every ancestor resolved but the entry itself left alone,
+// so a symlink links to the link rather than to its target.
+fn canonical_path(path: &Path) -> Option<PathBuf> {
+    fs::symlink_metadata(path).ok()?;
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            Some(parent.canonicalize().ok()?.join(name))
+        }
+        (_, Some(name)) => Some(std::env::current_dir().ok()?.join(name)),
+        // "/", "." and ".." have no name of their own
+        _ => path.canonicalize().ok(),
+    }
+}
+
+fn hostname() -> String {
+    let mut buf = [0_u8; 256];
+
+    // SAFETY: the buffer is writable for its whole length, and the last
+    // byte is never handed over so the result is always NUL terminated
+    let ret =
+        unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len() - 1) };
+
+    if ret != 0 {
+        return String::new();
+    }
+
+    CStr::from_bytes_until_nul(&buf)
+        .map(|host| host.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// RFC 3986 unreserved characters and '/' pass through, every other byte
+// (including any that aren't UTF-8) becomes %XX.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod hyperlink_tests {
+    use super::*;
+
+    #[test]
+    fn percent_encodes_paths() {
+        assert_eq!(percent_encode(b"/tmp/a-b_c.~d"), "/tmp/a-b_c.~d");
+        assert_eq!(percent_encode(b"/tmp/my file#1"), "/tmp/my%20file%231");
+        assert_eq!(percent_encode("/café".as_bytes()), "/caf%C3%A9");
+        assert_eq!(percent_encode(b"/bad\xff"), "/bad%FF");
+    }
+
+    #[test]
+    fn links_entries_on_disk() {
+        let linker = Hyperlinker {
+            host: String::from("my host"),
+        };
+
+        let (start, end) =
+            linker.link(Path::new("tests/mock_dir/A.rs")).unwrap();
+        let start = String::from_utf8(start).unwrap();
+
+        assert!(start.starts_with("\x1b]8;;file://my%20host/"));
+        assert!(start.ends_with("/tests/mock_dir/A.rs\x1b\\"));
+        assert_eq!(end, OSC8_END);
+
+        assert_eq!(linker.link(Path::new("tests/mock_dir/missing")), None);
+    }
+}
@@ -1,6 +1,22 @@
+pub mod archive;
+pub mod charset;
 pub mod colors;
+pub mod compare;
+pub mod diff;
 mod dirent;
 pub mod display;
 mod error;
+pub mod escape;
+mod file_type;
+pub mod hyperlink;
 pub mod pattern;
+pub mod predicate;
+pub mod snapshot;
+pub mod source;
+pub mod stats;
+mod time;
+pub mod top;
 pub mod tree;
+pub mod tui;
+#[cfg(target_os = "linux")]
+pub mod watch;
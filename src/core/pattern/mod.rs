@@ -1,5 +1,19 @@
 use std::collections::HashSet;
 
+pub mod regex;
+
+// Name filters applied by TreeIterator. An inclusive filter keeps the
+// entries it matches, an exclusive one keeps everything else.
+pub trait Filter {
+    fn is_match(&self, value: &str) -> bool;
+
+    fn is_inclusive(&self) -> bool;
+
+    fn keep(&self, value: &str) -> bool {
+        self.is_match(value) == self.is_inclusive()
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum PatternType {
     OneOrMore,
@@ -26,7 +40,7 @@ impl Pattern {
             match ch {
                 '*' => mapped_pattern.push(PatternType::OneOrMore),
                 '?' => mapped_pattern.push(PatternType::One),
-                '[' if !active_group.is_some() => active_group = Some(vec![]),
+                '[' if active_group.is_none() => active_group = Some(vec![]),
                 ']' => {
                     let char_set = active_group
                         .take()
@@ -148,6 +162,16 @@ impl Pattern {
     }
 }
 
+impl Filter for Pattern {
+    fn is_match(&self, value: &str) -> bool {
+        self.is_match(value)
+    }
+
+    fn is_inclusive(&self) -> bool {
+        self.inclusive
+    }
+}
+
 #[cfg(test)]
 mod pattern_parsing_tests {
     use super::*;
@@ -223,7 +247,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("*", true);
 
         let is_match = pattern.is_match("abc");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -231,7 +255,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("abc*", true);
 
         let is_match = pattern.is_match("abc");
-        assert!(is_match == false);
+        assert!(!is_match);
     }
 
     #[test]
@@ -239,7 +263,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a*c", true);
 
         let is_match = pattern.is_match("a_b_l_j_k_c");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -247,7 +271,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a_b*", true);
 
         let is_match = pattern.is_match("a_b_l_j_k_c");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -255,7 +279,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a[bljk_]c", true);
 
         let is_match = pattern.is_match("a_c");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -263,7 +287,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a[bljk_-]c", true);
 
         let is_match = pattern.is_match("a-c");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -271,7 +295,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a[b-k]c", true);
 
         let is_match = pattern.is_match("ajc");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -279,7 +303,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a[b-k|0-9]c", true);
 
         let is_match = pattern.is_match("a7c");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -287,7 +311,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a[b-k]c", true);
 
         let is_match = pattern.is_match("alc");
-        assert!(is_match == false);
+        assert!(!is_match);
     }
 
     #[test]
@@ -295,7 +319,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a![bljk_]c", true);
 
         let is_match = pattern.is_match("a_c");
-        assert!(is_match == false);
+        assert!(!is_match);
     }
 
     #[test]
@@ -303,7 +327,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a![b-k]c", true);
 
         let is_match = pattern.is_match("ajc");
-        assert!(is_match == false);
+        assert!(!is_match);
     }
 
     #[test]
@@ -311,7 +335,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("a![b-k]c", true);
 
         let is_match = pattern.is_match("alc");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -319,7 +343,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("ctx-[a-c]??_t*", true);
 
         let is_match = pattern.is_match("ctx-bcc_trest");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -327,7 +351,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("ctx-*-[a-c]??_t*", true);
 
         let is_match = pattern.is_match("ctx-qrs-bcc_trest");
-        assert!(is_match == true);
+        assert!(is_match);
     }
 
     #[test]
@@ -335,7 +359,7 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("ctx-*-[a-c]??_t*", true);
 
         let is_match = pattern.is_match("ctx-qrsbcc_trest-");
-        assert!(is_match == false);
+        assert!(!is_match);
     }
 
     #[test]
@@ -343,6 +367,6 @@ mod pattern_matching_tests {
         let pattern = Pattern::parse("ctx-[a-c]??_t*", true);
 
         let is_match = pattern.is_match("ctx-bcc_t");
-        assert!(is_match == false);
+        assert!(!is_match);
     }
 }
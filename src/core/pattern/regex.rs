@@ -0,0 +1,780 @@
+use std::fmt;
+
+use super::Filter;
+
+const MAX_REPEAT: u32 = 1000;
+// Repeats are compiled as copies, so nested ones multiply; this caps the
+// whole program rather than each count.
+const MAX_PROGRAM: usize = 100_000;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RegexError {
+    TrailingEscape,
+    UnbalancedParen,
+    UnclosedClass,
+    InvalidRange(char, char),
+    InvalidRepeat,
+    NothingToRepeat(char),
+    TooLarge,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexError::TrailingEscape => write!(f, "pattern ends with '\\'"),
+            RegexError::UnbalancedParen => write!(f, "unbalanced parenthesis"),
+            RegexError::UnclosedClass => {
+                write!(f, "character class is missing a closing ']'")
+            }
+            RegexError::InvalidRange(start, end) => {
+                write!(f, "invalid character range {start}-{end}")
+            }
+            RegexError::InvalidRepeat => {
+                write!(f, "invalid repetition count (max {MAX_REPEAT})")
+            }
+            RegexError::NothingToRepeat(ch) => {
+                write!(f, "'{ch}' does not follow anything to repeat")
+            }
+            RegexError::TooLarge => {
+                write!(f, "pattern is too large once repetitions are expanded")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn new(ranges: Vec<(char, char)>, negated: bool) -> Self {
+        Self { ranges, negated }
+    }
+
+    fn digit() -> Vec<(char, char)> {
+        vec![('0', '9')]
+    }
+
+    fn word() -> Vec<(char, char)> {
+        vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]
+    }
+
+    fn space() -> Vec<(char, char)> {
+        vec![('\t', '\r'), (' ', ' ')]
+    }
+
+    // Complement of a set of ranges, used for \D, \W and \S inside brackets.
+    fn invert(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+        ranges.sort();
+
+        let mut inverted = vec![];
+        let mut next = 0_u32;
+
+        for (start, end) in ranges {
+            if (start as u32) > next {
+                inverted.push((next, start as u32 - 1));
+            }
+            next = next.max(end as u32 + 1);
+        }
+
+        if next <= char::MAX as u32 {
+            inverted.push((next, char::MAX as u32));
+        }
+
+        // Surrogates aren't valid chars, so clamp each bound around them.
+        inverted
+            .into_iter()
+            .filter_map(|(start, end)| {
+                let start = char::from_u32(start).unwrap_or('\u{E000}');
+                let end = char::from_u32(end).unwrap_or('\u{D7FF}');
+                (start <= end).then_some((start, end))
+            })
+            .collect()
+    }
+
+    fn matches(&self, ch: char) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| start <= ch && ch <= end)
+            != self.negated
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(CharClass),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek();
+        self.pos += 1;
+        ch
+    }
+
+    fn parse(pattern: &str) -> Result<Node, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+
+        let node = parser.parse_alternation()?;
+
+        // The only thing that stops a top level alternation early is a ')'
+        // without a matching '('.
+        if parser.peek().is_some() {
+            return Err(RegexError::UnbalancedParen);
+        }
+
+        Ok(node)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+
+        while self.peek() == Some('|') {
+            self.next();
+            branches.push(self.parse_concat()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Node::Alternate(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = vec![];
+
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+
+            nodes.push(self.parse_repeat()?);
+        }
+
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.parse_atom()?;
+
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => {
+                        // parse_counted already consumed the braces
+                        self.pos -= 1;
+                        bounds
+                    }
+                    None => break,
+                },
+                _ => break,
+            };
+
+            self.next();
+
+            // Lazy quantifiers only change which match is reported, which
+            // doesn't matter when all we answer is "does it match".
+            if self.peek() == Some('?') {
+                self.next();
+            }
+
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            };
+        }
+
+        Ok(node)
+    }
+
+    // Parses {n}, {n,} and {n,m}. Anything else starting with '{' is left
+    // alone and treated as a literal brace.
+    fn parse_counted(
+        &mut self,
+    ) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let rest = self.chars[self.pos..].iter().collect::<String>();
+
+        let Some(close) = rest.find('}') else {
+            return Ok(None);
+        };
+
+        let body = &rest[1..close];
+
+        let parse_count = |value: &str| -> Option<u32> {
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+                None
+            } else {
+                value.parse::<u32>().ok()
+            }
+        };
+
+        let bounds = match body.split_once(',') {
+            None => parse_count(body).map(|count| (count, Some(count))),
+            Some((min, "")) => parse_count(min).map(|min| (min, None)),
+            Some((min, max)) => parse_count(min)
+                .zip(parse_count(max))
+                .map(|(min, max)| (min, Some(max))),
+        };
+
+        let Some((min, max)) = bounds else {
+            return Ok(None);
+        };
+
+        if min > MAX_REPEAT
+            || max.is_some_and(|max| max < min || max > MAX_REPEAT)
+        {
+            return Err(RegexError::InvalidRepeat);
+        }
+
+        self.pos += body.chars().count() + 2;
+
+        Ok(Some((min, max)))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let ch = self.next().expect("parse_atom called at end of pattern");
+
+        match ch {
+            '(' => {
+                // Groups never capture, so (?:...) is the same thing.
+                if self.peek() == Some('?')
+                    && self.chars.get(self.pos + 1) == Some(&':')
+                {
+                    self.pos += 2;
+                }
+
+                let node = self.parse_alternation()?;
+
+                if self.next() != Some(')') {
+                    return Err(RegexError::UnbalancedParen);
+                }
+
+                Ok(node)
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '*' | '+' | '?' => Err(RegexError::NothingToRepeat(ch)),
+            '\\' => {
+                let escaped = self.next().ok_or(RegexError::TrailingEscape)?;
+
+                Ok(match escaped {
+                    'd' => {
+                        Node::Class(CharClass::new(CharClass::digit(), false))
+                    }
+                    'D' => {
+                        Node::Class(CharClass::new(CharClass::digit(), true))
+                    }
+                    'w' => {
+                        Node::Class(CharClass::new(CharClass::word(), false))
+                    }
+                    'W' => Node::Class(CharClass::new(CharClass::word(), true)),
+                    's' => {
+                        Node::Class(CharClass::new(CharClass::space(), false))
+                    }
+                    'S' => {
+                        Node::Class(CharClass::new(CharClass::space(), true))
+                    }
+                    _ => Node::Literal(Self::unescape(escaped)),
+                })
+            }
+            _ => Ok(Node::Literal(ch)),
+        }
+    }
+
+    fn unescape(ch: char) -> char {
+        match ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            _ => ch,
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.peek() == Some('^');
+
+        if negated {
+            self.next();
+        }
+
+        let mut ranges = vec![];
+        let mut first = true;
+
+        loop {
+            let ch = self.next().ok_or(RegexError::UnclosedClass)?;
+
+            let start = match ch {
+                ']' if !first => break,
+                '\\' => {
+                    let escaped =
+                        self.next().ok_or(RegexError::UnclosedClass)?;
+
+                    let shorthand = match escaped {
+                        'd' => Some(CharClass::digit()),
+                        'D' => Some(CharClass::invert(CharClass::digit())),
+                        'w' => Some(CharClass::word()),
+                        'W' => Some(CharClass::invert(CharClass::word())),
+                        's' => Some(CharClass::space()),
+                        'S' => Some(CharClass::invert(CharClass::space())),
+                        _ => None,
+                    };
+
+                    if let Some(mut shorthand) = shorthand {
+                        ranges.append(&mut shorthand);
+                        first = false;
+                        continue;
+                    }
+
+                    Self::unescape(escaped)
+                }
+                _ => ch,
+            };
+
+            first = false;
+
+            // A '-' right before the closing bracket is a literal hyphen.
+            let is_range = self.peek() == Some('-')
+                && !matches!(self.chars.get(self.pos + 1), Some(']') | None);
+
+            if is_range {
+                self.next();
+
+                let end = match self.next() {
+                    Some('\\') => Self::unescape(
+                        self.next().ok_or(RegexError::UnclosedClass)?,
+                    ),
+                    Some(end) => end,
+                    None => return Err(RegexError::UnclosedClass),
+                };
+
+                if end < start {
+                    return Err(RegexError::InvalidRange(start, end));
+                }
+
+                ranges.push((start, end));
+            } else {
+                ranges.push((start, start));
+            }
+        }
+
+        Ok(Node::Class(CharClass::new(ranges, negated)))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(CharClass),
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), RegexError> {
+    if program.len() > MAX_PROGRAM {
+        return Err(RegexError::TooLarge);
+    }
+
+    match node {
+        Node::Empty => (),
+        Node::Literal(ch) => program.push(Inst::Char(*ch)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alternate(branches) => {
+            let mut jumps = vec![];
+
+            for (idx, branch) in branches.iter().enumerate() {
+                if idx == branches.len() - 1 {
+                    compile(branch, program)?;
+                } else {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program)?;
+
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+            }
+
+            let end = program.len();
+
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program)?;
+                    }
+
+                    let end = program.len();
+
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Regex {
+    program: Vec<Inst>,
+    pub inclusive: bool,
+}
+
+impl Regex {
+    pub fn parse(
+        pattern: &str,
+        is_inclusive: bool,
+    ) -> Result<Self, RegexError> {
+        let mut program = vec![];
+
+        compile(&Parser::parse(pattern)?, &mut program)?;
+        program.push(Inst::Match);
+
+        Ok(Self {
+            program,
+            inclusive: is_inclusive,
+        })
+    }
+
+    // Follows every non-consuming instruction reachable from `pc`, adding the
+    // consuming ones to `threads`. Returns true as soon as Match is reachable.
+    fn add_thread(
+        &self,
+        threads: &mut Vec<usize>,
+        seen: &mut [bool],
+        pc: usize,
+        pos: usize,
+        len: usize,
+    ) -> bool {
+        let mut stack = vec![pc];
+
+        while let Some(pc) = stack.pop() {
+            if seen[pc] {
+                continue;
+            }
+
+            seen[pc] = true;
+
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(*to),
+                Inst::Split(first, second) => {
+                    stack.push(*second);
+                    stack.push(*first);
+                }
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => (),
+                _ => threads.push(pc),
+            }
+        }
+
+        false
+    }
+
+    // Runs the program as an NFA simulation over every start position at
+    // once, so matching is linear in the length of the name.
+    pub fn is_match(&self, value: &str) -> bool {
+        let chars = value.chars().collect::<Vec<char>>();
+        let len = chars.len();
+
+        let mut seen = vec![false; self.program.len()];
+        let mut current = vec![];
+
+        if self.add_thread(&mut current, &mut seen, 0, 0, len) {
+            return true;
+        }
+
+        for (pos, ch) in chars.iter().enumerate() {
+            let mut next = vec![];
+            seen.fill(false);
+
+            for &pc in &current {
+                let advance = match &self.program[pc] {
+                    Inst::Char(expected) => expected == ch,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(*ch),
+                    _ => false,
+                };
+
+                if advance
+                    && self.add_thread(
+                        &mut next,
+                        &mut seen,
+                        pc + 1,
+                        pos + 1,
+                        len,
+                    )
+                {
+                    return true;
+                }
+            }
+
+            // Matches aren't anchored, so a new attempt starts at every char.
+            if self.add_thread(&mut next, &mut seen, 0, pos + 1, len) {
+                return true;
+            }
+
+            current = next;
+        }
+
+        false
+    }
+}
+
+impl Filter for Regex {
+    fn is_match(&self, value: &str) -> bool {
+        self.is_match(value)
+    }
+
+    fn is_inclusive(&self) -> bool {
+        self.inclusive
+    }
+}
+
+#[cfg(test)]
+mod regex_parsing_tests {
+    use super::*;
+
+    #[test]
+    fn parses_literals() {
+        let result = Parser::parse("ab");
+        assert_eq!(
+            result,
+            Ok(Node::Concat(vec![Node::Literal('a'), Node::Literal('b')]))
+        )
+    }
+
+    #[test]
+    fn parses_alternation_in_group() {
+        let result = Parser::parse("(a|bc)");
+        assert_eq!(
+            result,
+            Ok(Node::Alternate(vec![
+                Node::Literal('a'),
+                Node::Concat(vec![Node::Literal('b'), Node::Literal('c')])
+            ]))
+        )
+    }
+
+    #[test]
+    fn parses_counted_repetition() {
+        let result = Parser::parse("a{2,3}");
+        assert_eq!(
+            result,
+            Ok(Node::Repeat {
+                node: Box::new(Node::Literal('a')),
+                min: 2,
+                max: Some(3)
+            })
+        )
+    }
+
+    #[test]
+    fn parses_unterminated_brace_as_literal() {
+        let result = Parser::parse("a{2");
+        assert_eq!(
+            result,
+            Ok(Node::Concat(vec![
+                Node::Literal('a'),
+                Node::Literal('{'),
+                Node::Literal('2')
+            ]))
+        )
+    }
+
+    #[test]
+    fn parses_class_with_ranges_and_trailing_hyphen() {
+        let result = Parser::parse("[^a-c_-]");
+        assert_eq!(
+            result,
+            Ok(Node::Class(CharClass::new(
+                vec![('a', 'c'), ('_', '_'), ('-', '-')],
+                true
+            )))
+        )
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert_eq!(
+            Parser::parse("(ab").err(),
+            Some(RegexError::UnbalancedParen)
+        );
+        assert_eq!(
+            Parser::parse("ab)").err(),
+            Some(RegexError::UnbalancedParen)
+        );
+        assert_eq!(Parser::parse("[ab").err(), Some(RegexError::UnclosedClass));
+        assert_eq!(
+            Parser::parse("[z-a]").err(),
+            Some(RegexError::InvalidRange('z', 'a'))
+        );
+        assert_eq!(
+            Parser::parse("a{3,1}").err(),
+            Some(RegexError::InvalidRepeat)
+        );
+        assert_eq!(
+            Parser::parse("*a").err(),
+            Some(RegexError::NothingToRepeat('*'))
+        );
+        assert_eq!(
+            Parser::parse("a\\").err(),
+            Some(RegexError::TrailingEscape)
+        );
+    }
+
+    #[test]
+    fn rejects_nested_repeats_that_expand_too_far() {
+        assert_eq!(
+            Regex::parse("((a{1000}){1000}){1000}", true).err(),
+            Some(RegexError::TooLarge)
+        );
+        assert!(Regex::parse("(a{10}){100}", true).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod regex_matching_tests {
+    use super::*;
+
+    fn is_match(pattern: &str, value: &str) -> bool {
+        Regex::parse(pattern, true).unwrap().is_match(value)
+    }
+
+    #[test]
+    fn unanchored_literal() {
+        assert!(is_match("mod", "src_module.rs"));
+        assert!(!is_match("mod", "main.rs"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match("^main", "main.rs"));
+        assert!(!is_match("^ain", "main.rs"));
+        assert!(is_match("\\.rs$", "main.rs"));
+        assert!(!is_match("\\.rs$", "main.rs.bak"));
+    }
+
+    #[test]
+    fn numeric_suffix_range() {
+        let pattern = "_1[0-9]{2}\\.log$";
+
+        assert!(is_match(pattern, "build_100.log"));
+        assert!(is_match(pattern, "build_199.log"));
+        assert!(!is_match(pattern, "build_200.log"));
+        assert!(!is_match(pattern, "build_1000.log"));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        let pattern = "^(foo|ba(r|z))+\\.(rs|toml)$";
+
+        assert!(is_match(pattern, "foobaz.rs"));
+        assert!(is_match(pattern, "barbarfoo.toml"));
+        assert!(!is_match(pattern, "foobaq.rs"));
+    }
+
+    #[test]
+    fn shorthand_classes() {
+        assert!(is_match("^\\w+\\d$", "file_9"));
+        assert!(!is_match("^\\w+\\d$", "file-9"));
+        assert!(is_match("\\s", "with space"));
+        assert!(is_match("^[\\D]+$", "no_digits"));
+        assert!(!is_match("^[\\D]+$", "d1gits"));
+    }
+
+    #[test]
+    fn optional_and_bounded_repetition() {
+        assert!(is_match("^colou?r$", "color"));
+        assert!(is_match("^colou?r$", "colour"));
+        assert!(is_match("^a{2,}$", "aaaa"));
+        assert!(!is_match("^a{2,3}$", "aaaa"));
+    }
+
+    #[test]
+    fn empty_loops_terminate() {
+        assert!(is_match("^(a*)*$", "aaa"));
+        assert!(!is_match("^(a*)*b$", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac"));
+    }
+
+    #[test]
+    fn exclusive_filter_keeps_non_matches() {
+        let regex = Regex::parse("_test$", false).unwrap();
+
+        assert!(Filter::keep(&regex, "parser"));
+        assert!(!Filter::keep(&regex, "parser_test"));
+    }
+}
@@ -0,0 +1,523 @@
+use std::ffi::CString;
+use std::fmt;
+
+use super::dirent::DirEntry;
+use super::time::days_from_civil;
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = MINUTE * 60;
+const DAY: u64 = HOUR * 24;
+const WEEK: u64 = DAY * 7;
+
+const KIB: u64 = 1024;
+const MIB: u64 = KIB * 1024;
+const GIB: u64 = MIB * 1024;
+const TIB: u64 = GIB * 1024;
+
+// Why a predicate value was rejected. The option and the value itself
+// are reported alongside by CliError.
+#[derive(Debug, Eq, PartialEq)]
+pub enum PredicateError {
+    InvalidSize,
+    InvalidTime,
+    InvalidType(char),
+    InvalidPerm,
+    UnknownOwner,
+}
+
+impl fmt::Display for PredicateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PredicateError::InvalidSize => write!(f, "expected [+-]N[ckMGT]"),
+            PredicateError::InvalidTime => {
+                write!(f, "expected N[smhdw] or YYYY-MM-DD")
+            }
+            PredicateError::InvalidType(ch) => {
+                write!(f, "unknown type '{ch}', expected one of f d l p s x")
+            }
+            PredicateError::InvalidPerm => {
+                write!(f, "expected [-/]octal or [-/]ugo+rwx")
+            }
+            PredicateError::UnknownOwner => write!(f, "no such user"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Greater,
+    Less,
+    Equal,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PermMatch {
+    Exact,
+    // -mode: every bit in mode is set
+    All,
+    // /mode: at least one bit in mode is set
+    Any,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum EntryType {
+    File,
+    Directory,
+    SymLink,
+    Pipe,
+    Socket,
+    Executable,
+}
+
+// find-style metadata tests evaluated against each entry during traversal.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Predicate {
+    Size {
+        comparison: Comparison,
+        count: u64,
+        unit: u64,
+    },
+    // mtime is at or after this many seconds since the epoch
+    Newer(u64),
+    // mtime is before this many seconds since the epoch
+    OlderThan(u64),
+    Type(Vec<EntryType>),
+    Perm {
+        mode: u32,
+        perm_match: PermMatch,
+    },
+    Owner(u32),
+}
+
+impl Predicate {
+    pub fn parse_size(value: &str) -> Result<Self, PredicateError> {
+        let err = || PredicateError::InvalidSize;
+
+        let (comparison, rest) = match value.split_at(
+            value.find(|c: char| !matches!(c, '+' | '-')).unwrap_or(0),
+        ) {
+            ("+", rest) => (Comparison::Greater, rest),
+            ("-", rest) => (Comparison::Less, rest),
+            ("", rest) => (Comparison::Equal, rest),
+            _ => return Err(err()),
+        };
+
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+
+        let (count, suffix) = rest.split_at(digits_end);
+
+        let unit = match suffix {
+            "" | "c" | "B" => 1,
+            "k" | "K" => KIB,
+            "M" => MIB,
+            "G" => GIB,
+            "T" => TIB,
+            _ => return Err(err()),
+        };
+
+        Ok(Predicate::Size {
+            comparison,
+            count: count.parse::<u64>().map_err(|_| err())?,
+            unit,
+        })
+    }
+
+    pub fn parse_newer(value: &str, now: u64) -> Result<Self, PredicateError> {
+        Ok(Predicate::Newer(Self::parse_time(value, now)?))
+    }
+
+    pub fn parse_older_than(
+        value: &str,
+        now: u64,
+    ) -> Result<Self, PredicateError> {
+        Ok(Predicate::OlderThan(Self::parse_time(value, now)?))
+    }
+
+    // Accepts either an age relative to `now` (2d, 12h, 30m) or an absolute
+    // UTC date (2024-01-01 or 2024-01-01T08:30).
+    fn parse_time(value: &str, now: u64) -> Result<u64, PredicateError> {
+        let err = || PredicateError::InvalidTime;
+
+        if value.contains('-') {
+            return parse_date(value).ok_or_else(err);
+        }
+
+        let digits_end = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+
+        let (count, suffix) = value.split_at(digits_end);
+        let count = count.parse::<u64>().map_err(|_| err())?;
+
+        let unit = match suffix {
+            "s" => 1,
+            "m" => MINUTE,
+            "h" => HOUR,
+            "" | "d" => DAY,
+            "w" => WEEK,
+            _ => return Err(err()),
+        };
+
+        Ok(now.saturating_sub(count.saturating_mul(unit)))
+    }
+
+    pub fn parse_type(value: &str) -> Result<Self, PredicateError> {
+        value
+            .chars()
+            .filter(|&ch| ch != ',' && ch != '|')
+            .map(|ch| match ch {
+                'f' => Ok(EntryType::File),
+                'd' => Ok(EntryType::Directory),
+                'l' => Ok(EntryType::SymLink),
+                'p' => Ok(EntryType::Pipe),
+                's' => Ok(EntryType::Socket),
+                'x' => Ok(EntryType::Executable),
+                _ => Err(PredicateError::InvalidType(ch)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Predicate::Type)
+    }
+
+    pub fn parse_perm(value: &str) -> Result<Self, PredicateError> {
+        let err = || PredicateError::InvalidPerm;
+
+        let (perm_match, mode) = match value.split_at(value.len().min(1)) {
+            ("/", mode) => (PermMatch::Any, mode),
+            ("-", mode) => (PermMatch::All, mode),
+            _ => (PermMatch::Exact, value),
+        };
+
+        if mode.is_empty() {
+            return Err(err());
+        }
+
+        let mode = if mode.chars().all(|c| c.is_digit(8)) {
+            u32::from_str_radix(mode, 8).map_err(|_| err())?
+        } else {
+            parse_symbolic_mode(mode).ok_or_else(err)?
+        };
+
+        if mode > 0o7777 {
+            return Err(err());
+        }
+
+        Ok(Predicate::Perm { mode, perm_match })
+    }
+
+    pub fn parse_owner(value: &str) -> Result<Self, PredicateError> {
+        if let Ok(uid) = value.parse::<u32>() {
+            return Ok(Predicate::Owner(uid));
+        }
+
+        lookup_uid(value)
+            .map(Predicate::Owner)
+            .ok_or(PredicateError::UnknownOwner)
+    }
+
+    pub fn is_match(&self, entry: &DirEntry) -> bool {
+        match self {
+            Predicate::Size {
+                comparison,
+                count,
+                unit,
+            } => {
+                // Like find, sizes are rounded up to the unit before comparing.
+                let size = entry.get_size().div_ceil(*unit);
+
+                match comparison {
+                    Comparison::Greater => size > *count,
+                    Comparison::Less => size < *count,
+                    Comparison::Equal => size == *count,
+                }
+            }
+            Predicate::Newer(since) => {
+                entry.get_last_modified().as_secs() >= *since
+            }
+            Predicate::OlderThan(before) => {
+                entry.get_last_modified().as_secs() < *before
+            }
+            Predicate::Type(types) => {
+                types.iter().any(|entry_type| match entry_type {
+                    EntryType::File => {
+                        !entry.is_dir()
+                            && !entry.is_symlink()
+                            && !entry.is_fifo()
+                            && !entry.is_socket()
+                    }
+                    EntryType::Directory => entry.is_dir(),
+                    EntryType::SymLink => entry.is_symlink(),
+                    EntryType::Pipe => entry.is_fifo(),
+                    EntryType::Socket => entry.is_socket(),
+                    EntryType::Executable => {
+                        !entry.is_symlink() && entry.is_executable()
+                    }
+                })
+            }
+            Predicate::Perm { mode, perm_match } => {
+                let entry_mode = entry.get_mode() & 0o7777;
+
+                match perm_match {
+                    PermMatch::Exact => entry_mode == *mode,
+                    PermMatch::All => entry_mode & mode == *mode,
+                    PermMatch::Any => *mode == 0 || entry_mode & mode != 0,
+                }
+            }
+            Predicate::Owner(uid) => entry.get_uid() == *uid,
+        }
+    }
+}
+
+// Builds a permission mask from clauses like u+x,g=rw,a+r. Only the bits
+// named matter since the mask is compared, not applied.
+fn parse_symbolic_mode(mode: &str) -> Option<u32> {
+    let mut mask = 0;
+
+    for clause in mode.split(',') {
+        let op_idx = clause.find(['+', '='])?;
+        let (who, perms) = (&clause[..op_idx], &clause[op_idx + 1..]);
+
+        let who_mask = who.chars().try_fold(0, |acc, ch| {
+            Some(
+                acc | match ch {
+                    'u' => 0o4700,
+                    'g' => 0o2070,
+                    'o' => 0o1007,
+                    'a' => 0o7777,
+                    _ => return None,
+                },
+            )
+        })?;
+
+        let who_mask = if who.is_empty() { 0o7777 } else { who_mask };
+
+        let perm_mask = perms.chars().try_fold(0, |acc, ch| {
+            Some(
+                acc | match ch {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    _ => return None,
+                },
+            )
+        })?;
+
+        mask |= who_mask & perm_mask;
+    }
+
+    Some(mask)
+}
+
+fn parse_date(value: &str) -> Option<u64> {
+    let (date, time) = value.split_once('T').unwrap_or((value, "00:00"));
+
+    let mut date_parts = date.splitn(3, '-').map(|part| part.parse::<i64>());
+    let year = date_parts.next()?.ok()?;
+    let month = date_parts.next()?.ok()?;
+    let day = date_parts.next()?.ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut time_parts = time.splitn(3, ':').map(|part| part.parse::<i64>());
+    let hours = time_parts.next()?.ok()?;
+    let mins = time_parts.next().unwrap_or(Ok(0)).ok()?;
+    let secs = time_parts.next().unwrap_or(Ok(0)).ok()?;
+
+    if !(0..24).contains(&hours)
+        || !(0..60).contains(&mins)
+        || !(0..60).contains(&secs)
+    {
+        return None;
+    }
+
+    let timestamp = days_from_civil(year, month, day) * DAY as i64
+        + hours * HOUR as i64
+        + mins * MINUTE as i64
+        + secs;
+
+    u64::try_from(timestamp).ok()
+}
+
+// Asks NSS rather than reading /etc/passwd, so LDAP and sssd users are
+// found too.
+fn lookup_uid(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut buf = vec![0_u8; 1024];
+
+    loop {
+        // SAFETY: passwd is plain old data that getpwnam_r fills in
+        let mut passwd = unsafe { std::mem::zeroed::<libc::passwd>() };
+        let mut result = std::ptr::null_mut();
+
+        // SAFETY: every pointer is valid for the call and buf is writable
+        // for its whole length; the strings passwd points into live in buf
+        let ret = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                &mut result,
+            )
+        };
+
+        match ret {
+            // the entry didn't fit, try again with more room
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            0 if !result.is_null() => return Some(passwd.pw_uid),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod predicate_parsing_tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(
+            Predicate::parse_size("+10M"),
+            Ok(Predicate::Size {
+                comparison: Comparison::Greater,
+                count: 10,
+                unit: MIB
+            })
+        );
+        assert_eq!(
+            Predicate::parse_size("-2k"),
+            Ok(Predicate::Size {
+                comparison: Comparison::Less,
+                count: 2,
+                unit: KIB
+            })
+        );
+        assert_eq!(
+            Predicate::parse_size("512"),
+            Ok(Predicate::Size {
+                comparison: Comparison::Equal,
+                count: 512,
+                unit: 1
+            })
+        );
+        assert!(Predicate::parse_size("+-1").is_err());
+        assert!(Predicate::parse_size("10Q").is_err());
+    }
+
+    #[test]
+    fn parses_relative_times() {
+        let now = 10 * DAY;
+
+        assert_eq!(
+            Predicate::parse_newer("2d", now),
+            Ok(Predicate::Newer(8 * DAY))
+        );
+        assert_eq!(
+            Predicate::parse_older_than("36h", now),
+            Ok(Predicate::OlderThan(10 * DAY - 36 * HOUR))
+        );
+        assert!(Predicate::parse_newer("2y", now).is_err());
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        assert_eq!(
+            Predicate::parse_older_than("2024-01-01", 0),
+            Ok(Predicate::OlderThan(1_704_067_200))
+        );
+        assert_eq!(
+            Predicate::parse_newer("2000-02-29T12:30", 0),
+            Ok(Predicate::Newer(951_827_400))
+        );
+        assert!(Predicate::parse_newer("2024-13-01", 0).is_err());
+    }
+
+    #[test]
+    fn parses_types() {
+        assert_eq!(
+            Predicate::parse_type("f|l"),
+            Ok(Predicate::Type(vec![EntryType::File, EntryType::SymLink]))
+        );
+        assert_eq!(
+            Predicate::parse_type("q"),
+            Err(PredicateError::InvalidType('q'))
+        );
+    }
+
+    #[test]
+    fn parses_perms() {
+        assert_eq!(
+            Predicate::parse_perm("/u+x"),
+            Ok(Predicate::Perm {
+                mode: 0o100,
+                perm_match: PermMatch::Any
+            })
+        );
+        assert_eq!(
+            Predicate::parse_perm("-644"),
+            Ok(Predicate::Perm {
+                mode: 0o644,
+                perm_match: PermMatch::All
+            })
+        );
+        assert_eq!(
+            Predicate::parse_perm("ug=rw,o+r"),
+            Ok(Predicate::Perm {
+                mode: 0o664,
+                perm_match: PermMatch::Exact
+            })
+        );
+        assert!(Predicate::parse_perm("/u-x").is_err());
+        assert!(Predicate::parse_perm("9").is_err());
+    }
+
+    #[test]
+    fn parses_owners() {
+        assert_eq!(Predicate::parse_owner("0"), Ok(Predicate::Owner(0)));
+        assert_eq!(Predicate::parse_owner("root"), Ok(Predicate::Owner(0)));
+        assert!(Predicate::parse_owner("no-such-user-here").is_err());
+    }
+}
+
+#[cfg(test)]
+mod predicate_matching_tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn entry(path: &str) -> DirEntry {
+        DirEntry::from_path(PathBuf::from(path), 1).unwrap()
+    }
+
+    #[test]
+    fn matches_types() {
+        let files = Predicate::parse_type("f").unwrap();
+        let dirs = Predicate::parse_type("d").unwrap();
+
+        assert!(files.is_match(&entry("tests/mock_dir/A.rs")));
+        assert!(!files.is_match(&entry("tests/mock_dir/A")));
+        assert!(dirs.is_match(&entry("tests/mock_dir/A")));
+    }
+
+    #[test]
+    fn matches_sizes() {
+        let empty = entry("tests/mock_dir/A.rs");
+
+        assert!(Predicate::parse_size("0").unwrap().is_match(&empty));
+        assert!(Predicate::parse_size("-1k").unwrap().is_match(&empty));
+        assert!(!Predicate::parse_size("+0").unwrap().is_match(&empty));
+    }
+
+    #[test]
+    fn matches_times() {
+        let file = entry("tests/mock_dir/A.rs");
+
+        assert!(Predicate::OlderThan(u64::MAX).is_match(&file));
+        assert!(!Predicate::Newer(u64::MAX).is_match(&file));
+    }
+}
@@ -0,0 +1,579 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+use super::dirent::{DirEntry, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use super::tree::Tree;
+
+// Bumped whenever the layout changes in a way older readers can't skip.
+const VERSION: u64 = 1;
+
+// One entry as --snapshot records it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SnapshotEntry {
+    // relative to the root
+    pub path: PathBuf,
+    // the file type bits and permissions, like st_mode
+    pub mode: u32,
+    pub size: u64,
+    // seconds since the epoch
+    pub mtime: u64,
+    pub target: Option<PathBuf>,
+}
+
+impl SnapshotEntry {
+    fn from_dirent(entry: &DirEntry, path: PathBuf) -> Self {
+        Self {
+            path,
+            mode: entry.get_mode(),
+            size: entry.get_size(),
+            mtime: entry.get_last_modified().as_secs(),
+            target: entry.linked_path().cloned(),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.mode & S_IFMT {
+            S_IFDIR => "directory",
+            S_IFREG => "file",
+            S_IFLNK => "link",
+            _ => "other",
+        }
+    }
+}
+
+// A walked tree, saved by --snapshot and compared against by --diff.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    pub root: PathBuf,
+    // in walk order, the root itself left out
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct SnapshotError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => {
+                write!(f, "{}:{line}: {}", self.path.display(), self.reason)
+            }
+            None => write!(f, "{}: {}", self.path.display(), self.reason),
+        }
+    }
+}
+
+impl Snapshot {
+    // Walks `tree`, so whatever filters it has decide what's recorded.
+    pub fn walk(tree: Tree) -> Self {
+        let mut root = PathBuf::new();
+        let mut entries = vec![];
+
+        for (_, entry) in tree {
+            if entry.depth == 0 {
+                root = entry.path().to_path_buf();
+                continue;
+            }
+
+            let path = entry
+                .path()
+                .strip_prefix(&root)
+                .unwrap_or(entry.path())
+                .to_path_buf();
+
+            entries.push(SnapshotEntry::from_dirent(&entry, path));
+        }
+
+        Self { root, entries }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        std::fs::write(path, self.to_json()).map_err(|err| SnapshotError {
+            path: path.to_path_buf(),
+            line: None,
+            reason: err.to_string(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        let source =
+            std::fs::read_to_string(path).map_err(|err| SnapshotError {
+                path: path.to_path_buf(),
+                line: None,
+                reason: err.to_string(),
+            })?;
+
+        Self::from_json(&source).map_err(|(line, reason)| SnapshotError {
+            path: path.to_path_buf(),
+            line: Some(line),
+            reason,
+        })
+    }
+
+    // One entry per line, so snapshots diff and grep well. Names that
+    // aren't UTF-8 are stored lossily.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\n  \"version\": {VERSION},\n  \"root\": {},\n  \"entries\": [",
+            quote(&self.root.to_string_lossy())
+        );
+
+        for (idx, entry) in self.entries.iter().enumerate() {
+            json.push_str(if idx == 0 { "\n    " } else { ",\n    " });
+            json.push_str(&format!(
+                "{{\"path\": {}, \"type\": \"{}\", \"mode\": {}, \
+                 \"size\": {}, \"mtime\": {}",
+                quote(&entry.path.to_string_lossy()),
+                entry.type_name(),
+                entry.mode,
+                entry.size,
+                entry.mtime
+            ));
+
+            if let Some(target) = &entry.target {
+                json.push_str(&format!(
+                    ", \"target\": {}",
+                    quote(&target.to_string_lossy())
+                ));
+            }
+
+            json.push('}');
+        }
+
+        json.push_str("\n  ]\n}\n");
+        json
+    }
+
+    // Reads what to_json writes. Unknown keys are skipped so later
+    // versions can add to entries.
+    pub fn from_json(source: &str) -> Result<Self, (usize, String)> {
+        let mut parser = Parser {
+            chars: source.chars().peekable(),
+            line: 1,
+        };
+
+        let value = parser.document().map_err(|err| (parser.line, err))?;
+        let line = parser.line;
+        let invalid = |reason: &str| (line, reason.to_owned());
+
+        let Value::Object(fields) = value else {
+            return Err(invalid("expected an object"));
+        };
+
+        let version = field(&fields, "version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid("missing version"))?;
+
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+
+        let root = field(&fields, "root")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("missing root"))?;
+
+        let Some(Value::Array(items)) = field(&fields, "entries") else {
+            return Err(invalid("missing entries"));
+        };
+
+        let mut entries = vec![];
+
+        for item in items {
+            let Value::Object(fields) = item else {
+                return Err(invalid("entries must be objects"));
+            };
+
+            let number = |key: &str| {
+                field(fields, key).and_then(Value::as_u64).ok_or_else(|| {
+                    invalid(&format!("entry without a numeric {key}"))
+                })
+            };
+
+            entries.push(SnapshotEntry {
+                path: field(fields, "path")
+                    .and_then(Value::as_str)
+                    .map(PathBuf::from)
+                    .ok_or_else(|| invalid("entry without a path"))?,
+                mode: u32::try_from(number("mode")?)
+                    .map_err(|_| invalid("mode out of range"))?,
+                size: number("size")?,
+                mtime: number("mtime")?,
+                target: field(fields, "target")
+                    .and_then(Value::as_str)
+                    .map(PathBuf::from),
+            });
+        }
+
+        Ok(Self {
+            root: PathBuf::from(root),
+            entries,
+        })
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            ch if ch.is_control() => {
+                quoted.push_str(&format!("\\u{:04x}", u32::from(ch)));
+            }
+            ch => quoted.push(ch),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    // kept as written, converted by whoever knows what it should be
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+fn field<'a>(fields: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    fields
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value)
+}
+
+// Just enough JSON for snapshots: everything but floats in exponent form
+// is accepted.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<char> {
+        let ch = self.chars.next();
+
+        if ch == Some('\n') {
+            self.line += 1;
+        }
+
+        ch
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.chars.peek() == Some(&expected) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        while matches!(self.chars.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+            self.next();
+        }
+    }
+
+    fn document(&mut self) -> Result<Value, String> {
+        let value = self.value()?;
+        self.skip_blank();
+
+        match self.chars.peek() {
+            None => Ok(value),
+            Some(ch) => Err(format!("unexpected '{ch}' after the document")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_blank();
+
+        match self.chars.peek() {
+            Some('{') => {
+                self.next();
+                self.object()
+            }
+            Some('[') => {
+                self.next();
+                self.array()
+            }
+            Some('"') => {
+                self.next();
+                self.string().map(Value::String)
+            }
+            Some('-' | '0'..='9') => Ok(self.number()),
+            Some('t' | 'f' | 'n') => self.keyword(),
+            Some(ch) => Err(format!("unexpected '{ch}'")),
+            None => Err(String::from("unexpected end of file")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        let mut fields = vec![];
+        self.skip_blank();
+
+        if self.eat('}') {
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.skip_blank();
+
+            if !self.eat('"') {
+                return Err(String::from("expected a quoted key"));
+            }
+
+            let key = self.string()?;
+            self.skip_blank();
+
+            if !self.eat(':') {
+                return Err(format!("expected ':' after \"{key}\""));
+            }
+
+            fields.push((key, self.value()?));
+            self.skip_blank();
+
+            if self.eat('}') {
+                return Ok(Value::Object(fields));
+            }
+
+            if !self.eat(',') {
+                return Err(String::from("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut items = vec![];
+        self.skip_blank();
+
+        if self.eat(']') {
+            return Ok(Value::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.skip_blank();
+
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+
+            if !self.eat(',') {
+                return Err(String::from("expected ',' or ']'"));
+            }
+        }
+    }
+
+    // After the opening quote.
+    fn string(&mut self) -> Result<String, String> {
+        let mut text = String::new();
+
+        loop {
+            match self.chars.next_if(|ch| *ch != '\n') {
+                None => return Err(String::from("unterminated string")),
+                Some('"') => return Ok(text),
+                Some('\\') => text.push(self.escape()?),
+                Some(ch) => text.push(ch),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        match self.next() {
+            Some('"') => Ok('"'),
+            Some('\\') => Ok('\\'),
+            Some('/') => Ok('/'),
+            Some('b') => Ok('\u{8}'),
+            Some('f') => Ok('\u{c}'),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('u') => {
+                let high = self.hex4()?;
+
+                // outside the BMP as a surrogate pair
+                if (0xd800..0xdc00).contains(&high) {
+                    if !(self.eat('\\') && self.eat('u')) {
+                        return Err(String::from("unpaired surrogate"));
+                    }
+
+                    let low = self.hex4()?;
+
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(String::from("unpaired surrogate"));
+                    }
+
+                    let code =
+                        0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+
+                    return char::from_u32(code)
+                        .ok_or_else(|| String::from("invalid surrogate pair"));
+                }
+
+                char::from_u32(high)
+                    .ok_or_else(|| String::from("unpaired surrogate"))
+            }
+            _ => Err(String::from("invalid escape")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|ch| ch.to_digit(16))
+                .ok_or_else(|| String::from("expected four hex digits"))?;
+
+            code = code * 16 + digit;
+        }
+
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Value {
+        let mut number = String::new();
+
+        while let Some(ch) = self
+            .chars
+            .next_if(|ch| matches!(ch, '-' | '+' | '.' | '0'..='9'))
+        {
+            number.push(ch);
+        }
+
+        Value::Number(number)
+    }
+
+    fn keyword(&mut self) -> Result<Value, String> {
+        let mut word = String::new();
+
+        while let Some(ch) = self.chars.next_if(char::is_ascii_alphabetic) {
+            word.push(ch);
+        }
+
+        match word.as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            "null" => Ok(Value::Null),
+            _ => Err(format!("unexpected '{word}'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+
+    fn sample() -> Snapshot {
+        Snapshot {
+            root: PathBuf::from("build"),
+            entries: vec![
+                SnapshotEntry {
+                    path: PathBuf::from("bin"),
+                    mode: S_IFDIR | 0o755,
+                    size: 4096,
+                    mtime: 1_700_000_000,
+                    target: None,
+                },
+                SnapshotEntry {
+                    path: PathBuf::from("bin/say \"hi\"\t.sh"),
+                    mode: S_IFREG | 0o755,
+                    size: 12,
+                    mtime: 1_700_000_001,
+                    target: None,
+                },
+                SnapshotEntry {
+                    path: PathBuf::from("latest"),
+                    mode: S_IFLNK | 0o777,
+                    size: 3,
+                    mtime: 1_700_000_002,
+                    target: Some(PathBuf::from("bin")),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn writes_one_entry_per_line() {
+        assert_eq!(
+            sample().to_json(),
+            "{\n  \"version\": 1,\n  \"root\": \"build\",\n  \"entries\": [\n    \
+             {\"path\": \"bin\", \"type\": \"directory\", \"mode\": 16877, \"size\": 4096, \"mtime\": 1700000000},\n    \
+             {\"path\": \"bin/say \\\"hi\\\"\\t.sh\", \"type\": \"file\", \"mode\": 33261, \"size\": 12, \"mtime\": 1700000001},\n    \
+             {\"path\": \"latest\", \"type\": \"link\", \"mode\": 41471, \"size\": 3, \"mtime\": 1700000002, \"target\": \"bin\"}\n  \
+             ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let snapshot = sample();
+
+        assert_eq!(Snapshot::from_json(&snapshot.to_json()), Ok(snapshot));
+        assert_eq!(
+            Snapshot::from_json(
+                "{\"version\": 1, \"root\": \"\\u00e9\\ud83d\\ude00\", \
+                 \"entries\": [], \"extra\": [null, true, 1.5]}"
+            ),
+            Ok(Snapshot {
+                root: PathBuf::from("é😀"),
+                entries: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_snapshots() {
+        let error = |source: &str| Snapshot::from_json(source).unwrap_err();
+
+        assert_eq!(
+            error("{\"version\": 2, \"root\": \"\", \"entries\": []}").1,
+            "unsupported version 2"
+        );
+        assert_eq!(
+            error("{\n\"version\": 1,\n\"root\": \"x\n\"}"),
+            (3, String::from("unterminated string"))
+        );
+        assert_eq!(
+            error("{\"version\": 1, \"root\": \"\", \"entries\": [{}]}").1,
+            "entry without a path"
+        );
+        assert_eq!(error("[1 2]").1, "expected ',' or ']'");
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use super::dirent::{DirEntry, Metadata, S_IFDIR, S_IFMT};
+
+// Where TreeIterator gets its entries from. The filesystem is the usual
+// source, but anything that can list the children of a path can be
+// rendered with the same filtering, sorting and connectors.
+pub trait EntrySource {
+    fn root(&self, path: PathBuf, depth: usize) -> io::Result<DirEntry>;
+
+    fn read_dir(&self, dir: &Path, depth: usize) -> io::Result<Vec<DirEntry>>;
+}
+
+pub struct FsSource;
+
+impl EntrySource for FsSource {
+    fn root(&self, path: PathBuf, depth: usize) -> io::Result<DirEntry> {
+        DirEntry::from_path(path, depth)
+    }
+
+    // Entries removed between the listing and their stat are skipped, as
+    // if the listing had come a moment later.
+    fn read_dir(&self, dir: &Path, depth: usize) -> io::Result<Vec<DirEntry>> {
+        Ok(std::fs::read_dir(dir)?
+            .flatten()
+            .filter_map(|entry| DirEntry::from_entry(entry, depth).ok())
+            .collect())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct VirtualNode {
+    metadata: Metadata,
+    linked_path: Option<PathBuf>,
+}
+
+// A hierarchy built from path strings, with no filesystem access.
+#[derive(Debug)]
+pub struct VirtualSource {
+    root: PathBuf,
+    nodes: HashMap<PathBuf, VirtualNode>,
+    children: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl VirtualSource {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            nodes: HashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    // Builds a hierarchy from newline or NUL separated paths, as produced
+    // by `git ls-files`, `find -print0` or `tar -t`. A trailing '/' marks a
+    // directory, as does having children.
+    pub fn from_listing(root: PathBuf, listing: &[u8]) -> Self {
+        let mut source = Self::new(root);
+
+        let separator = if listing.contains(&b'\0') {
+            b'\0'
+        } else {
+            b'\n'
+        };
+
+        for line in listing.split(|&byte| byte == separator) {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let metadata = if line.ends_with(b"/") {
+                Metadata::directory()
+            } else {
+                Metadata::file()
+            };
+
+            source.insert(Path::new(OsStr::from_bytes(line)), metadata, None);
+        }
+
+        source
+    }
+
+    // Adds `path` (relative to the root) and any missing parents. Leading
+    // `/` and `./` are ignored so absolute and find-style paths nest under
+    // the root, and `..` is resolved lexically.
+    pub fn insert(
+        &mut self,
+        path: &Path,
+        metadata: Metadata,
+        linked_path: Option<PathBuf>,
+    ) {
+        let mut components: Vec<&OsStr> = vec![];
+
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => components.push(name),
+                Component::ParentDir => {
+                    components.pop();
+                }
+                _ => (),
+            }
+        }
+
+        if components.is_empty() {
+            return;
+        }
+
+        let mut parent = self.root.clone();
+
+        for (idx, name) in components.iter().enumerate() {
+            let current = parent.join(name);
+            let is_leaf = idx == components.len() - 1;
+
+            if !self.nodes.contains_key(&current) {
+                self.children
+                    .entry(parent.clone())
+                    .or_default()
+                    .push(current.clone());
+
+                self.nodes.insert(
+                    current.clone(),
+                    VirtualNode {
+                        metadata: Metadata::directory(),
+                        linked_path: None,
+                    },
+                );
+            }
+
+            if is_leaf {
+                let node = self.nodes.get_mut(&current).unwrap();
+                node.metadata = metadata.clone();
+                node.linked_path = linked_path.clone();
+            }
+
+            parent = current;
+        }
+    }
+
+    fn entry(&self, path: &Path, depth: usize) -> DirEntry {
+        let node = self.nodes.get(path).expect("virtual node to exist");
+
+        let mut metadata = node.metadata.clone();
+
+        // Listed as a file, but anything with children can only be a
+        // directory.
+        if self.children.contains_key(path) {
+            metadata.mode = S_IFDIR | (metadata.mode & !S_IFMT);
+        }
+
+        let mut entry = DirEntry::from_virtual(
+            path.to_path_buf(),
+            depth,
+            metadata,
+            node.linked_path.clone(),
+        );
+
+        entry.links_to_dir = node
+            .linked_path
+            .as_ref()
+            .and_then(|target| self.resolve(path, target))
+            .is_some_and(|target| self.is_dir(&target));
+
+        entry
+    }
+
+    // Where a relative link target lands within the hierarchy. Absolute
+    // targets point outside it.
+    fn resolve(&self, link: &Path, target: &Path) -> Option<PathBuf> {
+        let mut resolved = link.parent()?.to_path_buf();
+
+        for component in target.components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir if resolved != self.root => {
+                    resolved.pop();
+                }
+                Component::ParentDir => return None,
+                Component::CurDir => (),
+                _ => return None,
+            }
+        }
+
+        Some(resolved)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.children.contains_key(path)
+            || self
+                .nodes
+                .get(path)
+                .is_some_and(|node| node.metadata.mode & S_IFMT == S_IFDIR)
+    }
+}
+
+impl EntrySource for VirtualSource {
+    fn root(&self, path: PathBuf, depth: usize) -> io::Result<DirEntry> {
+        Ok(DirEntry::from_virtual(
+            path,
+            depth,
+            Metadata::directory(),
+            None,
+        ))
+    }
+
+    fn read_dir(&self, dir: &Path, depth: usize) -> io::Result<Vec<DirEntry>> {
+        Ok(self.children.get(dir).map_or_else(Vec::new, |children| {
+            children
+                .iter()
+                .map(|child| self.entry(child, depth))
+                .collect()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod virtual_source_tests {
+    use super::*;
+    use crate::core::dirent::S_IFLNK;
+
+    fn names(source: &VirtualSource, dir: &str) -> Vec<String> {
+        let mut names = source
+            .read_dir(Path::new(dir), 1)
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                let kind = if entry.is_dir() { "/" } else { "" };
+                format!("{}{kind}", entry.get_name().unwrap().to_str().unwrap())
+            })
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn builds_hierarchy_from_newline_listing() {
+        let source = VirtualSource::from_listing(
+            PathBuf::from("."),
+            b"src/main.rs\nsrc/cli/mod.rs\r\nREADME.md\n\n",
+        );
+
+        assert_eq!(names(&source, "."), vec!["README.md", "src/"]);
+        assert_eq!(names(&source, "./src"), vec!["cli/", "main.rs"]);
+        assert_eq!(names(&source, "./src/cli"), vec!["mod.rs"]);
+    }
+
+    #[test]
+    fn builds_hierarchy_from_nul_listing() {
+        let source = VirtualSource::from_listing(
+            PathBuf::from("."),
+            b"./a/b\0./a/c/\0/abs/d\0",
+        );
+
+        assert_eq!(names(&source, "."), vec!["a/", "abs/"]);
+        assert_eq!(names(&source, "./a"), vec!["b", "c/"]);
+        assert_eq!(names(&source, "./abs"), vec!["d"]);
+    }
+
+    #[test]
+    fn file_with_children_becomes_directory() {
+        let source =
+            VirtualSource::from_listing(PathBuf::from("."), b"a\na/b\n");
+
+        assert_eq!(names(&source, "."), vec!["a/"]);
+    }
+
+    #[test]
+    fn resolves_links_within_the_hierarchy() {
+        let mut source =
+            VirtualSource::from_listing(PathBuf::from("."), b"bin/\nrun.sh\n");
+        let link = Metadata {
+            mode: S_IFLNK | 0o777,
+            ..Metadata::default()
+        };
+
+        for (name, target) in [
+            ("to_bin", "bin"),
+            ("to_script", "./run.sh"),
+            ("to_parent", "bin/.."),
+            ("to_missing", "../bin"),
+            ("to_abs", "/bin"),
+        ] {
+            source.insert(
+                Path::new(name),
+                link.clone(),
+                Some(PathBuf::from(target)),
+            );
+        }
+
+        let mut links = source
+            .read_dir(Path::new("."), 1)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.is_symlink())
+            .map(|entry| {
+                let name = entry.get_name().unwrap().to_str().unwrap();
+                (name.to_owned(), entry.links_to_dir)
+            })
+            .collect::<Vec<_>>();
+        links.sort();
+
+        assert_eq!(
+            links,
+            [
+                (String::from("to_abs"), false),
+                (String::from("to_bin"), true),
+                (String::from("to_missing"), false),
+                (String::from("to_parent"), true),
+                (String::from("to_script"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_duplicates() {
+        let source =
+            VirtualSource::from_listing(PathBuf::from("."), b"a/b\na/b\na/\n");
+
+        assert_eq!(names(&source, "./a"), vec!["b"]);
+    }
+}
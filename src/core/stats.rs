@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::time::format_utc;
+
+// Files directly under the root are grouped under this name.
+const ROOT_GROUP: &str = ".";
+const NO_EXTENSION: &str = "(none)";
+
+#[derive(Debug, Default, PartialEq)]
+struct Group {
+    count: usize,
+    total_bytes: u64,
+    // (size, name) of the biggest file
+    largest: Option<(u64, String)>,
+    // newest mtime, in seconds since the epoch
+    newest: Option<u64>,
+}
+
+impl Group {
+    fn add(&mut self, name: &str, size: u64, mtime: u64) {
+        self.count += 1;
+        self.total_bytes += size;
+
+        if self
+            .largest
+            .as_ref()
+            .is_none_or(|(largest, _)| size > *largest)
+        {
+            self.largest = Some((size, name.to_owned()));
+        }
+
+        self.newest =
+            Some(self.newest.map_or(mtime, |newest| newest.max(mtime)));
+    }
+}
+
+// --stats: what the listed files add up to, by extension and by the
+// top-level directory they live under.
+#[derive(Debug, Default)]
+pub struct Stats {
+    extensions: BTreeMap<String, Group>,
+    top_dirs: BTreeMap<String, Group>,
+}
+
+impl Stats {
+    // Top-level directories show up even if nothing under them is kept.
+    pub fn add_top_dir(&mut self, name: &str) {
+        self.top_dirs.entry(name.to_owned()).or_default();
+    }
+
+    // `top_dir` is None for files directly under the root.
+    pub fn add_file(
+        &mut self,
+        name: &str,
+        top_dir: Option<&str>,
+        size: u64,
+        mtime: u64,
+    ) {
+        let extension = Path::new(name).extension().map_or(
+            String::from(NO_EXTENSION),
+            |extension| {
+                format!(".{}", extension.to_string_lossy().to_lowercase())
+            },
+        );
+
+        self.extensions
+            .entry(extension)
+            .or_default()
+            .add(name, size, mtime);
+
+        self.top_dirs
+            .entry(top_dir.unwrap_or(ROOT_GROUP).to_owned())
+            .or_default()
+            .add(name, size, mtime);
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "{}\n{}",
+            render_table("extension", &self.extensions),
+            render_table("directory", &self.top_dirs)
+        )
+    }
+}
+
+// Biggest groups first, names breaking ties.
+fn render_table(title: &str, groups: &BTreeMap<String, Group>) -> String {
+    let mut sorted = groups.iter().collect::<Vec<_>>();
+    sorted.sort_by(|(a_name, a), (b_name, b)| {
+        b.total_bytes.cmp(&a.total_bytes).then(a_name.cmp(b_name))
+    });
+
+    let mut rows = vec![[
+        title.to_owned(),
+        String::from("files"),
+        String::from("bytes"),
+        String::from("largest"),
+        String::from("newest"),
+    ]];
+
+    for (name, group) in sorted {
+        rows.push([
+            name.clone(),
+            group.count.to_string(),
+            group.total_bytes.to_string(),
+            group
+                .largest
+                .as_ref()
+                .map_or(String::from("-"), |(size, name)| {
+                    format!("{name} ({size} B)")
+                }),
+            group.newest.map_or(String::from("-"), format_utc),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+
+    for row in &rows {
+        // counts and sizes are right-aligned
+        let line = format!(
+            "{:<w0$}  {:>w1$}  {:>w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn groups_by_extension_and_top_dir() {
+        let mut stats = Stats::default();
+
+        stats.add_top_dir("src");
+        stats.add_top_dir("empty");
+        stats.add_file("main.rs", Some("src"), 300, 20);
+        stats.add_file("lib.RS", Some("src"), 500, 10);
+        stats.add_file("README", None, 40, 30);
+
+        assert_eq!(
+            stats.extensions[".rs"],
+            Group {
+                count: 2,
+                total_bytes: 800,
+                largest: Some((500, String::from("lib.RS"))),
+                newest: Some(20),
+            }
+        );
+        assert_eq!(stats.extensions[NO_EXTENSION].count, 1);
+        assert_eq!(stats.top_dirs[ROOT_GROUP].total_bytes, 40);
+        assert_eq!(stats.top_dirs["empty"], Group::default());
+    }
+
+    #[test]
+    fn renders_aligned_tables() {
+        let mut stats = Stats::default();
+
+        stats.add_top_dir("empty");
+        stats.add_file("a.rs", None, 1_200, 0);
+        stats.add_file("b.md", None, 7, 1_700_000_000);
+
+        assert_eq!(
+            stats.render(),
+            "extension  files  bytes  largest        newest\n\
+             .rs            1   1200  a.rs (1200 B)  1970-01-01 00:00\n\
+             .md            1      7  b.md (7 B)     2023-11-14 22:13\n\
+             \n\
+             directory  files  bytes  largest        newest\n\
+             .              2   1207  a.rs (1200 B)  2023-11-14 22:13\n\
+             empty          0      0  -              -\n"
+        );
+    }
+}
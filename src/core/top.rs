@@ -0,0 +1,175 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::time::format_utc;
+
+// What --top ranks by.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TopBy {
+    #[default]
+    Size,
+    Mtime,
+    // directories by how many entries are listed directly under them
+    Count,
+}
+
+impl TopBy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "size" => Some(TopBy::Size),
+            "mtime" => Some(TopBy::Mtime),
+            "count" => Some(TopBy::Count),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            TopBy::Size => "largest files",
+            TopBy::Mtime => "most recently modified files",
+            TopBy::Count => "directories with the most entries",
+        }
+    }
+}
+
+// The N highest ranked paths, kept in a min-heap that never grows past
+// N so a huge walk costs O(N) memory. Equal keys keep the path that
+// sorts first.
+#[derive(Debug)]
+pub struct Top {
+    by: TopBy,
+    limit: usize,
+    heap: BinaryHeap<Reverse<(u64, Reverse<PathBuf>)>>,
+    // --by count only, filled in as children go by
+    child_counts: HashMap<PathBuf, u64>,
+}
+
+impl Top {
+    pub fn new(by: TopBy, limit: usize) -> Self {
+        Self {
+            by,
+            limit,
+            heap: BinaryHeap::new(),
+            child_counts: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, key: u64, path: PathBuf) {
+        self.heap.push(Reverse((key, Reverse(path))));
+
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
+
+    // Called for every listed entry. `is_regular` picks out files for
+    // size and mtime; count only needs to know the parent.
+    pub fn add(
+        &mut self,
+        path: &Path,
+        is_regular: bool,
+        size: u64,
+        mtime: u64,
+    ) {
+        match self.by {
+            TopBy::Size if is_regular => self.push(size, path.to_path_buf()),
+            TopBy::Mtime if is_regular => self.push(mtime, path.to_path_buf()),
+            TopBy::Count => {
+                if let Some(parent) = path.parent() {
+                    *self
+                        .child_counts
+                        .entry(parent.to_path_buf())
+                        .or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn render(mut self) -> String {
+        for (path, count) in std::mem::take(&mut self.child_counts) {
+            self.push(count, path);
+        }
+
+        let rows = self
+            .heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((key, Reverse(path)))| {
+                let key = match self.by {
+                    TopBy::Size => format!("{key} B"),
+                    TopBy::Mtime => format_utc(key),
+                    TopBy::Count => key.to_string(),
+                };
+
+                (key, path)
+            })
+            .collect::<Vec<_>>();
+
+        let width = rows
+            .iter()
+            .map(|(key, _)| key.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut rendered =
+            format!("Top {} {}\n", self.limit, self.by.describe());
+
+        for (key, path) in rows {
+            rendered.push_str(&format!("{key:>width$}  {}\n", path.display()));
+        }
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod top_tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_largest_files() {
+        let mut top = Top::new(TopBy::Size, 2);
+
+        top.add(Path::new("a/small"), true, 10, 0);
+        top.add(Path::new("a/dir"), false, 4096, 0);
+        top.add(Path::new("a/big"), true, 3000, 0);
+        top.add(Path::new("b/medium"), true, 200, 0);
+        top.add(Path::new("a/medium"), true, 200, 0);
+
+        assert_eq!(top.heap.len(), 2);
+        assert_eq!(
+            top.render(),
+            "Top 2 largest files\n\
+             3000 B  a/big\n\
+             \x20200 B  a/medium\n"
+        );
+    }
+
+    #[test]
+    fn ranks_by_mtime_and_child_count() {
+        let mut top = Top::new(TopBy::Mtime, 1);
+
+        top.add(Path::new("old"), true, 0, 0);
+        top.add(Path::new("new"), true, 0, 1_700_000_000);
+
+        assert_eq!(
+            top.render(),
+            "Top 1 most recently modified files\n2023-11-14 22:13  new\n"
+        );
+
+        let mut top = Top::new(TopBy::Count, 5);
+
+        for path in ["root/a", "root/b", "root/a/x", "root/c"] {
+            top.add(Path::new(path), true, 0, 0);
+        }
+
+        assert_eq!(
+            top.render(),
+            "Top 5 directories with the most entries\n\
+             3  root\n\
+             1  root/a\n"
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::vec;
 
@@ -8,10 +8,14 @@ use crate::same_file::Handle;
 use crate::cli::TreeIteratorFlags;
 
 use super::dirent::DirEntry;
-use super::pattern::Pattern;
+use super::pattern::Filter;
+use super::predicate::Predicate;
+use super::source::{EntrySource, FsSource};
 
 pub struct Tree {
-    pub pattern: Option<Pattern>,
+    pub source: Box<dyn EntrySource>,
+    pub filters: Vec<Box<dyn Filter>>,
+    pub predicates: Vec<Predicate>,
     pub root: Option<PathBuf>,
     pub visit_all: bool,
     pub dirs_only: bool,
@@ -20,15 +24,21 @@ pub struct Tree {
     pub rev_alpha_sort: bool,
     pub last_mod_sort: bool,
     pub follow_symlinks: bool,
+    pub one_fs: bool,
+    pub prune: bool,
+    pub file_limit: Option<usize>,
 }
 
 impl Tree {
     pub fn new(
         tree_iterator_flags: &mut TreeIteratorFlags,
-        pattern: Option<Pattern>,
+        filters: Vec<Box<dyn Filter>>,
+        predicates: Vec<Predicate>,
     ) -> Self {
         Tree {
-            pattern,
+            source: Box::new(FsSource),
+            filters,
+            predicates,
             root: tree_iterator_flags.root.take(),
             max_depth: tree_iterator_flags.max_depth.take(),
             visit_all: tree_iterator_flags.visit_all,
@@ -37,16 +47,30 @@ impl Tree {
             rev_alpha_sort: tree_iterator_flags.rev_alpha_sort,
             last_mod_sort: tree_iterator_flags.last_mod_sort,
             follow_symlinks: tree_iterator_flags.follow_symlinks,
+            one_fs: tree_iterator_flags.one_fs,
+            prune: tree_iterator_flags.prune,
+            file_limit: tree_iterator_flags.file_limit,
         }
     }
+
+    // Renders entries from somewhere other than the filesystem, e.g. a
+    // --fromfile listing.
+    pub fn with_source(mut self, source: Box<dyn EntrySource>) -> Self {
+        self.source = source;
+        self
+    }
 }
 
-#[derive(Debug)]
-struct Visited {
-    pub path: PathBuf,
+// What the walk dropped or couldn't read, for the report.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WalkStats {
+    pub hidden: usize,
+    pub filtered: usize,
+    pub unreadable: usize,
 }
 
 pub struct TreeIterator {
+    source: Box<dyn EntrySource>,
     start: Option<PathBuf>,
     dirent_list: Vec<std::vec::IntoIter<DirEntry>>,
     visited_paths: Vec<DirHandle>,
@@ -56,12 +80,25 @@ pub struct TreeIterator {
     rev_alpha_sort: bool,
     last_mod_sort: bool,
     follow_symlinks: bool,
+    one_fs: bool,
+    // the root's device, once known, for -x
+    device: Option<u64>,
     max_depth: Option<usize>,
+    prune: bool,
+    file_limit: Option<usize>,
     depth: usize,
-    pattern: Option<Pattern>,
+    filters: Vec<Box<dyn Filter>>,
+    predicates: Vec<Predicate>,
+    // listings --prune read ahead of the walk, by directory
+    listed: HashMap<PathBuf, std::io::Result<Listing>>,
+    stats: WalkStats,
 }
 
 impl TreeIterator {
+    pub fn stats(&self) -> WalkStats {
+        self.stats
+    }
+
     pub fn handle_entry(
         &mut self,
         mut dirent: DirEntry,
@@ -70,18 +107,6 @@ impl TreeIterator {
         // is present in visited paths. (Recursion detected)
         //
         // This mirrors the behavior of linux tree cmd.
-
-        // if dirent.is_symlink() && self.follow_symlinks {
-        //     println!(
-        //         "{:?} {}",
-        //         self.visited_paths,
-        //         format!(
-        //             "{:?}/{:?}",
-        //             self.root.as_ref().unwrap(),
-        //             dirent.linked_path().unwrap()
-        //         )
-        //     );
-        // }
         if dirent.is_symlink()
             && self.follow_symlinks
             && self.is_recursive(dirent.linked_path().unwrap().as_path())
@@ -102,82 +127,170 @@ impl TreeIterator {
         };
 
         if is_dir {
-            let rd = std::fs::read_dir(dir_path.unwrap())
-                .expect("Error reading dir");
-
-            let mut entry_list: Vec<DirEntry> = rd
-                .filter_map(|entry| {
-                    if let Ok(entry) = entry {
-                        let dir_entry =
-                            DirEntry::from_entry(entry, self.depth + 1);
-
-                        if dir_entry.is_dir() && self.follow_symlinks {
-                            self.visited_paths.push(DirHandle {
-                                path: dir_entry.path().to_path_buf(),
-                            });
-                        }
-
-                        let keep = match (
-                            self.pattern.as_ref(),
-                            dir_entry.get_clean_name(),
-                            dir_entry.is_dir(),
-                        ) {
-                            (Some(matcher), name, false) => {
-                                let is_match = matcher.is_match(name);
-                                (is_match && matcher.inclusive)
-                                    || (!is_match && !matcher.inclusive)
-                            }
-                            _ => true,
-                        };
-
-                        return match (
-                            keep,
-                            self.visit_all,
-                            dir_entry.is_hidden(),
-                            self.dirs_only,
-                            dir_entry.is_dir(),
-                        ) {
-                            (false, _, _, _, _) => None,
-                            (true, false, true, _, _)
-                            | (true, _, _, true, false) => None,
-                            _ => Some(dir_entry),
-                        };
-                    }
+            let dir_path = dir_path.unwrap().to_path_buf();
+
+            // a mount point under -x is shown but not read
+            if self.is_other_fs(&dirent) {
+                self.dirent_list.push(vec![].into_iter());
+                return Ok(Some(dirent));
+            }
 
-                    None
-                })
-                .collect();
+            // --prune may have listed it already while deciding whether
+            // it's kept
+            let listing = match self.listed.remove(&dir_path) {
+                Some(listing) => listing,
+                None => self.list(&dir_path, self.depth + 1),
+            };
+
+            // an unreadable directory is still listed, just empty
+            let entries = match listing {
+                // --filelimit: shown, but not opened
+                Ok(listing)
+                    if self
+                        .file_limit
+                        .is_some_and(|limit| listing.entries.len() > limit) =>
+                {
+                    dirent.exceeds_limit = Some(listing.entries.len());
+                    vec![]
+                }
+                Ok(listing) => {
+                    self.stats.hidden += listing.hidden;
+                    self.stats.filtered += listing.filtered;
+                    listing.entries
+                }
+                Err(_) => {
+                    self.stats.unreadable += 1;
+                    dirent.is_unreadable = true;
+                    vec![]
+                }
+            };
 
-            entry_list.sort_by(|a, b| match (a.is_dir(), b.is_dir()) {
+            self.dirent_list.push(entries.into_iter());
+        }
+
+        Ok(Some(dirent))
+    }
+
+    // Reads a directory's entries at `depth`, keeping what the filters,
+    // predicates and --prune let through, in display order.
+    fn list(&mut self, path: &Path, depth: usize) -> std::io::Result<Listing> {
+        let mut listing = Listing {
+            entries: vec![],
+            hidden: 0,
+            filtered: 0,
+        };
+
+        for dir_entry in self.source.read_dir(path, depth)? {
+            if dir_entry.is_dir() && self.follow_symlinks {
+                self.visited_paths.push(DirHandle {
+                    path: dir_entry.path().to_path_buf(),
+                });
+            }
+
+            if dir_entry.is_hidden() && !self.visit_all {
+                listing.hidden += 1;
+            } else if self.keep_entry(&dir_entry) {
+                listing.entries.push(dir_entry);
+            } else {
+                listing.filtered += 1;
+            }
+        }
+
+        listing
+            .entries
+            .sort_by(|a, b| match (a.is_dir(), b.is_dir()) {
                 (true, false) if self.dirs_first => Ordering::Less,
                 (false, true) if self.dirs_first => Ordering::Greater,
-                _ if self.last_mod_sort => {
-                    todo!()
-                    // a.get_last_modified().cmp(&b.get_last_modified())
-                }
                 _ => {
-                    let a_name = a.get_clean_name();
-                    let b_name = b.get_clean_name();
+                    // oldest first, like GNU tree, with names breaking ties
+                    let order = if self.last_mod_sort {
+                        a.modified().cmp(&b.modified())
+                    } else {
+                        Ordering::Equal
+                    }
+                    .then_with(|| a.get_clean_name().cmp(&b.get_clean_name()));
 
                     if self.rev_alpha_sort {
-                        b_name.cmp(a_name)
+                        order.reverse()
                     } else {
-                        a_name.cmp(b_name)
+                        order
                     }
                 }
             });
 
-            self.dirent_list.push(entry_list.into_iter());
+        Ok(listing)
+    }
+
+    // Directories are kept for structure unless an exclude (-I,
+    // --exclude-regex or a config ignore) names them, like GNU tree, or
+    // --prune finds nothing under them; everything else has to pass every
+    // filter and predicate.
+    fn keep_entry(&mut self, dir_entry: &DirEntry) -> bool {
+        if dir_entry.is_dir() {
+            let name = dir_entry.get_lossy_name();
+            let is_excluded = self
+                .filters
+                .iter()
+                .any(|filter| !filter.is_inclusive() && filter.is_match(&name));
+
+            return !is_excluded && (!self.prune || self.keep_dir(dir_entry));
         }
 
-        Ok(Some(dirent))
+        !self.dirs_only
+            && self
+                .filters
+                .iter()
+                .all(|filter| filter.keep(&dir_entry.get_lossy_name()))
+            && self.matches(dir_entry)
+    }
+
+    // With --prune, a directory stays when --type lets it match on its
+    // own, or when anything the walk will show under it is kept. Its
+    // listing is saved for when the walk gets there, so each directory
+    // is read once however deep the tree is.
+    fn keep_dir(&mut self, dir_entry: &DirEntry) -> bool {
+        let is_candidate = self
+            .predicates
+            .iter()
+            .any(|predicate| matches!(predicate, Predicate::Type(_)));
+
+        if is_candidate && self.matches(dir_entry) {
+            return true;
+        }
+
+        // nothing below -L or past a mount point under -x is shown, so
+        // nothing there can keep it
+        if self.max_depth.is_some_and(|max| dir_entry.depth > max)
+            || self.is_other_fs(dir_entry)
+        {
+            return false;
+        }
+
+        let listing = self.list(dir_entry.path(), dir_entry.depth + 1);
+        let keep = listing
+            .as_ref()
+            .is_ok_and(|listing| !listing.entries.is_empty());
+
+        self.listed.insert(dir_entry.path().to_path_buf(), listing);
+        keep
+    }
+
+    fn is_other_fs(&self, dir_entry: &DirEntry) -> bool {
+        self.device
+            .is_some_and(|device| dir_entry.get_device() != device)
+    }
+
+    fn matches(&self, dir_entry: &DirEntry) -> bool {
+        self.predicates
+            .iter()
+            .all(|predicate| predicate.is_match(dir_entry))
     }
 
     fn is_recursive(&self, path: &Path) -> bool {
-        Handle::from_path(path).map_or(false, |h| {
-            self.visited_paths
-                .iter()
-                .any(|visited| Handle::from_path(&visited.path).unwrap() == h)
+        Handle::from_path(path).is_ok_and(|h| {
+            self.visited_paths.iter().any(|visited| {
+                Handle::from_path(&visited.path).is_ok_and(|v| v == h)
+            })
         })
     }
 }
@@ -186,14 +299,30 @@ struct DirHandle {
     path: PathBuf,
 }
 
+// One directory's kept entries, and how many were dropped on the way.
+struct Listing {
+    entries: Vec<DirEntry>,
+    hidden: usize,
+    filtered: usize,
+}
+
 impl Iterator for TreeIterator {
     type Item = (usize, DirEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(root) = self.start.take() {
-            if let Ok(Some(dent)) =
-                self.handle_entry(DirEntry::from_path(root, self.depth))
-            {
+            // a root that vanished before the walk got to it is counted
+            // like a directory that can't be read
+            let Ok(root) = self.source.root(root, self.depth) else {
+                self.stats.unreadable += 1;
+                return None;
+            };
+
+            if self.one_fs {
+                self.device = Some(root.get_device());
+            }
+
+            if let Ok(Some(dent)) = self.handle_entry(root) {
                 return Some((1, dent));
             }
         }
@@ -237,6 +366,7 @@ impl IntoIterator for Tree {
 
     fn into_iter(mut self) -> Self::IntoIter {
         TreeIterator {
+            source: self.source,
             start: self.root.take(),
             dirent_list: vec![],
             visited_paths: vec![],
@@ -247,8 +377,262 @@ impl IntoIterator for Tree {
             rev_alpha_sort: self.rev_alpha_sort,
             last_mod_sort: self.last_mod_sort,
             follow_symlinks: self.follow_symlinks,
+            one_fs: self.one_fs,
+            device: None,
             depth: 0,
-            pattern: self.pattern.take(),
+            prune: self.prune,
+            file_limit: self.file_limit,
+            filters: std::mem::take(&mut self.filters),
+            predicates: std::mem::take(&mut self.predicates),
+            listed: HashMap::new(),
+            stats: WalkStats::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tree_tests {
+    use super::*;
+    use crate::core::dirent::Metadata;
+    use crate::core::pattern::regex::Regex;
+    use crate::core::source::VirtualSource;
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    // Wraps a listing and logs every directory read from it.
+    struct CountingSource {
+        inner: VirtualSource,
+        reads: Rc<RefCell<Vec<PathBuf>>>,
+    }
+
+    impl EntrySource for CountingSource {
+        fn root(&self, path: PathBuf, depth: usize) -> io::Result<DirEntry> {
+            self.inner.root(path, depth)
+        }
+
+        fn read_dir(
+            &self,
+            dir: &Path,
+            depth: usize,
+        ) -> io::Result<Vec<DirEntry>> {
+            self.reads.borrow_mut().push(dir.to_path_buf());
+            self.inner.read_dir(dir, depth)
+        }
+    }
+
+    fn flags() -> TreeIteratorFlags {
+        TreeIteratorFlags {
+            root: Some(PathBuf::from(".")),
+            max_depth: None,
+            visit_all: false,
+            dirs_only: false,
+            dirs_first: false,
+            last_mod_sort: false,
+            rev_alpha_sort: false,
+            follow_symlinks: false,
+            one_fs: false,
+            prune: false,
+            file_limit: None,
+        }
+    }
+
+    // Walks `listing` with --prune, returning the paths shown under the
+    // root and every directory read on the way.
+    fn walk(
+        listing: &[u8],
+        max_depth: Option<usize>,
+        predicates: Vec<Predicate>,
+    ) -> (Vec<String>, Vec<PathBuf>) {
+        let reads = Rc::new(RefCell::new(vec![]));
+        let source = CountingSource {
+            inner: VirtualSource::from_listing(PathBuf::from("."), listing),
+            reads: Rc::clone(&reads),
+        };
+
+        let mut flags = TreeIteratorFlags {
+            max_depth,
+            prune: true,
+            ..flags()
+        };
+
+        let paths = Tree::new(&mut flags, vec![], predicates)
+            .with_source(Box::new(source))
+            .into_iter()
+            .skip(1)
+            .map(|(_, entry)| entry.path().display().to_string())
+            .collect();
+
+        let reads = reads.borrow().clone();
+        (paths, reads)
+    }
+
+    #[test]
+    fn prunes_reading_each_directory_once() {
+        let (paths, reads) = walk(
+            b"a/b/c/x.rs\ne/f/\ng/y.txt\n",
+            None,
+            vec![Predicate::parse_size("-1k").unwrap()],
+        );
+
+        assert_eq!(
+            paths,
+            [
+                "./a",
+                "./a/b",
+                "./a/b/c",
+                "./a/b/c/x.rs",
+                "./g",
+                "./g/y.txt"
+            ]
+        );
+
+        let mut unique = reads.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), reads.len());
+    }
+
+    #[test]
+    fn prunes_only_on_what_is_within_the_depth_limit() {
+        let (paths, _) = walk(b"a/b/c/x.rs\ng/y.txt\n", Some(1), vec![]);
+
+        assert_eq!(paths, ["./g", "./g/y.txt"]);
+    }
+
+    #[test]
+    fn type_predicates_match_directories() {
+        let (paths, _) = walk(
+            b"a/b/\ne/x.rs\n",
+            None,
+            vec![Predicate::parse_type("d").unwrap()],
+        );
+
+        assert_eq!(paths, ["./a", "./a/b", "./e"]);
+    }
+
+    #[test]
+    fn sorts_by_modification_time() {
+        let source = || {
+            let mut source = VirtualSource::new(PathBuf::from("."));
+
+            for (name, secs) in [("b", 1), ("a", 2), ("c", 1)] {
+                let metadata = Metadata {
+                    modified: Some(Duration::from_secs(secs)),
+                    ..Metadata::file()
+                };
+                source.insert(Path::new(name), metadata, None);
+            }
+
+            source
+        };
+
+        let names = |rev_alpha_sort| {
+            let mut flags = TreeIteratorFlags {
+                last_mod_sort: true,
+                rev_alpha_sort,
+                ..flags()
+            };
+
+            Tree::new(&mut flags, vec![], vec![])
+                .with_source(Box::new(source()))
+                .into_iter()
+                .skip(1)
+                .map(|(_, entry)| entry.path().display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(false), ["./b", "./c", "./a"]);
+        assert_eq!(names(true), ["./a", "./c", "./b"]);
+    }
+
+    #[test]
+    fn file_limit_skips_large_directories() {
+        let source = VirtualSource::from_listing(
+            PathBuf::from("."),
+            b"a/x\na/y\na/z\nb/z\n",
+        );
+        let mut flags = TreeIteratorFlags {
+            file_limit: Some(2),
+            ..flags()
+        };
+
+        let entries = Tree::new(&mut flags, vec![], vec![])
+            .with_source(Box::new(source))
+            .into_iter()
+            .skip(1)
+            .map(|(_, entry)| {
+                (entry.path().display().to_string(), entry.exceeds_limit)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            entries,
+            [
+                (String::from("./a"), Some(3)),
+                (String::from("./b"), None),
+                (String::from("./b/z"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_fs_does_not_descend_into_other_devices() {
+        let mut source =
+            VirtualSource::from_listing(PathBuf::from("."), b"mnt/x\nsrc/y\n");
+        let mounted = Metadata {
+            dev: 1,
+            ..Metadata::directory()
+        };
+        source.insert(Path::new("mnt"), mounted, None);
+
+        let mut flags = TreeIteratorFlags {
+            one_fs: true,
+            ..flags()
+        };
+
+        let paths = Tree::new(&mut flags, vec![], vec![])
+            .with_source(Box::new(source))
+            .into_iter()
+            .skip(1)
+            .map(|(_, entry)| entry.path().display().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(paths, ["./mnt", "./src", "./src/y"]);
+    }
+
+    #[test]
+    fn counts_a_vanished_root_as_unreadable() {
+        let mut flags = TreeIteratorFlags {
+            root: Some(PathBuf::from("tests/no_such_dir")),
+            ..flags()
+        };
+
+        let mut entries = Tree::new(&mut flags, vec![], vec![]).into_iter();
+
+        assert!(entries.next().is_none());
+        assert_eq!(entries.stats().unreadable, 1);
+    }
+
+    #[test]
+    fn filters_see_the_leading_dot() {
+        let source =
+            VirtualSource::from_listing(PathBuf::from("."), b".env\nenv\n");
+        let mut flags = TreeIteratorFlags {
+            visit_all: true,
+            ..flags()
+        };
+        let filters: Vec<Box<dyn Filter>> =
+            vec![Box::new(Regex::parse("^\\.", true).unwrap())];
+
+        let paths = Tree::new(&mut flags, filters, vec![])
+            .with_source(Box::new(source))
+            .into_iter()
+            .skip(1)
+            .map(|(_, entry)| entry.path().display().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(paths, ["./.env"]);
+    }
+}
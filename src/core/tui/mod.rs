@@ -0,0 +1,800 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub mod term;
+
+use term::{Key, Terminal};
+
+use super::charset::Charset;
+use super::colors::Colors;
+use super::dirent::DirEntry;
+use super::display::{column_widths, format_info};
+use super::escape::NameEscaper;
+use super::pattern::Pattern;
+use super::tree::Tree;
+
+use crate::cli::{Cmd, Flags, TreeIteratorFlags};
+
+const DEFAULT_INDENT: &str = "    ";
+const HINTS: &str =
+    "arrows/hjkl move  / filter  . hidden  m metadata  enter select  q quit";
+
+// How the browser was left.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Select(PathBuf),
+    Quit,
+}
+
+// One loaded entry. Nodes are kept in display order, every directory's
+// children straight after it, and stay loaded when it's collapsed so
+// reopening it doesn't hit the disk again.
+struct Node {
+    entry: DirEntry,
+    expanded: bool,
+    loaded: bool,
+}
+
+impl Node {
+    fn new(entry: DirEntry) -> Self {
+        Self {
+            entry,
+            expanded: false,
+            loaded: false,
+        }
+    }
+}
+
+// --interactive: the tree as a full-screen outline where directories are
+// read one level at a time as they're opened.
+pub struct Browser<'a> {
+    // a copy whose metadata switches and -a follow the toggles
+    cmd: Cmd,
+    colors: &'a Colors,
+    new_tree: &'a dyn Fn(&mut TreeIteratorFlags) -> Tree,
+    nodes: Vec<Node>,
+    // index into `nodes`
+    selected: usize,
+    // the first visible row on screen
+    scroll: usize,
+    query: String,
+    editing: bool,
+    // what `m` switches on: the columns from the command line, or
+    // permissions and size if none were asked for
+    columns: [bool; 8],
+    show_metadata: bool,
+}
+
+impl<'a> Browser<'a> {
+    pub fn new(
+        cmd: &Cmd,
+        colors: &'a Colors,
+        new_tree: &'a dyn Fn(&mut TreeIteratorFlags) -> Tree,
+    ) -> Self {
+        let mut cmd = cmd.clone();
+        let mut columns = [false; 8];
+
+        for (column, flag) in columns.iter_mut().zip(metadata(&mut cmd.flags)) {
+            *column = *flag;
+        }
+
+        let show_metadata = columns.contains(&true);
+
+        if !show_metadata {
+            columns[0] = true;
+            columns[2] = true;
+        }
+
+        let mut browser = Self {
+            cmd,
+            colors,
+            new_tree,
+            nodes: vec![],
+            selected: 0,
+            scroll: 0,
+            query: String::new(),
+            editing: false,
+            columns,
+            show_metadata,
+        };
+
+        let roots = browser.cmd.flags.dir_paths.clone();
+        browser.open_roots(&roots, &HashSet::new());
+
+        browser
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Runs until something is selected or the browser is quit.
+    pub fn run(&mut self, terminal: &mut Terminal) -> io::Result<Outcome> {
+        loop {
+            let (width, height) = terminal.size();
+            let frame = self.render(width, height);
+            terminal.draw(&frame)?;
+
+            let page = height.saturating_sub(1).max(1);
+
+            if let Some(outcome) = self.handle(terminal.read_key()?, page) {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    // The entry at `path` followed by its children, walked by the same
+    // TreeIterator, filters and predicates as the plain tree.
+    fn list(&self, path: &Path) -> Vec<DirEntry> {
+        // it may have been removed since its parent was read
+        if !path.exists() {
+            return vec![];
+        }
+
+        let mut flags = self.cmd.get_tree_iter_flags(path.to_path_buf());
+        // one level at a time, deeper ones are read when they're opened
+        flags.max_depth = Some(0);
+
+        (self.new_tree)(&mut flags)
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    // Lists every root, and again every directory in `expanded` below
+    // them.
+    fn open_roots(&mut self, roots: &[PathBuf], expanded: &HashSet<PathBuf>) {
+        for root in roots {
+            let Some(entry) = self.list(root).into_iter().next() else {
+                continue;
+            };
+
+            self.nodes.push(Node::new(entry));
+            self.expand(self.nodes.len() - 1);
+        }
+
+        let mut idx = 0;
+
+        while idx < self.nodes.len() {
+            if expanded.contains(self.nodes[idx].entry.path()) {
+                self.expand(idx);
+            }
+
+            idx += 1;
+        }
+    }
+
+    fn is_expandable(&self, idx: usize) -> bool {
+        let entry = &self.nodes[idx].entry;
+
+        entry.is_dir()
+            || (self.cmd.flags.follow_symlinks
+                && entry.is_symlink()
+                && entry.path().is_dir())
+    }
+
+    fn expand(&mut self, idx: usize) {
+        if !self.is_expandable(idx) {
+            return;
+        }
+
+        if !self.nodes[idx].loaded {
+            let depth = self.nodes[idx].entry.depth;
+            let mut entries =
+                self.list(self.nodes[idx].entry.path()).into_iter();
+
+            // the directory itself comes first, read again just now
+            if let Some(entry) = entries.next() {
+                self.nodes[idx].entry.is_unreadable = entry.is_unreadable;
+                self.nodes[idx].entry.exceeds_limit = entry.exceeds_limit;
+            }
+
+            let children = entries
+                .map(|mut entry| {
+                    entry.depth = depth + 1;
+                    Node::new(entry)
+                })
+                .collect::<Vec<_>>();
+
+            if self.selected > idx {
+                self.selected += children.len();
+            }
+
+            self.nodes.splice(idx + 1..idx + 1, children);
+            self.nodes[idx].loaded = true;
+        }
+
+        self.nodes[idx].expanded = true;
+    }
+
+    fn parent(&self, idx: usize) -> Option<usize> {
+        let depth = self.nodes[idx].entry.depth;
+
+        self.nodes[..idx]
+            .iter()
+            .rposition(|node| node.entry.depth < depth)
+    }
+
+    // The typed filter, once it's complete enough to parse.
+    fn filter(&self) -> Option<Pattern> {
+        (!self.query.is_empty() && is_complete_pattern(&self.query))
+            .then(|| Pattern::parse(&self.query, true))
+    }
+
+    // Node indices in the order they're drawn. A filter shows every
+    // loaded entry it matches, collapsed or not, along with the
+    // directories leading to it.
+    fn visible(&self) -> Vec<usize> {
+        let Some(pattern) = self.filter() else {
+            let mut rows = vec![];
+            let mut collapsed_at: Option<usize> = None;
+
+            for (idx, node) in self.nodes.iter().enumerate() {
+                let depth = node.entry.depth;
+
+                if collapsed_at.is_some_and(|collapsed| depth > collapsed) {
+                    continue;
+                }
+
+                collapsed_at = (!node.expanded).then_some(depth);
+                rows.push(idx);
+            }
+
+            return rows;
+        };
+
+        let mut keep = vec![false; self.nodes.len()];
+        // walking backwards, the next entry shallower than the last kept
+        // one is its parent
+        let mut kept_depth: Option<usize> = None;
+
+        for (idx, node) in self.nodes.iter().enumerate().rev() {
+            let depth = node.entry.depth;
+
+            if depth == 0 {
+                keep[idx] = true;
+                kept_depth = None;
+            } else if kept_depth.is_some_and(|kept| depth < kept)
+                || matches(&pattern, &node.entry.get_clean_name())
+            {
+                keep[idx] = true;
+                kept_depth = Some(depth);
+            }
+        }
+
+        (0..self.nodes.len()).filter(|idx| keep[*idx]).collect()
+    }
+
+    // Moves the selection by `delta` rows, stopping at either end.
+    fn move_by(&mut self, rows: &[usize], delta: isize) {
+        let position = rows
+            .iter()
+            .position(|idx| *idx == self.selected)
+            .unwrap_or(0);
+        let position = position
+            .saturating_add_signed(delta)
+            .min(rows.len().saturating_sub(1));
+
+        if let Some(idx) = rows.get(position) {
+            self.selected = *idx;
+        }
+    }
+
+    // Keeps the selection on screen after the visible rows change,
+    // preferring the first entry the filter matches.
+    fn reselect(&mut self) {
+        let rows = self.visible();
+
+        if rows.contains(&self.selected) {
+            return;
+        }
+
+        let pattern = self.filter();
+
+        self.selected = rows
+            .iter()
+            .copied()
+            .find(|idx| {
+                pattern.as_ref().is_some_and(|pattern| {
+                    matches(pattern, &self.nodes[*idx].entry.get_clean_name())
+                })
+            })
+            .or(rows.first().copied())
+            .unwrap_or(0);
+    }
+
+    // Reads everything again, e.g. after hidden files are toggled,
+    // keeping open whatever was open.
+    fn reload(&mut self) {
+        let roots = self
+            .nodes
+            .iter()
+            .filter(|node| node.entry.depth == 0)
+            .map(|node| node.entry.path().to_path_buf())
+            .collect::<Vec<_>>();
+        let expanded = self
+            .nodes
+            .iter()
+            .filter(|node| node.expanded)
+            .map(|node| node.entry.path().to_path_buf())
+            .collect::<HashSet<_>>();
+        let selected = self.nodes[self.selected].entry.path().to_path_buf();
+
+        self.nodes.clear();
+        self.selected = 0;
+        self.open_roots(&roots, &expanded);
+
+        if let Some(idx) = self
+            .nodes
+            .iter()
+            .position(|node| node.entry.path() == selected)
+        {
+            self.selected = idx;
+        }
+
+        self.reselect();
+    }
+
+    fn toggle_metadata(&mut self) {
+        self.show_metadata = !self.show_metadata;
+
+        for (flag, column) in
+            metadata(&mut self.cmd.flags).into_iter().zip(self.columns)
+        {
+            *flag = column && self.show_metadata;
+        }
+    }
+
+    // Applies a key press. `page` is how far PageUp and PageDown move.
+    pub fn handle(&mut self, key: Key, page: usize) -> Option<Outcome> {
+        if self.editing {
+            match key {
+                Key::Char(ch) => self.query.push(ch),
+                Key::Backspace => {
+                    self.query.pop();
+                }
+                Key::Enter => self.editing = false,
+                Key::Esc => {
+                    self.query.clear();
+                    self.editing = false;
+                }
+                _ => return self.handle_command(key, page),
+            }
+
+            self.reselect();
+            return None;
+        }
+
+        self.handle_command(key, page)
+    }
+
+    fn handle_command(&mut self, key: Key, page: usize) -> Option<Outcome> {
+        let rows = self.visible();
+        let page = isize::try_from(page).unwrap_or(isize::MAX);
+
+        match key {
+            Key::Char('q') | Key::Ctrl('c') => return Some(Outcome::Quit),
+            Key::Esc if self.query.is_empty() => return Some(Outcome::Quit),
+            Key::Esc => {
+                self.query.clear();
+                self.reselect();
+            }
+            Key::Enter => {
+                let path = self.nodes[self.selected].entry.path();
+                return Some(Outcome::Select(path.to_path_buf()));
+            }
+            Key::Up | Key::Char('k') => self.move_by(&rows, -1),
+            Key::Down | Key::Char('j') => self.move_by(&rows, 1),
+            Key::PageUp => self.move_by(&rows, -page),
+            Key::PageDown => self.move_by(&rows, page),
+            Key::Home | Key::Char('g') => self.move_by(&rows, isize::MIN),
+            Key::End | Key::Char('G') => self.move_by(&rows, isize::MAX),
+            Key::Right | Key::Char('l') => {
+                if self.nodes[self.selected].expanded {
+                    self.move_by(&rows, 1);
+                } else {
+                    self.expand(self.selected);
+                }
+            }
+            Key::Left | Key::Char('h') => {
+                if self.nodes[self.selected].expanded {
+                    self.nodes[self.selected].expanded = false;
+                } else if let Some(parent) = self.parent(self.selected) {
+                    self.selected = parent;
+                }
+            }
+            Key::Tab | Key::Char(' ') => {
+                if self.nodes[self.selected].expanded {
+                    self.nodes[self.selected].expanded = false;
+                } else {
+                    self.expand(self.selected);
+                }
+            }
+            Key::Char('/') => self.editing = true,
+            Key::Char('.') => {
+                self.cmd.flags.all = !self.cmd.flags.all;
+                self.reload();
+            }
+            Key::Char('m') => self.toggle_metadata(),
+            _ => {}
+        }
+
+        None
+    }
+
+    // A whole screen: the rows around the selection and a status line.
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<u8> {
+        let rows = self.visible();
+        let area = height.saturating_sub(1).max(1);
+
+        // scroll just far enough to keep the selection in view
+        let position = rows
+            .iter()
+            .position(|idx| *idx == self.selected)
+            .unwrap_or(0);
+
+        if position < self.scroll {
+            self.scroll = position;
+        } else if position >= self.scroll + area {
+            self.scroll = position + 1 - area;
+        }
+
+        self.scroll = self.scroll.min(rows.len().saturating_sub(area));
+
+        let escaper = NameEscaper::from_flags(&self.cmd.flags);
+        let connectors = self
+            .cmd
+            .flags
+            .charset
+            .unwrap_or_else(Charset::from_locale)
+            .connectors();
+
+        let infos = rows
+            .iter()
+            .map(|idx| {
+                self.nodes[*idx]
+                    .entry
+                    .get_additional_info(&self.cmd, self.colors)
+            })
+            .collect::<Vec<_>>();
+
+        // roots never show metadata, like the plain tree
+        let widths = column_widths(
+            rows.iter()
+                .zip(&infos)
+                .filter(|(idx, _)| self.nodes[**idx].entry.depth != 0)
+                .map(|(_, info)| info.as_slice()),
+        );
+
+        let has_next = self.has_next_sibling(&rows);
+        let filtering = self.filter().is_some();
+        let mut has_remaining = HashSet::new();
+        let mut frame = vec![];
+
+        for (position, idx) in rows.iter().enumerate().take(self.scroll + area)
+        {
+            let node = &self.nodes[*idx];
+            let entry = &node.entry;
+            let depth = entry.depth;
+
+            let mut line = vec![];
+
+            if depth != 0 {
+                for level in 1..depth {
+                    let outer_connector = if has_remaining.contains(&level) {
+                        connectors.vertical
+                    } else {
+                        b" "
+                    };
+
+                    line.extend_from_slice(outer_connector);
+                    line.extend_from_slice(DEFAULT_INDENT.as_bytes());
+                }
+
+                let connector = if has_next[position] {
+                    has_remaining.insert(depth);
+                    connectors.tee
+                } else {
+                    has_remaining.remove(&depth);
+                    connectors.last
+                };
+
+                line.extend_from_slice(connector);
+                line.extend_from_slice(connectors.horizontal);
+                line.push(b' ');
+                line.extend(format_info(&infos[position], &widths).as_bytes());
+            }
+
+            if position < self.scroll {
+                continue;
+            }
+
+            let (fg_bg, _) = self.colors.get_color_esc_seq(
+                entry.get_file_type(),
+                &entry.get_name().unwrap_or_default().to_string_lossy(),
+            );
+
+            line.extend_from_slice(fg_bg.as_bytes());
+
+            // reverse video after the color so the color survives it
+            if *idx == self.selected {
+                line.extend_from_slice(b"\x1b[7m");
+            }
+
+            line.extend(
+                escaper.escape(
+                    entry.get_name().unwrap_or(entry.path().as_os_str()),
+                ),
+            );
+            line.extend_from_slice(b"\x1b[0m");
+
+            // a filter shows what's loaded whether it's expanded or not
+            let is_closed = !node.loaded || (!node.expanded && !filtering);
+
+            if self.is_expandable(*idx) && is_closed {
+                line.extend_from_slice(b" [+]");
+            }
+
+            if entry.is_unreadable {
+                line.extend_from_slice(b" [error opening dir]");
+            }
+
+            if let Some(count) = entry.exceeds_limit {
+                line.extend(
+                    format!(
+                        " [{count} entries exceeds filelimit, not opening dir]"
+                    )
+                    .as_bytes(),
+                );
+            }
+
+            if let Some(path) = entry.linked_path() {
+                line.extend_from_slice(b" -> ");
+                line.extend(escaper.escape(path.as_os_str()));
+            }
+
+            let row = position - self.scroll + 1;
+            frame.extend(format!("\x1b[{row};1H").as_bytes());
+            frame.extend(line);
+            frame.extend_from_slice(b"\x1b[K");
+        }
+
+        for row in rows.len().saturating_sub(self.scroll) + 1..=area {
+            frame.extend(format!("\x1b[{row};1H\x1b[K").as_bytes());
+        }
+
+        let status = if self.editing {
+            format!("/{}", self.query)
+        } else if !self.query.is_empty() {
+            format!("filter: {}  (/ edit, esc clear)", self.query)
+        } else {
+            String::from(HINTS)
+        };
+
+        let status = status.chars().take(width).collect::<String>();
+        frame.extend(
+            format!("\x1b[{height};1H\x1b[7m{status:<width$}\x1b[0m")
+                .as_bytes(),
+        );
+
+        frame
+    }
+
+    // For each visible row, whether a later row shares its parent, which
+    // picks a tee rather than an elbow for it.
+    fn has_next_sibling(&self, rows: &[usize]) -> Vec<bool> {
+        let mut has_next = vec![false; rows.len()];
+        // walking backwards, whether a row was seen at each depth since
+        // the last shallower one
+        let mut seen: Vec<bool> = vec![];
+
+        for (position, idx) in rows.iter().enumerate().rev() {
+            let depth = self.nodes[*idx].entry.depth;
+
+            seen.truncate(depth + 1);
+            seen.resize(depth + 1, false);
+            has_next[position] = seen[depth];
+            seen[depth] = true;
+        }
+
+        has_next
+    }
+}
+
+// The switches behind the metadata columns, in the order of `columns`.
+fn metadata(flags: &mut Flags) -> [&mut bool; 8] {
+    [
+        &mut flags.protections,
+        &mut flags.size,
+        &mut flags.human_readable_size,
+        &mut flags.last_modified,
+        &mut flags.inode,
+        &mut flags.device,
+        &mut flags.username,
+        &mut flags.group,
+    ]
+}
+
+// Pattern matches from the start of a name, so trying every suffix turns
+// plain text into a substring search while globs keep working.
+fn matches(pattern: &Pattern, name: &str) -> bool {
+    name.char_indices()
+        .any(|(idx, _)| pattern.is_match(&name[idx..]))
+}
+
+// Pattern::parse gives up on bracket groups that are still being typed,
+// so the filter only applies once every group is closed and well formed.
+fn is_complete_pattern(query: &str) -> bool {
+    let mut group: Option<String> = None;
+
+    for ch in query.chars() {
+        match (&mut group, ch) {
+            (None, '[') => group = Some(String::new()),
+            (None, ']') => return false,
+            (None, _) => {}
+            (Some(chars), ']') => {
+                if chars.is_empty()
+                    || chars.starts_with('-')
+                    || chars.ends_with('-')
+                {
+                    return false;
+                }
+
+                group = None;
+            }
+            (Some(chars), ch) => chars.push(ch),
+        }
+    }
+
+    group.is_none()
+}
+
+// Opens the terminal and browses until a path is picked or the browser is
+// quit.
+pub fn browse(
+    cmd: &Cmd,
+    colors: &Colors,
+    new_tree: &dyn Fn(&mut TreeIteratorFlags) -> Tree,
+) -> io::Result<Outcome> {
+    let mut browser = Browser::new(cmd, colors, new_tree);
+
+    if browser.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "nothing to browse",
+        ));
+    }
+
+    let mut terminal = Terminal::open()?;
+
+    browser.run(&mut terminal)
+}
+
+#[cfg(test)]
+mod tui_tests {
+    use super::*;
+
+    fn browse_mock_dir(test: impl FnOnce(&mut Browser)) {
+        let cmd = Cmd {
+            flags: Flags {
+                dir_paths: vec![PathBuf::from("tests/mock_dir")],
+                charset: Some(Charset::Ascii),
+                ..Flags::default()
+            },
+        };
+        let colors = Colors::default();
+        let new_tree =
+            |flags: &mut TreeIteratorFlags| Tree::new(flags, vec![], vec![]);
+
+        test(&mut Browser::new(&cmd, &colors, &new_tree));
+    }
+
+    fn names(browser: &Browser) -> Vec<String> {
+        browser
+            .visible()
+            .into_iter()
+            .map(|idx| {
+                let entry = &browser.nodes[idx].entry;
+                format!(
+                    "{}{}",
+                    "  ".repeat(entry.depth),
+                    entry.get_clean_name()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn opens_directories_lazily() {
+        browse_mock_dir(|browser| {
+            assert_eq!(
+                names(browser),
+                ["mock_dir", "  A", "  A.rs", "  B", "  C", "  sym_linked"]
+            );
+            assert!(!browser.nodes[1].loaded);
+
+            browser.handle(Key::Down, 10);
+            browser.handle(Key::Right, 10);
+            browser.handle(Key::Down, 10);
+            browser.handle(Key::Right, 10);
+
+            assert_eq!(
+                names(browser)[1..4],
+                ["  A", "    A_NESTED", "    a.rs"]
+            );
+
+            // collapse A_NESTED, up to A, collapse A
+            browser.handle(Key::Char('h'), 10);
+            browser.handle(Key::Char('h'), 10);
+            browser.handle(Key::Char('h'), 10);
+
+            assert_eq!(names(browser).len(), 6);
+            assert!(browser.nodes[1].loaded);
+            assert_eq!(
+                browser.handle(Key::Enter, 10),
+                Some(Outcome::Select(PathBuf::from("tests/mock_dir/A")))
+            );
+        });
+    }
+
+    #[test]
+    fn filters_loaded_entries_and_toggles_hidden() {
+        browse_mock_dir(|browser| {
+            browser.handle(Key::Char('j'), 10);
+            browser.handle(Key::Char('l'), 10);
+
+            for key in "/b.rs".chars() {
+                browser.handle(Key::Char(key), 10);
+            }
+
+            assert_eq!(names(browser), ["mock_dir", "  A", "    b.rs"]);
+            assert_eq!(
+                browser.handle(Key::Enter, 10),
+                None,
+                "enter ends editing"
+            );
+            assert_eq!(
+                browser.handle(Key::Enter, 10),
+                Some(Outcome::Select(PathBuf::from("tests/mock_dir/A")))
+            );
+
+            browser.handle(Key::Esc, 10);
+            browser.handle(Key::Char('.'), 10);
+
+            assert!(names(browser).contains(&String::from("  hidden")));
+            assert!(names(browser).contains(&String::from("    b.rs")));
+            assert_eq!(browser.handle(Key::Char('q'), 10), Some(Outcome::Quit));
+        });
+    }
+
+    #[test]
+    fn renders_connectors_and_metadata() {
+        browse_mock_dir(|browser| {
+            browser.handle(Key::Char('m'), 10);
+
+            let frame = String::from_utf8(browser.render(40, 4)).unwrap();
+
+            assert!(frame.starts_with(
+                "\x1b[1;1H\x1b[7mmock_dir\x1b[0m\x1b[K\
+                 \x1b[2;1H|--- [drwxrwxr-x"
+            ));
+            assert!(frame.contains("] A.rs\x1b[0m\x1b[K\x1b[4;1H\x1b[7m"));
+
+            browser.handle(Key::End, 10);
+            let frame = String::from_utf8(browser.render(40, 4)).unwrap();
+
+            assert!(frame.contains("\x1b[3;1H`--- [lrwxrwxrwx"));
+        });
+    }
+
+    #[test]
+    fn waits_for_bracket_groups_to_close() {
+        assert!(is_complete_pattern("*.rs"));
+        assert!(is_complete_pattern("[ab]c"));
+        assert!(!is_complete_pattern("[ab"));
+        assert!(!is_complete_pattern("]"));
+        assert!(!is_complete_pattern("[-a]"));
+        assert!(!is_complete_pattern("[a-]"));
+    }
+}
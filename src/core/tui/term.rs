@@ -0,0 +1,249 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+
+// Alternate screen, hidden cursor and no line wrapping, so long lines are
+// clipped at the right edge instead of pushing the rest of the frame down.
+const ENTER_SCREEN: &[u8] = b"\x1b[?1049h\x1b[?25l\x1b[?7l";
+const LEAVE_SCREEN: &[u8] = b"\x1b[?7h\x1b[?25h\x1b[?1049l";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Enter,
+    Tab,
+    Backspace,
+    Esc,
+    Ctrl(char),
+    Char(char),
+}
+
+// The controlling terminal in raw mode. Frames are drawn on /dev/tty
+// rather than stdout, which is left free for the selected path. The
+// previous settings are restored on drop, panics included.
+pub struct Terminal {
+    tty: File,
+    original: libc::termios,
+    pending: Vec<Key>,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+
+        // SAFETY: termios is plain old data, and tcgetattr fills it in
+        // before it's read
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+
+        // SAFETY: the fd stays open for as long as `tty` lives
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // no line buffering, echo or signal keys, but output processing
+        // stays on so "\n" still returns the carriage
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        // SAFETY: as above, and `raw` is a valid termios
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSAFLUSH, &raw) }
+            != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut terminal = Self {
+            tty,
+            original,
+            pending: vec![],
+        };
+        terminal.tty.write_all(ENTER_SCREEN)?;
+
+        Ok(terminal)
+    }
+
+    // (columns, rows), or 80x24 if the terminal won't say.
+    pub fn size(&self) -> (usize, usize) {
+        // SAFETY: winsize is plain old data
+        let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+
+        // SAFETY: TIOCGWINSZ only writes a winsize through the pointer
+        let ret = unsafe {
+            libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size)
+        };
+
+        if ret != 0 || size.ws_col == 0 || size.ws_row == 0 {
+            (80, 24)
+        } else {
+            (usize::from(size.ws_col), usize::from(size.ws_row))
+        }
+    }
+
+    pub fn draw(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.tty.write_all(frame)?;
+        self.tty.flush()
+    }
+
+    // Blocks until a key is pressed.
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        loop {
+            if !self.pending.is_empty() {
+                return Ok(self.pending.remove(0));
+            }
+
+            // an escape sequence arrives in a single read, so a lone ESC
+            // byte is the Esc key
+            let mut buf = [0; 64];
+            let len = match self.tty.read(&mut buf) {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            self.pending = decode(&buf[..len]);
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(LEAVE_SCREEN);
+
+        // SAFETY: the fd is still open and `original` came from tcgetattr
+        unsafe {
+            libc::tcsetattr(
+                self.tty.as_raw_fd(),
+                libc::TCSAFLUSH,
+                &self.original,
+            )
+        };
+    }
+}
+
+// Splits one read from the terminal into keys. Sequences that aren't
+// recognised are dropped whole.
+pub fn decode(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut rest = bytes;
+
+    while let Some(&byte) = rest.first() {
+        let (key, len) = match byte {
+            0x1b => decode_escape(rest),
+            b'\r' | b'\n' => (Some(Key::Enter), 1),
+            b'\t' => (Some(Key::Tab), 1),
+            0x7f | 0x08 => (Some(Key::Backspace), 1),
+            0x01..=0x1a => (Some(Key::Ctrl(char::from(b'a' + byte - 1))), 1),
+            _ => decode_char(rest),
+        };
+
+        keys.extend(key);
+        rest = &rest[len..];
+    }
+
+    keys
+}
+
+fn decode_escape(bytes: &[u8]) -> (Option<Key>, usize) {
+    match bytes {
+        [_] => (Some(Key::Esc), 1),
+        // ESC O x is what keypad mode sends for arrows, home and end
+        [_, b'O', final_byte, ..] => (letter_key(*final_byte), 3),
+        [_, b'[', rest @ ..] => {
+            // parameters and intermediates up to a final byte in @..~
+            let Some(end) =
+                rest.iter().position(|byte| (0x40..=0x7e).contains(byte))
+            else {
+                return (None, bytes.len());
+            };
+
+            let key = match (&rest[..end], rest[end]) {
+                (b"", final_byte) => letter_key(final_byte),
+                (b"1" | b"7", b'~') => Some(Key::Home),
+                (b"4" | b"8", b'~') => Some(Key::End),
+                (b"5", b'~') => Some(Key::PageUp),
+                (b"6", b'~') => Some(Key::PageDown),
+                _ => None,
+            };
+
+            (key, end + 3)
+        }
+        // Alt+key, or ESC typed just before another key
+        _ => (Some(Key::Esc), 1),
+    }
+}
+
+fn letter_key(byte: u8) -> Option<Key> {
+    match byte {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        b'H' => Some(Key::Home),
+        b'F' => Some(Key::End),
+        _ => None,
+    }
+}
+
+fn decode_char(bytes: &[u8]) -> (Option<Key>, usize) {
+    let len = match bytes[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    }
+    .min(bytes.len());
+
+    let key = std::str::from_utf8(&bytes[..len])
+        .ok()
+        .and_then(|text| text.chars().next())
+        .filter(|ch| !ch.is_control())
+        .map(Key::Char);
+
+    (key, len)
+}
+
+#[cfg(test)]
+mod term_tests {
+    use super::*;
+
+    #[test]
+    fn decodes_keys_and_escape_sequences() {
+        assert_eq!(
+            decode(b"j\x1b[A\x1bOB\x1b[5~\r\x7f"),
+            [
+                Key::Char('j'),
+                Key::Up,
+                Key::Down,
+                Key::PageUp,
+                Key::Enter,
+                Key::Backspace
+            ]
+        );
+        assert_eq!(decode(b"\x1b"), [Key::Esc]);
+        assert_eq!(decode(b"\x03"), [Key::Ctrl('c')]);
+        assert_eq!(decode("é/".as_bytes()), [Key::Char('é'), Key::Char('/')]);
+    }
+
+    #[test]
+    fn drops_unknown_sequences() {
+        assert_eq!(decode(b"\x1b[1;5Cq"), [Key::Char('q')]);
+        assert_eq!(decode(b"\x1b[200~x"), [Key::Char('x')]);
+        assert_eq!(decode(b"\x1b[12"), []);
+    }
+}
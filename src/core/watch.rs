@@ -0,0 +1,457 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::colors::Colors;
+use super::display::{format_report, Display, Report};
+use super::tree::Tree;
+
+use crate::cli::{Cmd, TreeIteratorFlags};
+
+// How long a change stays highlighted.
+const HIGHLIGHT_FOR: Duration = Duration::from_secs(3);
+// Builds write in bursts, so events are gathered for a moment before
+// redrawing.
+const SETTLE: Duration = Duration::from_millis(100);
+
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF;
+
+// wd, mask, cookie and name length, before the name itself
+const EVENT_HEADER: usize = 16;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Change {
+    // created, moved in or written to
+    Added(PathBuf),
+    // deleted or moved out
+    Removed(PathBuf),
+}
+
+// An inotify instance watching the directories a tree was read from.
+pub struct Inotify {
+    fd: OwnedFd,
+    watches: HashMap<i32, PathBuf>,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: no pointers involved, the result is checked below
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            // SAFETY: the fd was just opened and nothing else owns it
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watches: HashMap::new(),
+        })
+    }
+
+    // Watches exactly `dirs`: new ones are added, ones no longer read
+    // are dropped.
+    pub fn watch_only(&mut self, dirs: &[PathBuf]) {
+        let wanted = dirs.iter().map(PathBuf::as_path).collect::<HashSet<_>>();
+        let fd = self.fd.as_raw_fd();
+
+        self.watches.retain(|wd, path| {
+            let keep = wanted.contains(path.as_path());
+
+            if !keep {
+                // SAFETY: plain integers, a stale wd just fails
+                unsafe { libc::inotify_rm_watch(fd, *wd) };
+            }
+
+            keep
+        });
+
+        for dir in dirs {
+            let Ok(c_path) = CString::new(dir.as_os_str().as_bytes()) else {
+                continue;
+            };
+
+            // SAFETY: c_path is NUL terminated and outlives the call
+            let wd =
+                unsafe { libc::inotify_add_watch(fd, c_path.as_ptr(), MASK) };
+
+            // a directory removed since the walk fails here, and the
+            // event for its removal has redrawn or will redraw anyway
+            if wd >= 0 {
+                self.watches.insert(wd, dir.clone());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.watches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    // Blocks for up to `timeout`, or until something happens if None.
+    // Returns None on timeout, and the changes otherwise, which can be
+    // empty when inotify only had bookkeeping to report.
+    pub fn wait(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<Vec<Change>>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+        });
+
+        // SAFETY: pollfd is valid for the one entry we pass
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+
+            return if err.kind() == io::ErrorKind::Interrupted {
+                Ok(Some(vec![]))
+            } else {
+                Err(err)
+            };
+        }
+
+        if ready == 0 {
+            return Ok(None);
+        }
+
+        let mut buf = [0_u8; 16 * 1024];
+
+        // SAFETY: buf is writable for its whole length
+        let len = unsafe {
+            libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len())
+        };
+
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Some(self.parse(&buf[..len.unsigned_abs()])))
+    }
+
+    // Turns raw inotify_event records into changes under the watched
+    // directories.
+    fn parse(&mut self, mut bytes: &[u8]) -> Vec<Change> {
+        let mut changes = vec![];
+
+        while bytes.len() >= EVENT_HEADER {
+            let field = |at: usize| {
+                u32::from_ne_bytes(bytes[at..at + 4].try_into().unwrap())
+            };
+
+            let wd = field(0) as i32;
+            let mask = field(4);
+            let name_len = field(12) as usize;
+
+            let end = (EVENT_HEADER + name_len).min(bytes.len());
+            // the name is padded with NULs to keep records aligned
+            let name = bytes[EVENT_HEADER..end]
+                .split(|byte| *byte == 0)
+                .next()
+                .unwrap_or_default();
+
+            bytes = &bytes[end..];
+
+            if mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&wd);
+                continue;
+            }
+
+            let Some(dir) = self.watches.get(&wd) else {
+                continue;
+            };
+
+            let path = if name.is_empty() {
+                dir.clone()
+            } else {
+                dir.join(OsStr::from_bytes(name))
+            };
+
+            if mask
+                & (libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_CLOSE_WRITE)
+                != 0
+            {
+                changes.push(Change::Added(path));
+            } else if mask
+                & (libc::IN_DELETE
+                    | libc::IN_MOVED_FROM
+                    | libc::IN_DELETE_SELF
+                    | libc::IN_MOVE_SELF)
+                != 0
+            {
+                changes.push(Change::Removed(path));
+            }
+        }
+
+        changes
+    }
+}
+
+// What changed lately, each with when it happened.
+#[derive(Debug, Default)]
+struct Recent {
+    added: HashMap<PathBuf, Instant>,
+    removed: Vec<(PathBuf, Instant)>,
+}
+
+impl Recent {
+    fn record(&mut self, change: Change, now: Instant) {
+        match change {
+            Change::Added(path) => {
+                self.removed.retain(|(removed, _)| *removed != path);
+                self.added.insert(path, now);
+            }
+            Change::Removed(path) => {
+                self.added.remove(&path);
+                self.removed.retain(|(removed, _)| *removed != path);
+                self.removed.push((path, now));
+            }
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.added
+            .retain(|_, at| now.duration_since(*at) < HIGHLIGHT_FOR);
+        self.removed
+            .retain(|(_, at)| now.duration_since(*at) < HIGHLIGHT_FOR);
+    }
+
+    // How long until the oldest highlight should fade.
+    fn next_expiry(&self, now: Instant) -> Option<Duration> {
+        self.added
+            .values()
+            .chain(self.removed.iter().map(|(_, at)| at))
+            .min()
+            .map(|at| (*at + HIGHLIGHT_FOR).saturating_duration_since(now))
+    }
+}
+
+// Draws every root, then redraws whenever a directory that was read
+// changes, until interrupted.
+pub fn watch(
+    cmd: &Cmd,
+    colors: &Colors,
+    new_tree: &dyn Fn(&mut TreeIteratorFlags) -> Tree,
+) -> io::Result<()> {
+    let mut inotify = Inotify::new()?;
+    let mut recent = Recent::default();
+    let mut stdout = io::stdout();
+
+    loop {
+        recent.expire(Instant::now());
+
+        let (frame, dirs) = render(cmd, colors, new_tree, &recent);
+        inotify.watch_only(&dirs);
+
+        let mut screen = b"\x1b[H".to_vec();
+
+        // each line clears what the last frame left to its right
+        for line in frame.split(|byte| *byte == b'\n') {
+            screen.extend(line);
+            screen.extend(b"\x1b[K\n");
+        }
+
+        screen.extend(
+            format!(
+                "watching {} {}, ctrl-c to stop\x1b[K\x1b[J",
+                inotify.len(),
+                if inotify.len() == 1 {
+                    "directory"
+                } else {
+                    "directories"
+                }
+            )
+            .as_bytes(),
+        );
+
+        stdout.write_all(&screen)?;
+        stdout.flush()?;
+
+        let Some(mut changes) =
+            inotify.wait(recent.next_expiry(Instant::now()))?
+        else {
+            continue;
+        };
+
+        // let the rest of a burst arrive before redrawing
+        std::thread::sleep(SETTLE);
+
+        while let Some(more) = inotify.wait(Some(Duration::ZERO))? {
+            changes.extend(more);
+        }
+
+        let now = Instant::now();
+
+        for change in changes {
+            recent.record(change, now);
+        }
+    }
+}
+
+// One frame: every tree, the report and what was recently removed, along
+// with the directories that were read.
+fn render(
+    cmd: &Cmd,
+    colors: &Colors,
+    new_tree: &dyn Fn(&mut TreeIteratorFlags) -> Tree,
+    recent: &Recent,
+) -> (Vec<u8>, Vec<PathBuf>) {
+    let highlight = recent.added.keys().cloned().collect::<HashSet<_>>();
+
+    let mut frame = vec![];
+    let mut dirs = vec![];
+    let mut report = Report::default();
+
+    for path in &cmd.flags.dir_paths {
+        if !path.exists() {
+            frame
+                .extend(format!("Path does not exist - {path:?}\n").as_bytes());
+            continue;
+        }
+
+        let mut flags = cmd.get_tree_iter_flags(path.clone());
+        let rendered =
+            Display::render(new_tree(&mut flags), cmd, colors, &highlight);
+
+        frame.extend(rendered.output);
+
+        // -x shows mount points without reading them, so they aren't
+        // watched either
+        let device = device_of(path).filter(|_| cmd.flags.one_fs);
+        dirs.extend(rendered.dirs.into_iter().filter(|dir| {
+            device.is_none_or(|device| device_of(dir) == Some(device))
+        }));
+        report += rendered.report;
+    }
+
+    if !cmd.flags.no_report {
+        frame.extend(format_report(&report, &cmd.flags, colors).as_bytes());
+        frame.push(b'\n');
+    }
+
+    for (path, _) in &recent.removed {
+        frame.extend(
+            format!("\x1b[7mremoved\x1b[0m {}\n", path.display()).as_bytes(),
+        );
+    }
+
+    (frame, dirs)
+}
+
+fn device_of(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("small-axe-{name}-{}", std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn wait_for_changes(inotify: &mut Inotify) -> Vec<Change> {
+        let mut changes = vec![];
+
+        while let Some(more) =
+            inotify.wait(Some(Duration::from_millis(200))).unwrap()
+        {
+            changes.extend(more);
+        }
+
+        changes
+    }
+
+    #[test]
+    fn reports_created_renamed_and_removed_entries() {
+        let dir = scratch_dir("watch");
+        let mut inotify = Inotify::new().unwrap();
+        inotify.watch_only(std::slice::from_ref(&dir));
+
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::rename(dir.join("a.txt"), dir.join("b.txt")).unwrap();
+        std::fs::remove_file(dir.join("b.txt")).unwrap();
+
+        assert_eq!(
+            wait_for_changes(&mut inotify),
+            [
+                Change::Added(dir.join("a.txt")),
+                Change::Added(dir.join("a.txt")),
+                Change::Removed(dir.join("a.txt")),
+                Change::Added(dir.join("b.txt")),
+                Change::Removed(dir.join("b.txt")),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stops_watching_dirs_that_are_no_longer_read() {
+        let dir = scratch_dir("unwatch");
+        let sub = dir.join("sub");
+        std::fs::create_dir(&sub).unwrap();
+
+        let mut inotify = Inotify::new().unwrap();
+        inotify.watch_only(&[dir.clone(), sub.clone()]);
+        assert_eq!(inotify.len(), 2);
+
+        inotify.watch_only(std::slice::from_ref(&dir));
+        std::fs::write(sub.join("ignored"), "").unwrap();
+
+        assert_eq!(inotify.len(), 1);
+        assert_eq!(wait_for_changes(&mut inotify), []);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forgets_changes_after_a_while() {
+        let start = Instant::now();
+        let mut recent = Recent::default();
+
+        recent.record(Change::Added(PathBuf::from("a")), start);
+        recent.record(Change::Removed(PathBuf::from("b")), start);
+        recent.record(
+            Change::Removed(PathBuf::from("a")),
+            start + Duration::from_secs(1),
+        );
+
+        assert!(recent.added.is_empty());
+        assert_eq!(recent.removed.len(), 2);
+        assert_eq!(recent.next_expiry(start), Some(HIGHLIGHT_FOR));
+
+        recent.expire(start + HIGHLIGHT_FOR);
+
+        assert_eq!(
+            recent.removed,
+            [(PathBuf::from("a"), start + Duration::from_secs(1))]
+        );
+    }
+}
@@ -1,88 +1,420 @@
-use crate::cli::{Cmd, TreeIteratorFlags};
+use crate::cli::{Cmd, Flags, TreeIteratorFlags};
+use crate::core::archive::{self, ArchiveKind};
 use crate::core::colors::Colors;
-use crate::core::display::Display;
-use crate::core::pattern::Pattern;
+use crate::core::compare;
+use crate::core::diff;
+use crate::core::display::{write_stdout, Display, Report};
+use crate::core::pattern::regex::{Regex, RegexError};
+use crate::core::pattern::{Filter, Pattern};
+use crate::core::predicate::{Predicate, PredicateError};
+use crate::core::snapshot::Snapshot;
+use crate::core::source::VirtualSource;
 use crate::core::tree::Tree;
+use crate::core::tui::{self, Outcome};
+#[cfg(target_os = "linux")]
+use crate::core::watch;
 
 pub mod cli;
 pub mod core;
 
-extern crate once_cell;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
 extern crate same_file;
 
-const HELP: &str = r"
-  usage: tree [-adfipshugqrtnoCFPIN] --[help version noreport inodes device dirsfirst prune filelimit] [path]
-
-  --help                    -- list all flags
-  --version                 -- prints version of tree
-  --noreport                -- silence total directory and file count
-  --inodes                  -- include inode of resource
-  --device                  -- include device id of resource
-  --dirsfirst               -- print directories before files
-  --prune                   -- remove empty directories from output
-  --filelimit [#]           -- skips directories with a file count over this limit
-  -D                        -- print last modified
-  -a                        -- include hidden files
-  -d                        -- include directories only
-  -f                        -- print full path of resource
-  -F                        -- print '/' to identify directories
-  -i                        -- no indentation
-  -l                        -- follow symlinks
-  -P [wildcard pattern]     -- include files and directories that match pattern
-  -I [wildcard pattern]     -- exclude files and directories that match pattern
-  -p                        -- print protections on resource
-  -s                        -- print resource size
-  -h                        -- print human readable resource size
-  -u                        -- print user name
-  -g                        -- print group
-  -q                        -- replace unprintable characters with '?'
-  -N                        -- print unprintable characters as is
-  -r                        -- reverse alphabetic sort
-  -t                        -- last modified sort
-  -n                        -- no colors
-  -C                        -- use ls colors
-  -L                        -- sets max-depth of tree traversal
-  -o                        -- output file path
-";
+fn build_filters(flags: &Flags) -> Result<Vec<Box<dyn Filter>>, RegexError> {
+    let mut filters: Vec<Box<dyn Filter>> = vec![];
+
+    if let Some(match_pattern) = &flags.pattern_match {
+        filters.push(Box::new(Pattern::parse(match_pattern.as_str(), true)));
+    }
+
+    if let Some(exclude_pattern) = &flags.pattern_exclude {
+        filters.push(Box::new(Pattern::parse(exclude_pattern.as_str(), false)));
+    }
+
+    // config file ignores apply alongside -I
+    for ignore in &flags.ignore {
+        filters.push(Box::new(Pattern::parse(ignore.as_str(), false)));
+    }
+
+    if let Some(match_regex) = &flags.regex_match {
+        filters.push(Box::new(Regex::parse(match_regex.as_str(), true)?));
+    }
+
+    if let Some(exclude_regex) = &flags.regex_exclude {
+        filters.push(Box::new(Regex::parse(exclude_regex.as_str(), false)?));
+    }
+
+    Ok(filters)
+}
+
+fn build_predicates(flags: &Flags) -> Result<Vec<Predicate>, PredicateError> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+
+    let mut predicates = vec![];
+
+    if let Some(size) = &flags.size_filter {
+        predicates.push(Predicate::parse_size(size)?);
+    }
+
+    if let Some(newer) = &flags.newer {
+        predicates.push(Predicate::parse_newer(newer, now)?);
+    }
+
+    if let Some(older_than) = &flags.older_than {
+        predicates.push(Predicate::parse_older_than(older_than, now)?);
+    }
+
+    if let Some(entry_type) = &flags.entry_type {
+        predicates.push(Predicate::parse_type(entry_type)?);
+    }
+
+    if let Some(perm) = &flags.perm {
+        predicates.push(Predicate::parse_perm(perm)?);
+    }
+
+    if let Some(owner) = &flags.owner {
+        predicates.push(Predicate::parse_owner(owner)?);
+    }
+
+    Ok(predicates)
+}
+
+// Reads a --fromfile listing. Paths from stdin hang off ".", otherwise
+// the listing file itself is the root, like GNU tree.
+fn read_listing(path: &Path) -> std::io::Result<(PathBuf, Vec<u8>)> {
+    if path == Path::new("-") {
+        let mut listing = vec![];
+        std::io::stdin().read_to_end(&mut listing)?;
+
+        Ok((PathBuf::from("."), listing))
+    } else {
+        Ok((path.to_path_buf(), std::fs::read(path)?))
+    }
+}
+
+// Archives are detected by content, so `tree build.tar.gz` works without
+// --archive as long as the path isn't a directory.
+fn is_archive(flags: &Flags, path: &Path) -> bool {
+    flags.archive
+        || (!flags.from_file
+            && path.is_file()
+            && matches!(ArchiveKind::detect(path), Ok(Some(_))))
+}
+
+fn new_tree(
+    flags: &Flags,
+    tree_iterator_flags: &mut TreeIteratorFlags,
+) -> Tree {
+    // the option table already rejected anything that doesn't parse
+    Tree::new(
+        tree_iterator_flags,
+        build_filters(flags).expect("regexes are checked when parsed"),
+        build_predicates(flags).expect("predicates are checked when parsed"),
+    )
+}
+
+// --interactive draws on the terminal and writes nothing but the picked
+// path to stdout, so `cd "$(tree --interactive)"` works. Quitting without
+// picking anything exits with 1.
+fn interactive(cmd: &Cmd) -> i32 {
+    if cmd.flags.from_file || cmd.flags.archive {
+        eprintln!("tree: --interactive can only browse directories");
+        return 2;
+    }
+
+    // the screen is the terminal even when stdout is captured
+    let colors =
+        Colors::new(cmd.flags.color, true).with_theme(&cmd.flags.theme);
+
+    let new_tree = |flags: &mut TreeIteratorFlags| new_tree(&cmd.flags, flags);
+
+    match tui::browse(cmd, &colors, &new_tree) {
+        Ok(Outcome::Select(path)) => {
+            let mut line = path.into_os_string().into_vec();
+            line.push(b'\n');
+
+            match std::io::stdout().write_all(&line) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            }
+        }
+        Ok(Outcome::Quit) => 1,
+        Err(err) => {
+            eprintln!("tree: {err}");
+            1
+        }
+    }
+}
+
+// --watch redraws until interrupted, so it only returns on errors.
+#[cfg(target_os = "linux")]
+fn watch(cmd: &Cmd, colors: &Colors) -> i32 {
+    if cmd.flags.from_file || cmd.flags.archive {
+        eprintln!("tree: --watch can only follow directories");
+        return 2;
+    }
+
+    let new_tree = |flags: &mut TreeIteratorFlags| new_tree(&cmd.flags, flags);
+
+    match watch::watch(cmd, colors, &new_tree) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("tree: {err}");
+            1
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_: &Cmd, _: &Colors) -> i32 {
+    eprintln!("tree: --watch needs inotify, which is only on Linux");
+    2
+}
+
+// --snapshot and --diff record paths relative to one walked directory.
+fn check_single_root(cmd: &Cmd, option: &str) -> Result<(), i32> {
+    if cmd.flags.from_file || cmd.flags.archive {
+        eprintln!("tree: {option} can only walk directories");
+        return Err(2);
+    }
+
+    if cmd.flags.dir_paths.len() != 1 {
+        eprintln!("tree: {option} takes a single directory");
+        return Err(2);
+    }
+
+    Ok(())
+}
+
+fn walk(cmd: &Cmd, root: &Path) -> Snapshot {
+    let mut tree_iterator_flags = cmd.get_tree_iter_flags(root.to_path_buf());
+
+    Snapshot::walk(new_tree(&cmd.flags, &mut tree_iterator_flags))
+}
+
+// Compares the directory against --diff's snapshot file, or against
+// another directory walked the same way. Exits with 1 if anything
+// differs, like diff(1).
+fn diff(cmd: &Cmd, colors: &Colors, against: &Path) -> i32 {
+    if let Err(status) = check_single_root(cmd, "--diff") {
+        return status;
+    }
+
+    let root = &cmd.flags.dir_paths[0];
+
+    if !root.is_dir() {
+        eprintln!("tree: {}: not a directory", root.display());
+        return 2;
+    }
+
+    let old = if against.is_dir() {
+        walk(cmd, against)
+    } else {
+        match Snapshot::load(against) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                eprintln!("tree: bad snapshot {err}");
+                return 2;
+            }
+        }
+    };
+
+    let new = walk(cmd, root);
+    let (mut output, counts) = diff::render(&old, &new, cmd, colors);
+
+    if !cmd.flags.no_report {
+        output.push(b'\n');
+        output.extend(diff::format_counts(&counts, colors).as_bytes());
+        output.push(b'\n');
+    }
+
+    if std::io::stdout().write_all(&output).is_err() {
+        return 2;
+    }
+
+    i32::from(counts.has_changes())
+}
+
+// --compare walks both directories with the same flags and shows them
+// side by side. Exits with 1 if they differ.
+fn compare(cmd: &Cmd, colors: &Colors) -> i32 {
+    if cmd.flags.from_file || cmd.flags.archive {
+        eprintln!("tree: --compare can only walk directories");
+        return 2;
+    }
+
+    let [left, right] = cmd.flags.dir_paths.as_slice() else {
+        eprintln!("tree: --compare takes two directories");
+        return 2;
+    };
+
+    for root in [left, right] {
+        if !root.is_dir() {
+            eprintln!("tree: {}: not a directory", root.display());
+            return 2;
+        }
+    }
+
+    let hash = |path: &Path| compare::hash_file(path);
+    let (mut output, counts) = compare::render(
+        &walk(cmd, left),
+        &walk(cmd, right),
+        cmd,
+        colors,
+        cmd.flags.hash.then_some(&hash),
+    );
+
+    if !cmd.flags.no_report {
+        output.push(b'\n');
+        output.extend(compare::format_counts(&counts, colors).as_bytes());
+        output.push(b'\n');
+    }
+
+    if std::io::stdout().write_all(&output).is_err() {
+        return 2;
+    }
+
+    i32::from(counts.has_differences())
+}
+
+// -o points stdout itself at the file, so every mode writes there and
+// color detection sees a file rather than the terminal.
+fn redirect_stdout(path: &Path) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+
+    // SAFETY: `file` is open until the end of this function, so its fd is
+    // valid for the call, and STDOUT_FILENO is always a valid target;
+    // dup2 only replaces fd 1, which keeps the file open once `file` is
+    // dropped
+    if unsafe { libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
 
 fn main() {
-    let mut cmd = Cmd::from(std::env::args());
+    let cmd = match Cmd::parse(std::env::args().skip(1)) {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            eprintln!("tree: {err}");
+            eprintln!("Try 'tree --help' for more information.");
+            std::process::exit(2);
+        }
+    };
 
     if cmd.flags.help {
-        println!("{HELP}");
-    } else if !cmd.flags.dir_path.as_ref().unwrap().is_dir() {
-        println!("Path is not a directory - {:?}", cmd.flags.dir_path);
-    } else {
-        Colors::from_ls_colors(cmd.flags.colors);
+        write_stdout(format!("{}\n", cli::options::help()).as_bytes());
+        return;
+    }
 
-        let pattern =
-            match (&cmd.flags.pattern_match, &cmd.flags.pattern_exclude) {
-                (Some(match_pattern), None) => {
-                    Some(Pattern::parse(match_pattern.as_str(), true))
-                }
+    if cmd.flags.version {
+        write_stdout(format!("{}\n", cli::version()).as_bytes());
+        return;
+    }
+
+    if let Some(path) = &cmd.flags.output_file {
+        if let Err(err) = redirect_stdout(path) {
+            eprintln!("tree: {}: {err}", path.display());
+            std::process::exit(2);
+        }
+    }
+
+    if cmd.flags.interactive {
+        std::process::exit(interactive(&cmd));
+    }
+
+    let colors = Colors::new(cmd.flags.color, std::io::stdout().is_terminal())
+        .with_theme(&cmd.flags.theme);
+
+    if cmd.flags.watch {
+        std::process::exit(watch(&cmd, &colors));
+    }
+
+    if cmd.flags.compare {
+        std::process::exit(compare(&cmd, &colors));
+    }
 
-                (None, Some(exclude_pattern)) => {
-                    Some(Pattern::parse(exclude_pattern.as_str(), false))
+    if let Some(against) = &cmd.flags.diff {
+        std::process::exit(diff(&cmd, &colors, against));
+    }
+
+    if let Some(snapshot) = &cmd.flags.snapshot {
+        if let Err(status) = check_single_root(&cmd, "--snapshot") {
+            std::process::exit(status);
+        }
+
+        if let Err(err) = walk(&cmd, &cmd.flags.dir_paths[0]).save(snapshot) {
+            eprintln!("tree: failed to save snapshot {err}");
+            std::process::exit(1);
+        }
+    }
+
+    let mut report = Report::default();
+    // roots that couldn't be listed still leave the others to print, but
+    // the exit status says something went wrong
+    let mut failed = false;
+
+    for path in &cmd.flags.dir_paths {
+        let mut root = path.clone();
+        let mut virtual_source = None;
+
+        if cmd.flags.from_file {
+            match read_listing(path) {
+                Ok((listing_root, listing)) => {
+                    virtual_source = Some(VirtualSource::from_listing(
+                        listing_root.clone(),
+                        &listing,
+                    ));
+                    root = listing_root;
+                }
+                Err(err) => {
+                    eprintln!("tree: failed to read file list - {err}");
+                    failed = true;
+                    continue;
                 }
+            }
+        } else if is_archive(&cmd.flags, path) {
+            match archive::read_archive(path, path.clone()) {
+                Ok(source) => virtual_source = Some(source),
+                Err(err) => {
+                    eprintln!("tree: failed to read archive - {err}");
+                    failed = true;
+                    continue;
+                }
+            }
+        } else if !path.exists() {
+            eprintln!("tree: {}: no such file or directory", path.display());
+            failed = true;
+            continue;
+        }
+
+        let mut tree_iterator_flags = cmd.get_tree_iter_flags(root);
+
+        // virtual entries can't be followed anywhere
+        tree_iterator_flags.follow_symlinks &= virtual_source.is_none();
+
+        let tree = new_tree(&cmd.flags, &mut tree_iterator_flags);
 
-                _ => None,
-            };
-
-        Display::print(
-            Tree::new(
-                &mut TreeIteratorFlags {
-                    root: cmd.flags.dir_path.take(),
-                    max_depth: cmd.flags.max_depth.take(),
-                    visit_all: cmd.flags.all,
-                    dirs_only: cmd.flags.dirs,
-                    dirs_first: cmd.flags.dirs_first,
-                    last_mod_sort: cmd.flags.last_modified_sort,
-                    rev_alpha_sort: cmd.flags.reverse_alpha_sort,
-                    follow_symlinks: cmd.flags.follow_symlinks,
-                },
-                pattern,
+        report += match virtual_source {
+            Some(source) => Display::print(
+                tree.with_source(Box::new(source)),
+                &cmd,
+                &colors,
             ),
-            &cmd,
-        );
+            None => Display::print(tree, &cmd, &colors),
+        };
+    }
+
+    Display::print_report(report, &cmd, &colors);
+
+    if failed {
+        std::process::exit(2);
     }
 }